@@ -0,0 +1,28 @@
+use thiserror::Error;
+
+use super::ActionOutput;
+
+#[derive(Error, Debug, Clone)]
+pub enum ActionError {
+    #[error("Error while spawning command '{0}': {1}")]
+    SpawnError(String, String),
+    #[error(
+        "Command '{}' failed with status code {:?}: {}",
+        .0.command,
+        .0.status_code,
+        .0.stderr
+    )]
+    CommandFailed(ActionOutput),
+    #[error("Command '{0}' timed out after {1} seconds")]
+    Timeout(String, u64),
+}
+
+impl ActionError {
+    /// Get the captured output of the failed command, when it ran to completion.
+    pub fn output(&self) -> Option<&ActionOutput> {
+        match self {
+            Self::CommandFailed(output) => Some(output),
+            _ => None,
+        }
+    }
+}
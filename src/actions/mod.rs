@@ -0,0 +1,7 @@
+mod error;
+mod models;
+mod service;
+
+pub use self::error::ActionError;
+pub use self::models::{Action, ActionOutput};
+pub use self::service::{ActionService, ShellExecutor};
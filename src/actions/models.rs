@@ -0,0 +1,55 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Command to execute after a repository synchronization.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct Action {
+    /// Shell command line (e.g. `npm ci && npm run build`)
+    pub command: String,
+    /// Additional environment variables
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Working directory, relative to the repository directory
+    #[serde(default)]
+    pub working_dir: Option<PathBuf>,
+    /// Timeout, in seconds (no timeout as default)
+    #[serde(default)]
+    pub timeout: Option<u64>,
+}
+
+impl Action {
+    pub fn new<T: Into<String>>(command: T) -> Self {
+        Self {
+            command: command.into(),
+            env: HashMap::new(),
+            working_dir: None,
+            timeout: None,
+        }
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout.map(Duration::from_secs)
+    }
+
+    pub fn resolve_working_dir(&self, repository_dir: &Path) -> PathBuf {
+        match &self.working_dir {
+            Some(dir) => repository_dir.join(dir),
+            None => repository_dir.to_owned(),
+        }
+    }
+}
+
+/// Captured result of an executed action.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct ActionOutput {
+    pub command: String,
+    pub status_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    pub duration_ms: u64,
+}
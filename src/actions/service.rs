@@ -0,0 +1,156 @@
+use std::{any::Any, path::Path, time::Instant};
+
+use async_trait::async_trait;
+use tokio::process::Command;
+
+use super::{Action, ActionError, ActionOutput};
+
+#[async_trait]
+pub trait ActionService: std::fmt::Debug + Send + Sync {
+    async fn run(
+        &self,
+        repository_dir: &Path,
+        action: &Action,
+    ) -> Result<ActionOutput, ActionError>;
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+#[derive(Debug)]
+pub struct ShellExecutor {
+    shell: String,
+}
+
+impl ShellExecutor {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Default for ShellExecutor {
+    fn default() -> Self {
+        Self { shell: "sh".into() }
+    }
+}
+
+#[async_trait]
+impl ActionService for ShellExecutor {
+    #[tracing::instrument(skip(self))]
+    async fn run(
+        &self,
+        repository_dir: &Path,
+        action: &Action,
+    ) -> Result<ActionOutput, ActionError> {
+        let start = Instant::now();
+        let child = Command::new(&self.shell)
+            .arg("-c")
+            .arg(&action.command)
+            .envs(&action.env)
+            .current_dir(action.resolve_working_dir(repository_dir))
+            .kill_on_drop(true)
+            .output();
+
+        let output = match action.timeout() {
+            Some(duration) => tokio::time::timeout(duration, child).await.map_err(|_| {
+                tracing::error!(command = %action.command, "command timed out");
+                ActionError::Timeout(action.command.clone(), duration.as_secs())
+            })?,
+            None => child.await,
+        }
+        .map_err(|e| ActionError::SpawnError(action.command.clone(), e.to_string()))?;
+
+        let action_output = ActionOutput {
+            command: action.command.clone(),
+            status_code: output.status.code(),
+            stdout: String::from_utf8_lossy(&output.stdout).trim().to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            duration_ms: start.elapsed().as_millis() as u64,
+        };
+
+        if output.status.success() {
+            tracing::info!(
+                return_code = action_output.status_code,
+                stdout = %action_output.stdout,
+                stderr = %action_output.stderr
+            );
+            Ok(action_output)
+        } else {
+            tracing::error!(
+                return_code = action_output.status_code,
+                stdout = %action_output.stdout,
+                stderr = %action_output.stderr
+            );
+            Err(ActionError::CommandFailed(action_output))
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use pretty_assertions::assert_eq;
+
+    use super::{Action, ActionError, ActionService, ShellExecutor};
+
+    #[tokio::test]
+    async fn test_run_captures_output() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut action = Action::new("echo $HR_TEST_VALUE && echo oops >&2");
+        action.env.insert("HR_TEST_VALUE".into(), "hello".into());
+
+        let output = ShellExecutor::new()
+            .run(tempdir.path(), &action)
+            .await
+            .unwrap();
+        assert_eq!(output.status_code, Some(0));
+        assert_eq!(output.stdout, "hello");
+        assert_eq!(output.stderr, "oops");
+    }
+
+    #[tokio::test]
+    async fn test_run_working_dir_override() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tempdir.path().join("site")).unwrap();
+        let mut action = Action::new("pwd");
+        action.working_dir = Some("site".into());
+
+        let output = ShellExecutor::new()
+            .run(tempdir.path(), &action)
+            .await
+            .unwrap();
+        assert!(output.stdout.ends_with("site"));
+    }
+
+    #[tokio::test]
+    async fn test_run_failure() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let action = Action::new("echo building && echo failed >&2 && exit 3");
+
+        let err = ShellExecutor::new()
+            .run(tempdir.path(), &action)
+            .await
+            .unwrap_err();
+        let output = err.output().unwrap();
+        assert_eq!(output.status_code, Some(3));
+        assert_eq!(output.stdout, "building");
+        assert_eq!(output.stderr, "failed");
+        assert_matches!(err, ActionError::CommandFailed(_));
+    }
+
+    #[tokio::test]
+    async fn test_run_timeout() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut action = Action::new("sleep 5");
+        action.timeout = Some(1);
+
+        let err = ShellExecutor::new()
+            .run(tempdir.path(), &action)
+            .await
+            .unwrap_err();
+        assert_matches!(err, ActionError::Timeout(_, 1));
+    }
+}
@@ -0,0 +1,589 @@
+pub mod middleware;
+
+use axum::{
+    extract::Extension,
+    http::{header::HeaderName, HeaderMap, HeaderValue, StatusCode},
+};
+use serde_json::Value;
+
+use crate::{
+    backends::{
+        check_delivery_date, cleanup_on_delete, clone_backend, header_to_str, parse_body,
+        parse_commit, pretty_print_json, push_event_response, record_delivery, synchronize_on_push,
+        RepositoryField,
+    },
+    config::Config,
+    error::ErrorCode,
+    history::Delivery,
+    jobs::JobRequest,
+    service::ServiceHandler,
+};
+
+use super::{CloudPushEvent, ServerRefsChangedEvent};
+
+/// Payload field of the synchronized repository on Bitbucket Cloud, also used to verify deliveries.
+pub const CLOUD_REPOSITORY_FIELD: RepositoryField = RepositoryField::FullName;
+
+/// Payload field of the synchronized repository on Bitbucket Server, also used to verify deliveries.
+pub const SERVER_REPOSITORY_FIELD: RepositoryField = RepositoryField::ProjectKeyAndSlug;
+
+fn json_header_map() -> HeaderMap {
+    let mut header_map = HeaderMap::new();
+    header_map.insert(
+        HeaderName::from_static("content-type"),
+        HeaderValue::from_static("application/json"),
+    );
+    header_map
+}
+
+fn event_key(headers: &HeaderMap) -> Result<&str, ErrorCode> {
+    headers
+        .get("x-event-key")
+        .ok_or(ErrorCode::MissingEventHeader)?
+        .to_str()
+        .map_err(|_| ErrorCode::MalformedEventHeader)
+}
+
+/// Bitbucket Cloud webhook.
+#[tracing::instrument(skip(config), fields(body_pretty = %pretty_print_json(&body)))]
+pub async fn webhook_cloud(
+    headers: HeaderMap,
+    body: String,
+    config: Extension<Config>,
+    services: Extension<ServiceHandler>,
+) -> Result<(StatusCode, HeaderMap, String), ErrorCode> {
+    let event = event_key(&headers)?;
+    let mut delivery = Delivery::new(
+        "bitbucket",
+        event,
+        header_to_str(&headers, "x-request-uuid"),
+    );
+
+    let result = match event {
+        "repo:push" => match parse_body(&body) {
+            Ok(e) => handle_cloud_push_event(&config, &services, &mut delivery, e, &body).await,
+            Err(e) => Err(e),
+        },
+        other => Err(ErrorCode::UnsupportedEventHeader(other.to_string())),
+    };
+
+    record_delivery(&services, delivery, &result);
+    result
+}
+
+/// Bitbucket Server (and Data Center) webhook.
+#[tracing::instrument(skip(config), fields(body_pretty = %pretty_print_json(&body)))]
+pub async fn webhook_server(
+    headers: HeaderMap,
+    body: String,
+    config: Extension<Config>,
+    services: Extension<ServiceHandler>,
+) -> Result<(StatusCode, HeaderMap, String), ErrorCode> {
+    let event = event_key(&headers)?;
+    let mut delivery = Delivery::new(
+        "bitbucket-server",
+        event,
+        header_to_str(&headers, "x-request-id"),
+    );
+
+    let result = match event {
+        "diagnostics:ping" => parse_body::<Value>(&body)
+            .map(|ping_event| (StatusCode::OK, json_header_map(), ping_event.to_string())),
+        "repo:refs_changed" => match parse_body(&body) {
+            Ok(e) => {
+                handle_server_refs_changed_event(&config, &services, &mut delivery, e, &body).await
+            }
+            Err(e) => Err(e),
+        },
+        other => Err(ErrorCode::UnsupportedEventHeader(other.to_string())),
+    };
+
+    record_delivery(&services, delivery, &result);
+    result
+}
+
+#[tracing::instrument(skip(body))]
+async fn handle_cloud_push_event<'a>(
+    config: &Config,
+    services: &ServiceHandler,
+    delivery: &mut Delivery,
+    push_event: CloudPushEvent<'a>,
+    body: &str,
+) -> Result<(StatusCode, HeaderMap, String), ErrorCode> {
+    let repository_path = CLOUD_REPOSITORY_FIELD.parse_path(body)?;
+    let backend = clone_backend(config, "bitbucket", &repository_path)?;
+
+    for change in &push_event.push.changes {
+        let (field, state) = match (&change.new, &change.old) {
+            (Some(new), _) => ("push.changes.new.type", new),
+            // Deleted references have no new state
+            (None, Some(old)) => ("push.changes.old.type", old),
+            (None, None) => continue,
+        };
+        let reference = state.to_ref_type().ok_or_else(|| {
+            ErrorCode::MalformedEventBodyField(
+                field.into(),
+                format!("Unsupported Git reference type: {}", state.ref_type),
+            )
+        })?;
+
+        if change.new.is_none() {
+            cleanup_on_delete(config, services, delivery, &repository_path, reference).await?;
+            continue;
+        }
+
+        synchronize_on_push(
+            config,
+            services,
+            delivery,
+            JobRequest::synchronization(backend.clone(), repository_path.clone(), reference)
+                .with_forced(change.forced)
+                .with_commit(Some(parse_commit(
+                    "push.changes.new.target.hash",
+                    state.target.hash,
+                )?)),
+        )
+        .await?;
+    }
+
+    let (status, body) = push_event_response(&push_event, delivery);
+    Ok((status, json_header_map(), body))
+}
+
+#[tracing::instrument(skip(body))]
+async fn handle_server_refs_changed_event<'a>(
+    config: &Config,
+    services: &ServiceHandler,
+    delivery: &mut Delivery,
+    refs_changed_event: ServerRefsChangedEvent<'a>,
+    body: &str,
+) -> Result<(StatusCode, HeaderMap, String), ErrorCode> {
+    check_delivery_date(config, refs_changed_event.date)?;
+
+    let repository_path = SERVER_REPOSITORY_FIELD.parse_path(body)?;
+    let backend = clone_backend(config, "bitbucket-server", &repository_path)?;
+
+    for change in &refs_changed_event.changes {
+        let reference = change.reference.to_ref_type().ok_or_else(|| {
+            ErrorCode::MalformedEventBodyField(
+                "changes.ref.type".into(),
+                format!(
+                    "Unsupported Git reference type: {}",
+                    change.reference.ref_type
+                ),
+            )
+        })?;
+
+        if change.change_type == "DELETE" {
+            cleanup_on_delete(config, services, delivery, &repository_path, reference).await?;
+            continue;
+        }
+
+        synchronize_on_push(
+            config,
+            services,
+            delivery,
+            JobRequest::synchronization(backend.clone(), repository_path.clone(), reference)
+                .with_commit(Some(parse_commit("changes.toHash", change.to_hash)?)),
+        )
+        .await?;
+    }
+
+    let (status, body) = push_event_response(&refs_changed_event, delivery);
+    Ok((status, json_header_map(), body))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use hmac::{Hmac, Mac};
+    use pretty_assertions::assert_eq;
+    use reqwest::StatusCode;
+    use serde_json::json;
+    use sha2::Sha256;
+    use tower::ServiceExt;
+
+    use crate::{
+        error::ErrorCode,
+        git::GitBackend,
+        http::build_http_router,
+        jobs::JobKind,
+        testing::{
+            assert_response_is_error, create_test_config, create_test_services,
+            extract_fake_git_service, wait_for_response_job,
+        },
+    };
+
+    fn sign(body: &str, secret: &str) -> String {
+        let mut hmac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        hmac.update(body.as_bytes());
+        format!("sha256={}", hex::encode(hmac.finalize().into_bytes()))
+    }
+
+    fn cloud_push_event_json() -> serde_json::Value {
+        json!({
+            "push": {
+                "changes": [
+                    {
+                        "new": {
+                            "type": "branch",
+                            "name": "sample",
+                            "target": {"hash": "b8b4a7a2a17d0bd2b0a1e4a84e7c0cd4d3b1c9b0"}
+                        },
+                        "old": null,
+                        "forced": false
+                    },
+                    {
+                        "new": null,
+                        "old": {
+                            "type": "branch",
+                            "name": "deleted",
+                            "target": {"hash": "1e65c05c1d5171631d92438a13901ca7dae9618c"}
+                        },
+                        "forced": false
+                    }
+                ]
+            },
+            "repository": {
+                "full_name": "Srynetix/things",
+                "name": "things"
+            }
+        })
+    }
+
+    fn server_refs_changed_event_json() -> serde_json::Value {
+        json!({
+            "eventKey": "repo:refs_changed",
+            "date": "2017-09-19T09:45:32+1000",
+            "repository": {
+                "slug": "things",
+                "name": "things",
+                "project": {"key": "PROJ"},
+                "links": {
+                    "clone": [
+                        {"href": "https://bitbucket.example.com/scm/proj/things.git", "name": "http"}
+                    ]
+                }
+            },
+            "changes": [
+                {
+                    "ref": {"id": "refs/tags/v1.0.0", "displayId": "v1.0.0", "type": "TAG"},
+                    "refId": "refs/tags/v1.0.0",
+                    "fromHash": "0000000000000000000000000000000000000000",
+                    "toHash": "178864a7d521b6f5e720b386b2c2b0ef8563e0dc",
+                    "type": "ADD"
+                }
+            ]
+        })
+    }
+
+    #[tokio::test]
+    async fn test_cloud_push_event_clone() {
+        let services = create_test_services();
+        let app = build_http_router(create_test_config(), services.clone());
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("X-Event-Key", "repo:push")
+                    .uri("/webhook/bitbucket")
+                    .body(cloud_push_event_json().to_string().into())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        wait_for_response_job(&services, response).await;
+
+        let fake_git_service = extract_fake_git_service(&services);
+        let calls = fake_git_service.clone_repository.calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].1.as_deref(), Some("sample"));
+        assert_eq!(calls[0].2, "https://bitbucket.org/Srynetix/things");
+    }
+
+    #[tokio::test]
+    async fn test_cloud_push_event_deleted_branch_cleanup() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut config = create_test_config();
+        config
+            .repository_mut("Srynetix/things")
+            .unwrap()
+            .set_target_dir(tempdir.path().join("previews/{branch}"));
+        let services = create_test_services();
+        let app = build_http_router(config, services.clone());
+
+        let mut json_data = cloud_push_event_json();
+        json_data["push"]["changes"]
+            .as_array_mut()
+            .unwrap()
+            .remove(0);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("X-Event-Key", "repo:push")
+                    .uri("/webhook/bitbucket")
+                    .body(json_data.to_string().into())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let job = wait_for_response_job(&services, response).await;
+        assert_eq!(job.kind, JobKind::Cleanup);
+        assert_eq!(job.reference, "deleted");
+        assert!(!extract_fake_git_service(&services)
+            .clone_repository
+            .called());
+    }
+
+    #[tokio::test]
+    async fn test_cloud_push_event_malformed_commit() {
+        let services = create_test_services();
+        let app = build_http_router(create_test_config(), services.clone());
+
+        let mut json_data = cloud_push_event_json();
+        json_data["push"]["changes"][0]["new"]["target"]["hash"] = "HEAD~1".into();
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("X-Event-Key", "repo:push")
+                    .uri("/webhook/bitbucket")
+                    .body(json_data.to_string().into())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let status = response.status();
+        assert_response_is_error(
+            response,
+            ErrorCode::MalformedEventBodyField(
+                "push.changes.new.target.hash".into(),
+                "Malformed commit hash: HEAD~1".into(),
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let fake_git_service = extract_fake_git_service(&services);
+        assert!(!fake_git_service.clone_repository.called());
+    }
+
+    #[tokio::test]
+    async fn test_server_refs_changed_event_clone() {
+        let mut config = create_test_config();
+        config.set_webhook_secret("secret");
+        config
+            .repository_mut("PROJ/things")
+            .unwrap()
+            .set_backend(GitBackend::Custom(
+                "https://bitbucket.example.com/scm".into(),
+            ));
+        let services = create_test_services();
+        let app = build_http_router(config, services.clone());
+
+        let body = server_refs_changed_event_json().to_string();
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("X-Event-Key", "repo:refs_changed")
+                    .header("X-Hub-Signature", sign(&body, "secret"))
+                    .uri("/webhook/bitbucket-server")
+                    .body(body.into())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        wait_for_response_job(&services, response).await;
+
+        let fake_git_service = extract_fake_git_service(&services);
+        let calls = fake_git_service.clone_repository.calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].1.as_deref(), Some("v1.0.0"));
+        assert_eq!(calls[0].2, "https://bitbucket.example.com/scm/PROJ/things");
+    }
+
+    #[tokio::test]
+    async fn test_server_refs_changed_event_deleted_tag_cleanup() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut config = create_test_config();
+        let repository = config.repository_mut("PROJ/things").unwrap();
+        repository.set_target_dir(tempdir.path().join("previews/{branch}"));
+        repository.set_backend(GitBackend::Custom(
+            "https://bitbucket.example.com/scm".into(),
+        ));
+        let services = create_test_services();
+        let app = build_http_router(config, services.clone());
+
+        let mut json_data = server_refs_changed_event_json();
+        json_data["changes"][0]["fromHash"] = "178864a7d521b6f5e720b386b2c2b0ef8563e0dc".into();
+        json_data["changes"][0]["toHash"] = "0000000000000000000000000000000000000000".into();
+        json_data["changes"][0]["type"] = "DELETE".into();
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("X-Event-Key", "repo:refs_changed")
+                    .uri("/webhook/bitbucket-server")
+                    .body(json_data.to_string().into())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let job = wait_for_response_job(&services, response).await;
+        assert_eq!(job.kind, JobKind::Cleanup);
+        assert_eq!(job.reference, "v1.0.0");
+        assert!(!extract_fake_git_service(&services)
+            .clone_repository
+            .called());
+    }
+
+    #[tokio::test]
+    async fn test_server_signature_uses_synchronized_repository() {
+        let mut config = create_test_config();
+        for (name, secret) in [
+            ("PROJ/things", "things-secret"),
+            ("PROJ/other", "other-secret"),
+        ] {
+            let repository = config.repository_mut(name).unwrap();
+            repository.set_webhook_secret(secret);
+            repository.set_backend(GitBackend::Custom(
+                "https://bitbucket.example.com/scm".into(),
+            ));
+        }
+        let services = create_test_services();
+        let app = build_http_router(config, services.clone());
+
+        // The payload names another repository than the one being synchronized
+        let mut json_data = server_refs_changed_event_json();
+        json_data["repository"]["full_name"] = "PROJ/things".into();
+        json_data["repository"]["slug"] = "other".into();
+        let body = json_data.to_string();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("X-Event-Key", "repo:refs_changed")
+                    .header("X-Hub-Signature", sign(&body, "things-secret"))
+                    .uri("/webhook/bitbucket-server")
+                    .body(body.into())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let status = response.status();
+        assert_response_is_error(response, ErrorCode::InvalidSignature).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let fake_git_service = extract_fake_git_service(&services);
+        assert!(!fake_git_service.clone_repository.called());
+    }
+
+    #[tokio::test]
+    async fn test_server_invalid_signature() {
+        let mut config = create_test_config();
+        config.set_webhook_secret("secret");
+        let app = build_http_router(config, create_test_services());
+
+        let body = server_refs_changed_event_json().to_string();
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("X-Event-Key", "repo:refs_changed")
+                    .header("X-Hub-Signature", sign(&body, "other"))
+                    .uri("/webhook/bitbucket-server")
+                    .body(body.into())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let status = response.status();
+        assert_response_is_error(response, ErrorCode::InvalidSignature).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_server_ping_event() {
+        let app = build_http_router(create_test_config(), create_test_services());
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("X-Event-Key", "diagnostics:ping")
+                    .uri("/webhook/bitbucket-server")
+                    .body(json!({"test": true}).to_string().into())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_missing_event_key() {
+        let app = build_http_router(create_test_config(), create_test_services());
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/webhook/bitbucket")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let status = response.status();
+        assert_response_is_error(response, ErrorCode::MissingEventHeader).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_server_expired_delivery() {
+        let mut config = create_test_config();
+        config.set_max_delivery_age(300);
+        let services = create_test_services();
+        let app = build_http_router(config, services.clone());
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("X-Event-Key", "repo:refs_changed")
+                    .uri("/webhook/bitbucket-server")
+                    .body(server_refs_changed_event_json().to_string().into())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_response_is_error(
+            response,
+            ErrorCode::ExpiredDelivery("2017-09-19T09:45:32+1000".into()),
+        )
+        .await;
+        assert!(extract_fake_git_service(&services)
+            .clone_repository
+            .calls()
+            .is_empty());
+    }
+}
@@ -0,0 +1,125 @@
+use serde::{Deserialize, Serialize};
+
+use crate::git::RefType;
+
+/// Bitbucket Cloud `repo:push` event.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CloudPushEvent<'a> {
+    #[serde(borrow)]
+    pub push: CloudPush<'a>,
+    pub repository: CloudRepository<'a>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CloudPush<'a> {
+    #[serde(borrow)]
+    pub changes: Vec<CloudChange<'a>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CloudChange<'a> {
+    #[serde(borrow)]
+    pub new: Option<CloudReference<'a>>,
+    #[serde(borrow)]
+    pub old: Option<CloudReference<'a>>,
+    #[serde(default)]
+    pub forced: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CloudReference<'a> {
+    #[serde(rename = "type")]
+    pub ref_type: &'a str,
+    pub name: &'a str,
+    pub target: CloudTarget<'a>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CloudTarget<'a> {
+    pub hash: &'a str,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CloudRepository<'a> {
+    pub full_name: &'a str,
+    pub name: &'a str,
+}
+
+impl<'a> CloudReference<'a> {
+    pub fn to_ref_type(&self) -> Option<RefType> {
+        match self.ref_type {
+            "branch" => Some(RefType::Branch(self.name.into())),
+            "tag" => Some(RefType::Tag(self.name.into())),
+            _ => None,
+        }
+    }
+}
+
+/// Bitbucket Server `repo:refs_changed` event.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerRefsChangedEvent<'a> {
+    pub event_key: &'a str,
+    pub date: &'a str,
+    #[serde(borrow)]
+    pub repository: ServerRepository<'a>,
+    #[serde(borrow)]
+    pub changes: Vec<ServerChange<'a>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerChange<'a> {
+    #[serde(rename = "ref")]
+    pub reference: ServerReference<'a>,
+    pub ref_id: &'a str,
+    pub from_hash: &'a str,
+    pub to_hash: &'a str,
+    #[serde(rename = "type")]
+    pub change_type: &'a str,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerReference<'a> {
+    pub id: &'a str,
+    pub display_id: &'a str,
+    #[serde(rename = "type")]
+    pub ref_type: &'a str,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ServerRepository<'a> {
+    pub slug: &'a str,
+    pub name: &'a str,
+    pub project: ServerProject<'a>,
+    #[serde(borrow)]
+    pub links: ServerLinks<'a>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ServerProject<'a> {
+    pub key: &'a str,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ServerLinks<'a> {
+    #[serde(borrow, default)]
+    pub clone: Vec<ServerLink<'a>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ServerLink<'a> {
+    pub href: &'a str,
+    pub name: &'a str,
+}
+
+impl<'a> ServerReference<'a> {
+    pub fn to_ref_type(&self) -> Option<RefType> {
+        match self.ref_type {
+            "BRANCH" => Some(RefType::Branch(self.display_id.into())),
+            "TAG" => Some(RefType::Tag(self.display_id.into())),
+            _ => None,
+        }
+    }
+}
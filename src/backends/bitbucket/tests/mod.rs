@@ -0,0 +1,36 @@
+use assert_matches::assert_matches;
+
+use crate::git::RefType;
+
+use super::{
+    models::{CloudPushEvent, ServerRefsChangedEvent},
+    SERVER_REPOSITORY_FIELD,
+};
+
+#[test]
+fn test_parse_cloud_push_event() {
+    let event_str = include_str!("./cloud_push_sample.json");
+    let event: CloudPushEvent = serde_json::from_str(event_str).expect("should deserialize");
+    assert_eq!(event.push.changes.len(), 1);
+    assert_matches!(
+        event.push.changes[0].new.as_ref().unwrap().to_ref_type(),
+        Some(RefType::Branch(b)) if b == "master"
+    );
+}
+
+#[test]
+fn test_parse_server_refs_changed_event() {
+    let event_str = include_str!("./server_refs_changed_sample.json");
+    let event: ServerRefsChangedEvent =
+        serde_json::from_str(event_str).expect("should deserialize");
+    assert_eq!(
+        SERVER_REPOSITORY_FIELD
+            .parse(event_str.as_bytes())
+            .as_deref(),
+        Some("PROJ/repository")
+    );
+    assert_matches!(
+        event.changes[0].reference.to_ref_type(),
+        Some(RefType::Branch(b)) if b == "master"
+    );
+}
@@ -0,0 +1,337 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{
+    config::Config,
+    server_info::{APP_NAME, APP_VERSION},
+};
+
+use super::error::GiteaError;
+
+pub struct Client {
+    base_url: Url,
+    token: String,
+}
+
+#[derive(Deserialize)]
+pub struct WebhookConfig {
+    url: String,
+}
+
+#[derive(Deserialize)]
+pub struct Webhook {
+    id: u32,
+    config: WebhookConfig,
+}
+
+impl Client {
+    pub fn new<T: Into<String>>(base_url: Url, token: T) -> Self {
+        Self {
+            base_url,
+            token: token.into(),
+        }
+    }
+
+    pub async fn try_register_webhook(
+        &self,
+        config: &Config,
+        owner: &str,
+        repo: &str,
+        url: &Url,
+    ) -> Result<u32, GiteaError> {
+        if let Some(u) = self.check_webhook_url(owner, repo, url).await? {
+            tracing::warn!(
+                id = u,
+                message = "Webhook already registered",
+                owner = owner,
+                repo = repo,
+                url = %url
+            );
+            Ok(u)
+        } else {
+            Ok(self.register_webhook(config, owner, repo, url).await?.id)
+        }
+    }
+
+    /// Takes the configuration like other backends, even if Gitea does not need it to unregister.
+    pub async fn try_unregister_webhook(
+        &self,
+        _config: &Config,
+        owner: &str,
+        repo: &str,
+        url: &Url,
+    ) -> Result<(), GiteaError> {
+        if let Some(u) = self.check_webhook_url(owner, repo, url).await? {
+            self.unregister_webhook(owner, repo, u).await
+        } else {
+            tracing::error!(
+                message = "Unknown webhook",
+                owner = owner,
+                repo = repo,
+                url = %url
+            );
+            Ok(())
+        }
+    }
+
+    fn create_client(&self) -> reqwest::Client {
+        reqwest::ClientBuilder::new()
+            .connect_timeout(Duration::from_secs(10))
+            .user_agent(format!("{APP_NAME}/{APP_VERSION}"))
+            .build()
+            .unwrap()
+    }
+
+    fn hooks_url(&self, owner: &str, repo: &str, suffix: &str) -> Url {
+        let mut base_url = self.base_url.clone();
+        if !base_url.path().ends_with('/') {
+            base_url.set_path(&format!("{}/", base_url.path()));
+        }
+
+        base_url
+            .join(&format!("api/v1/repos/{owner}/{repo}/hooks{suffix}"))
+            .unwrap()
+    }
+
+    fn authorization(&self) -> String {
+        format!("token {}", self.token)
+    }
+
+    async fn register_webhook(
+        &self,
+        config: &Config,
+        owner: &str,
+        repo: &str,
+        url: &Url,
+    ) -> Result<Webhook, GiteaError> {
+        #[derive(Serialize)]
+        struct WebhookConfig<'a> {
+            url: String,
+            content_type: &'static str,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            secret: Option<&'a str>,
+        }
+
+        #[derive(Serialize)]
+        struct Data<'a> {
+            #[serde(rename = "type")]
+            hook_type: &'static str,
+            config: WebhookConfig<'a>,
+            events: &'static [&'static str],
+            active: bool,
+        }
+
+        let data = Data {
+            hook_type: "gitea",
+            config: WebhookConfig {
+                url: url.to_string(),
+                content_type: "json",
+                secret: config.webhook_secret(),
+            },
+            events: &["push"],
+            active: true,
+        };
+
+        let resp = self
+            .create_client()
+            .post(self.hooks_url(owner, repo, ""))
+            .header("Authorization", self.authorization())
+            .json(&data)
+            .send()
+            .await
+            .map_err(GiteaError::CouldNotRegisterWebhook)?;
+
+        let webhook: Webhook = resp
+            .error_for_status()
+            .map_err(GiteaError::BadStatusCode)?
+            .json()
+            .await
+            .map_err(GiteaError::MalformedResponse)?;
+
+        tracing::info!(
+            id = webhook.id,
+            message = "New webhook installed",
+            owner = owner,
+            repo = repo,
+            url = %url
+        );
+
+        Ok(webhook)
+    }
+
+    async fn list_webhooks(&self, owner: &str, repo: &str) -> Result<Vec<Webhook>, GiteaError> {
+        let resp = self
+            .create_client()
+            .get(self.hooks_url(owner, repo, ""))
+            .header("Authorization", self.authorization())
+            .send()
+            .await
+            .map_err(GiteaError::CouldNotListWebhooks)?;
+
+        let data: Vec<Webhook> = resp
+            .error_for_status()
+            .map_err(GiteaError::BadStatusCode)?
+            .json()
+            .await
+            .map_err(GiteaError::MalformedResponse)?;
+        Ok(data)
+    }
+
+    async fn check_webhook_url(
+        &self,
+        owner: &str,
+        repo: &str,
+        url: &Url,
+    ) -> Result<Option<u32>, GiteaError> {
+        Ok(self
+            .list_webhooks(owner, repo)
+            .await?
+            .into_iter()
+            .find(|w| Url::parse(&w.config.url).ok().as_ref() == Some(url))
+            .map(|w| w.id))
+    }
+
+    async fn unregister_webhook(&self, owner: &str, repo: &str, id: u32) -> Result<(), GiteaError> {
+        let resp = self
+            .create_client()
+            .delete(self.hooks_url(owner, repo, &format!("/{id}")))
+            .header("Authorization", self.authorization())
+            .send()
+            .await
+            .map_err(GiteaError::CouldNotUnregisterWebhook)?;
+
+        resp.error_for_status().map_err(GiteaError::BadStatusCode)?;
+
+        tracing::info!(
+            id = id,
+            message = "Webhook unregistered",
+            owner = owner,
+            repo = repo,
+        );
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+
+    use super::Client;
+    use url::Url;
+
+    async fn test_client() -> (MockServer, Client) {
+        let server = MockServer::start().await;
+        let client = Client::new(Url::parse(&server.uri()).unwrap(), "token");
+
+        (server, client)
+    }
+
+    fn create_url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_register_webhook() {
+        let (server, client) = test_client().await;
+        let mut config = Config::empty();
+        config.set_webhook_secret("secret");
+
+        Mock::given(matchers::method("POST"))
+            .and(matchers::path("/api/v1/repos/owner/repo/hooks"))
+            .and(matchers::header("Authorization", "token token"))
+            .and(matchers::body_json(json!({
+                "type": "gitea",
+                "config": {
+                    "url": "http://url/",
+                    "content_type": "json",
+                    "secret": "secret"
+                },
+                "events": ["push"],
+                "active": true
+            })))
+            .respond_with(ResponseTemplate::new(201).set_body_json(json!({
+                "id": 1234u32,
+                "config": {
+                    "url": "http://url"
+                }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        assert_eq!(
+            client
+                .register_webhook(&config, "owner", "repo", &create_url("http://url"))
+                .await
+                .unwrap()
+                .id,
+            1234
+        );
+    }
+
+    #[tokio::test]
+    async fn test_try_register_webhook_present() {
+        let (server, client) = test_client().await;
+        let config = Config::empty();
+
+        Mock::given(matchers::method("GET"))
+            .and(matchers::path("/api/v1/repos/owner/repo/hooks"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+                {
+                    "id": 1234u32,
+                    "config": {
+                        "url": "http://url"
+                    }
+                }
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        assert_eq!(
+            client
+                .try_register_webhook(&config, "owner", "repo", &create_url("http://url"))
+                .await
+                .unwrap(),
+            1234
+        );
+    }
+
+    #[tokio::test]
+    async fn test_try_unregister_webhook_present() {
+        let (server, client) = test_client().await;
+        let config = Config::empty();
+
+        Mock::given(matchers::method("DELETE"))
+            .and(matchers::path("/api/v1/repos/owner/repo/hooks/1234"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        Mock::given(matchers::method("GET"))
+            .and(matchers::path("/api/v1/repos/owner/repo/hooks"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+                {
+                    "id": 1234,
+                    "config": {
+                        "url": "http://url"
+                    }
+                }
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        client
+            .try_unregister_webhook(&config, "owner", "repo", &create_url("http://url"))
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,19 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum GiteaError {
+    #[error("error while registering webhook.")]
+    CouldNotRegisterWebhook(#[source] reqwest::Error),
+
+    #[error("error while listing webhooks.")]
+    CouldNotListWebhooks(#[source] reqwest::Error),
+
+    #[error("error while unregistering webhook.")]
+    CouldNotUnregisterWebhook(#[source] reqwest::Error),
+
+    #[error("error code received from Gitea.")]
+    BadStatusCode(#[source] reqwest::Error),
+
+    #[error("error while parsing Gitea response.")]
+    MalformedResponse(#[source] reqwest::Error),
+}
@@ -0,0 +1,106 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use axum::{
+    body::Body,
+    http::{Method, Request},
+    response::{IntoResponse, Response},
+};
+
+use tower::{Layer, Service};
+
+use crate::{
+    crypto::{SignatureAlgorithm, WebhookVerifier},
+    http::buffer_request_body,
+};
+
+const GITEA_SIGNATURE_HEADER: &str = "X-Gitea-Signature";
+const FORGEJO_SIGNATURE_HEADER: &str = "X-Forgejo-Signature";
+
+pub struct VerifyGiteaSignatureLayer {
+    verifier: WebhookVerifier,
+}
+
+impl VerifyGiteaSignatureLayer {
+    pub fn new(verifier: WebhookVerifier) -> Self {
+        Self { verifier }
+    }
+}
+
+impl<S> Layer<S> for VerifyGiteaSignatureLayer {
+    type Service = VerifyGiteaSignatureMiddleware<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        VerifyGiteaSignatureMiddleware::new(self.verifier.clone(), inner)
+    }
+}
+
+#[derive(Clone)]
+pub struct VerifyGiteaSignatureMiddleware<S> {
+    verifier: WebhookVerifier,
+    inner: S,
+}
+
+impl<S> VerifyGiteaSignatureMiddleware<S> {
+    pub fn new(verifier: WebhookVerifier, inner: S) -> Self {
+        Self { verifier, inner }
+    }
+}
+
+type BoxFuture<'a, Output> = Pin<Box<dyn Future<Output = Output> + Send + 'a>>;
+
+impl<S> Service<Request<Body>> for VerifyGiteaSignatureMiddleware<S>
+where
+    S: Service<Request<Body>, Response = Response> + Send + 'static + Clone,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<Body>) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let verifier = self.verifier.clone();
+        let fut = async move {
+            if request.method() == Method::POST {
+                let body = match buffer_request_body(&mut request, verifier.max_body_size()).await {
+                    Ok(b) => b,
+                    Err(e) => return Ok(e.into_response()),
+                };
+
+                // Check for signature, sent without prefix
+                if verifier.is_enabled() {
+                    let headers = request.headers();
+                    let signature = headers
+                        .get(GITEA_SIGNATURE_HEADER)
+                        .or_else(|| headers.get(FORGEJO_SIGNATURE_HEADER))
+                        .map(|v| v.to_str().unwrap_or_default());
+
+                    if let Err(e) = verifier.verify_signature(
+                        verifier.repository_name(&body).as_deref(),
+                        signature,
+                        &body,
+                        SignatureAlgorithm::Sha256,
+                    ) {
+                        return Ok(e.into_response());
+                    }
+                }
+
+                *request.body_mut() = body.into();
+            }
+
+            let future = inner.call(request);
+            let response: Response = future.await?;
+            Ok(response)
+        };
+
+        Box::pin(fut)
+    }
+}
@@ -0,0 +1,294 @@
+pub mod middleware;
+
+use axum::{
+    extract::Extension,
+    http::{header::HeaderName, HeaderMap, HeaderValue, StatusCode},
+};
+
+use crate::{
+    backends::{
+        cleanup_on_delete, clone_backend, header_to_str, is_null_commit, parse_body, parse_commit,
+        pretty_print_json, push_event_response, record_delivery, synchronize_on_push,
+        RepositoryField,
+    },
+    config::Config,
+    error::ErrorCode,
+    git::RefType,
+    history::Delivery,
+    jobs::JobRequest,
+    service::ServiceHandler,
+};
+
+use super::PushEvent;
+
+/// Payload field of the synchronized repository, also used to verify deliveries.
+pub const REPOSITORY_FIELD: RepositoryField = RepositoryField::FullName;
+
+#[tracing::instrument(skip(config), fields(body_pretty = %pretty_print_json(&body)))]
+pub async fn webhook(
+    headers: HeaderMap,
+    body: String,
+    config: Extension<Config>,
+    services: Extension<ServiceHandler>,
+) -> Result<(StatusCode, HeaderMap, String), ErrorCode> {
+    let event = headers
+        .get("x-gitea-event")
+        .ok_or(ErrorCode::MissingEventHeader)?
+        .to_str()
+        .map_err(|_| ErrorCode::MalformedEventHeader)?;
+
+    let mut delivery = Delivery::new("gitea", event, header_to_str(&headers, "x-gitea-delivery"));
+    let result = handle_event(&config, &services, &mut delivery, event, &body).await;
+    record_delivery(&services, delivery, &result);
+    result
+}
+
+async fn handle_event(
+    config: &Config,
+    services: &ServiceHandler,
+    delivery: &mut Delivery,
+    event: &str,
+    body: &str,
+) -> Result<(StatusCode, HeaderMap, String), ErrorCode> {
+    match event {
+        "push" => handle_push_event(config, services, delivery, parse_body(body)?, body).await,
+        other => Err(ErrorCode::UnsupportedEventHeader(other.to_string())),
+    }
+}
+
+#[tracing::instrument(skip(body))]
+async fn handle_push_event<'a>(
+    config: &Config,
+    services: &ServiceHandler,
+    delivery: &mut Delivery,
+    push_event: PushEvent<'a>,
+    body: &str,
+) -> Result<(StatusCode, HeaderMap, String), ErrorCode> {
+    let mut header_map = HeaderMap::new();
+    header_map.insert(
+        HeaderName::from_static("content-type"),
+        HeaderValue::from_static("application/json"),
+    );
+    let reference = RefType::try_from(push_event.reference)
+        .map_err(|e| ErrorCode::MalformedEventBodyField("ref".into(), e.to_string()))?;
+    let repository_path = REPOSITORY_FIELD.parse_path(body)?;
+
+    if is_null_commit(push_event.after) {
+        cleanup_on_delete(config, services, delivery, &repository_path, reference).await?;
+    } else {
+        synchronize_on_push(
+            config,
+            services,
+            delivery,
+            JobRequest::synchronization(
+                clone_backend(config, "gitea", &repository_path)?,
+                repository_path.clone(),
+                reference,
+            )
+            .with_commit(Some(parse_commit("after", push_event.after)?)),
+        )
+        .await?;
+    }
+
+    let (status, body) = push_event_response(&push_event, delivery);
+    Ok((status, header_map, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use hmac::{Hmac, Mac};
+    use pretty_assertions::assert_eq;
+    use reqwest::StatusCode;
+    use serde_json::json;
+    use sha2::Sha256;
+    use tower::ServiceExt;
+
+    use crate::{
+        error::ErrorCode,
+        git::GitBackend,
+        http::build_http_router,
+        jobs::{JobKind, JobStatus},
+        testing::{
+            assert_response_is_error, create_test_config, create_test_services,
+            extract_fake_git_service, wait_for_response_job,
+        },
+    };
+
+    fn sign(body: &str, secret: &str) -> String {
+        let mut hmac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        hmac.update(body.as_bytes());
+        hex::encode(hmac.finalize().into_bytes())
+    }
+
+    fn push_event_json() -> serde_json::Value {
+        json!({
+            "ref": "refs/branches/sample",
+            "before": "28e1879d029cb852e4844d9c718537df08844e03",
+            "after": "bffeb74224043ba2feb48d137756c8a9331c449a",
+            "repository": {
+                "id": 140,
+                "name": "things",
+                "full_name": "Srynetix/things",
+                "html_url": "https://gitea.example.com/Srynetix/things"
+            },
+            "pusher": {
+                "login": "sample",
+                "email": "sample"
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_verify_gitea_signature_middleware_missing_signature() {
+        let mut config = create_test_config();
+        config.set_webhook_secret("secret");
+        let app = build_http_router(config, create_test_services());
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("X-Gitea-Event", "push")
+                    .uri("/webhook/gitea")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let status = response.status();
+        assert_response_is_error(response, ErrorCode::InvalidSignature).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_verify_gitea_signature_middleware_invalid_signature() {
+        let mut config = create_test_config();
+        config.set_webhook_secret("secret");
+        let app = build_http_router(config, create_test_services());
+
+        let body = push_event_json().to_string();
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("X-Gitea-Event", "push")
+                    .header("X-Gitea-Signature", sign(&body, "other"))
+                    .uri("/webhook/gitea")
+                    .body(body.into())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let status = response.status();
+        assert_response_is_error(response, ErrorCode::InvalidSignature).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_push_event_clone() {
+        let mut config = create_test_config();
+        config.set_webhook_secret("secret");
+        config
+            .repository_mut("Srynetix/things")
+            .unwrap()
+            .set_backend(GitBackend::Gitea("https://gitea.example.com".into()));
+        let services = create_test_services();
+        let app = build_http_router(config, services.clone());
+
+        let json_data = push_event_json();
+        let body = json_data.to_string();
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("X-Gitea-Event", "push")
+                    .header("X-Gitea-Signature", sign(&body, "secret"))
+                    .uri("/webhook/gitea")
+                    .body(body.into())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        wait_for_response_job(&services, response).await;
+
+        let fake_git_service = extract_fake_git_service(&services);
+        let calls = fake_git_service.clone_repository.calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].2, "https://gitea.example.com/Srynetix/things");
+    }
+
+    #[tokio::test]
+    async fn test_push_event_unconfigured_backend() {
+        let mut config = create_test_config();
+        config.set_webhook_secret("secret");
+        let services = create_test_services();
+        let app = build_http_router(config, services.clone());
+
+        let body = push_event_json().to_string();
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("X-Gitea-Event", "push")
+                    .header("X-Gitea-Signature", sign(&body, "secret"))
+                    .uri("/webhook/gitea")
+                    .body(body.into())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_response_is_error(
+            response,
+            ErrorCode::UnconfiguredBackend("Srynetix/things".into()),
+        )
+        .await;
+        assert!(extract_fake_git_service(&services)
+            .clone_repository
+            .calls()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_push_event_deleted_branch_cleanup() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(tempdir.path().join("previews/sample")).unwrap();
+
+        let mut config = create_test_config();
+        config
+            .repository_mut("Srynetix/things")
+            .unwrap()
+            .set_target_dir(tempdir.path().join("previews/{branch}"));
+        let services = create_test_services();
+        let app = build_http_router(config, services.clone());
+
+        let mut json_data = push_event_json();
+        json_data["after"] = "0000000000000000000000000000000000000000".into();
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("X-Gitea-Event", "push")
+                    .uri("/webhook/gitea")
+                    .body(json_data.to_string().into())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let job = wait_for_response_job(&services, response).await;
+        assert_eq!(job.kind, JobKind::Cleanup);
+        assert_eq!(job.status, JobStatus::Succeeded);
+        assert!(!tempdir.path().join("previews/sample").exists());
+        assert!(!extract_fake_git_service(&services)
+            .clone_repository
+            .called());
+    }
+}
@@ -0,0 +1,11 @@
+mod apiclient;
+mod error;
+mod http;
+mod models;
+
+#[cfg(test)]
+mod tests;
+
+pub use apiclient::*;
+pub use http::*;
+pub use models::*;
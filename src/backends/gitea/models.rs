@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PushEvent<'a> {
+    #[serde(rename = "ref")]
+    pub reference: &'a str,
+    pub before: &'a str,
+    pub after: &'a str,
+    pub repository: Repository<'a>,
+    pub pusher: User<'a>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Repository<'a> {
+    pub id: u64,
+    pub name: &'a str,
+    pub full_name: &'a str,
+    pub html_url: &'a str,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct User<'a> {
+    pub login: &'a str,
+    pub email: &'a str,
+}
@@ -0,0 +1,9 @@
+use super::models::PushEvent;
+
+#[test]
+fn test_parse_push_event() {
+    let event_str = include_str!("./push_sample.json");
+    let event: PushEvent = serde_json::from_str(event_str).expect("should deserialize");
+    assert_eq!(event.repository.full_name, "gitea/webhooks");
+    assert_eq!(event.reference, "refs/heads/develop");
+}
@@ -1,5 +1,6 @@
-use std::time::Duration;
+use std::{str::FromStr, time::Duration};
 
+use ipnet::IpNet;
 use serde::{Deserialize, Serialize};
 use url::Url;
 
@@ -26,6 +27,41 @@ pub struct Webhook {
     config: WebhookConfig,
 }
 
+#[derive(Deserialize)]
+struct Meta {
+    hooks: Vec<String>,
+}
+
+fn create_client() -> reqwest::Client {
+    reqwest::ClientBuilder::new()
+        .connect_timeout(Duration::from_secs(10))
+        .user_agent(format!("{APP_NAME}/{APP_VERSION}"))
+        .build()
+        .unwrap()
+}
+
+/// Fetch the IP ranges GitHub sends webhook deliveries from, using the unauthenticated `/meta` API.
+pub async fn fetch_hook_ip_ranges(config: &Config) -> Result<Vec<IpNet>, GitHubError> {
+    let url_path = config.github_api_url().join("/meta").unwrap();
+    let resp = create_client()
+        .get(url_path)
+        .send()
+        .await
+        .map_err(GitHubError::CouldNotFetchMeta)?;
+
+    let meta: Meta = resp
+        .error_for_status()
+        .map_err(GitHubError::BadStatusCode)?
+        .json()
+        .await
+        .map_err(GitHubError::MalformedResponse)?;
+
+    meta.hooks
+        .into_iter()
+        .map(|r| IpNet::from_str(&r).map_err(|_| GitHubError::MalformedIpRange(r)))
+        .collect()
+}
+
 impl Client {
     pub fn new<T: Into<String>>(token: T) -> Self {
         Self {
@@ -75,14 +111,6 @@ impl Client {
         }
     }
 
-    fn create_client(&self) -> reqwest::Client {
-        reqwest::ClientBuilder::new()
-            .connect_timeout(Duration::from_secs(10))
-            .user_agent(format!("{APP_NAME}/{APP_VERSION}"))
-            .build()
-            .unwrap()
-    }
-
     async fn register_webhook(
         &self,
         config: &Config,
@@ -118,8 +146,7 @@ impl Client {
             .github_api_url()
             .join(&format!("/repos/{owner}/{repo}/hooks"))
             .unwrap();
-        let resp = self
-            .create_client()
+        let resp = create_client()
             .post(url_path)
             .basic_auth(&self.token, Option::<String>::None)
             .json(&data)
@@ -155,8 +182,7 @@ impl Client {
             .github_api_url()
             .join(&format!("/repos/{owner}/{repo}/hooks"))
             .unwrap();
-        let resp = self
-            .create_client()
+        let resp = create_client()
             .get(url_path)
             .basic_auth(&self.token, Option::<String>::None)
             .send()
@@ -198,8 +224,7 @@ impl Client {
             .github_api_url()
             .join(&format!("/repos/{owner}/{repo}/hooks/{id}"))
             .unwrap();
-        let resp = self
-            .create_client()
+        let resp = create_client()
             .delete(url_path)
             .basic_auth(&self.token, Option::<String>::None)
             .send()
@@ -227,7 +252,7 @@ mod tests {
     use serde_json::json;
     use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
 
-    use super::Client;
+    use super::{fetch_hook_ip_ranges, Client};
     use url::Url;
 
     async fn test_config() -> (MockServer, Config) {
@@ -440,4 +465,28 @@ mod tests {
             .await
             .unwrap();
     }
+
+    #[tokio::test]
+    async fn test_fetch_hook_ip_ranges() {
+        let (server, config) = test_config().await;
+
+        Mock::given(matchers::method("GET"))
+            .and(matchers::path("/meta"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "verifiable_password_authentication": true,
+                "hooks": ["192.30.252.0/22", "2620:112:3000::/44"],
+                "web": ["140.82.112.0/20"]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        assert_eq!(
+            fetch_hook_ip_ranges(&config).await.unwrap(),
+            [
+                "192.30.252.0/22".parse().unwrap(),
+                "2620:112:3000::/44".parse().unwrap()
+            ]
+        );
+    }
 }
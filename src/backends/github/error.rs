@@ -11,6 +11,12 @@ pub enum GitHubError {
     #[error("error while unregistering webhook.")]
     CouldNotUnregisterWebhook(#[source] reqwest::Error),
 
+    #[error("error while fetching GitHub meta information.")]
+    CouldNotFetchMeta(#[source] reqwest::Error),
+
+    #[error("malformed IP range received from GitHub: '{0}'.")]
+    MalformedIpRange(String),
+
     #[error("error code received from GitHub.")]
     BadStatusCode(#[source] reqwest::Error),
 
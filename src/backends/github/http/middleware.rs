@@ -5,26 +5,32 @@ use std::{
 };
 
 use axum::{
-    body::{Body, HttpBody},
+    body::Body,
     http::{Method, Request},
     response::{IntoResponse, Response},
 };
 
 use tower::{Layer, Service};
 
-use crate::{crypto::is_valid_signature, error::ErrorCode};
+use crate::{
+    crypto::{SignatureAlgorithm, WebhookVerifier},
+    error::ErrorCode,
+    http::buffer_request_body,
+};
 
 const GITHUB_SIGNATURE_HEADER: &str = "X-Hub-Signature-256";
 const SIGNATURE_PREFIX: &str = "sha256=";
+const GITHUB_LEGACY_SIGNATURE_HEADER: &str = "X-Hub-Signature";
+const LEGACY_SIGNATURE_PREFIX: &str = "sha1=";
 const GITHUB_USER_AGENT: &str = "GitHub-Hookshot/";
 
 pub struct VerifyGitHubSignatureLayer {
-    secret: Option<String>,
+    verifier: WebhookVerifier,
 }
 
 impl VerifyGitHubSignatureLayer {
-    pub fn new(secret: Option<String>) -> Self {
-        Self { secret }
+    pub fn new(verifier: WebhookVerifier) -> Self {
+        Self { verifier }
     }
 }
 
@@ -32,19 +38,19 @@ impl<S> Layer<S> for VerifyGitHubSignatureLayer {
     type Service = VerifyGitHubSignatureMiddleware<S>;
 
     fn layer(&self, inner: S) -> Self::Service {
-        VerifyGitHubSignatureMiddleware::new(self.secret.clone(), inner)
+        VerifyGitHubSignatureMiddleware::new(self.verifier.clone(), inner)
     }
 }
 
 #[derive(Clone)]
 pub struct VerifyGitHubSignatureMiddleware<S> {
-    secret: Option<String>,
+    verifier: WebhookVerifier,
     inner: S,
 }
 
 impl<S> VerifyGitHubSignatureMiddleware<S> {
-    pub fn new(secret: Option<String>, inner: S) -> Self {
-        Self { secret, inner }
+    pub fn new(verifier: WebhookVerifier, inner: S) -> Self {
+        Self { verifier, inner }
     }
 }
 
@@ -65,7 +71,7 @@ where
 
     fn call(&mut self, mut request: Request<Body>) -> Self::Future {
         let mut inner = self.inner.clone();
-        let secret = self.secret.clone();
+        let verifier = self.verifier.clone();
         let fut = async move {
             if request.method() == Method::POST {
                 let headers = request.headers();
@@ -80,31 +86,40 @@ where
                     return Ok(ErrorCode::InvalidUserAgent.into_response());
                 }
 
-                // Check for signature
-                if let Some(secret) = secret {
-                    if let Some(v) = headers.get(GITHUB_SIGNATURE_HEADER) {
-                        let signature = v
-                            .to_str()
+                let body = match buffer_request_body(&mut request, verifier.max_body_size()).await {
+                    Ok(b) => b,
+                    Err(e) => return Ok(e.into_response()),
+                };
+
+                // Check for signature, falling back to the SHA-1 one when allowed
+                if verifier.is_enabled() {
+                    let headers = request.headers();
+                    let signature = match headers.get(GITHUB_SIGNATURE_HEADER) {
+                        Some(v) => Some((v, SIGNATURE_PREFIX, SignatureAlgorithm::Sha256)),
+                        None if verifier.legacy_github_signature() => headers
+                            .get(GITHUB_LEGACY_SIGNATURE_HEADER)
+                            .map(|v| (v, LEGACY_SIGNATURE_PREFIX, SignatureAlgorithm::Sha1)),
+                        None => None,
+                    };
+                    let algorithm = signature.map_or(SignatureAlgorithm::Sha256, |(_, _, a)| a);
+                    let signature = signature.map(|(v, prefix, _)| {
+                        v.to_str()
                             .ok()
-                            .and_then(|x| x.strip_prefix(SIGNATURE_PREFIX))
+                            .and_then(|x| x.strip_prefix(prefix))
                             .unwrap_or_default()
-                            .to_string();
-
-                        let mut body = Vec::<u8>::new();
-                        let request_body = request.body_mut();
-                        while let Some(d) = request_body.data().await {
-                            body.extend(d.unwrap());
-                        }
-
-                        if !is_valid_signature(&signature, &body, &secret) {
-                            return Ok(ErrorCode::InvalidSignature.into_response());
-                        }
-
-                        *request.body_mut() = body.into();
-                    } else {
-                        return Ok(ErrorCode::InvalidSignature.into_response());
+                    });
+
+                    if let Err(e) = verifier.verify_signature(
+                        verifier.repository_name(&body).as_deref(),
+                        signature,
+                        &body,
+                        algorithm,
+                    ) {
+                        return Ok(e.into_response());
                     }
                 }
+
+                *request.body_mut() = body.into();
             }
 
             let future = inner.call(request);
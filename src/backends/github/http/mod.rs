@@ -2,25 +2,27 @@ pub mod middleware;
 
 use axum::{
     extract::Extension,
-    http::{header::HeaderName, HeaderMap, HeaderValue},
+    http::{header::HeaderName, HeaderMap, HeaderValue, StatusCode},
 };
-use serde::Deserialize;
 
 use crate::{
+    backends::{
+        cleanup_on_delete, clone_backend, header_to_str, parse_body, parse_commit,
+        pretty_print_json, push_event_response, record_delivery, synchronize_on_push,
+        RepositoryField,
+    },
     config::Config,
     error::ErrorCode,
-    git::{GitBackend, RefType, RepoCloner, RepositoryPath},
+    git::RefType,
+    history::Delivery,
+    jobs::JobRequest,
     service::ServiceHandler,
 };
-use serde_json::Value;
 
-use super::{PingEvent, PushEvent};
+use super::{DeleteEvent, PingEvent, PushEvent};
 
-fn pretty_print_json(s: &str) -> String {
-    serde_json::from_str::<Value>(s)
-        .and_then(|n| serde_json::to_string_pretty(&n))
-        .unwrap_or_default()
-}
+/// Payload field of the synchronized repository, also used to verify deliveries.
+pub const REPOSITORY_FIELD: RepositoryField = RepositoryField::FullName;
 
 #[tracing::instrument(skip(config), fields(body_pretty = %pretty_print_json(&body)))]
 pub async fn webhook(
@@ -28,31 +30,46 @@ pub async fn webhook(
     body: String,
     config: Extension<Config>,
     services: Extension<ServiceHandler>,
-) -> Result<(HeaderMap, String), ErrorCode> {
+) -> Result<(StatusCode, HeaderMap, String), ErrorCode> {
     let event = headers
         .get("x-github-event")
-        .ok_or(ErrorCode::MissingEventHeader)?;
-
-    match event
+        .ok_or(ErrorCode::MissingEventHeader)?
         .to_str()
-        .map_err(|_| ErrorCode::MalformedEventHeader)?
-    {
-        "ping" => handle_ping_event(&config, &services, parse_body(&body)?).await,
-        "push" => handle_push_event(&config, &services, parse_body(&body)?).await,
-        other => Err(ErrorCode::UnsupportedEventHeader(other.to_string())),
-    }
+        .map_err(|_| ErrorCode::MalformedEventHeader)?;
+
+    let mut delivery = Delivery::new(
+        "github",
+        event,
+        header_to_str(&headers, "x-github-delivery"),
+    );
+    let result = handle_event(&config, &services, &mut delivery, event, &body).await;
+    record_delivery(&services, delivery, &result);
+    result
 }
 
-fn parse_body<'a, T: Deserialize<'a>>(body: &'a str) -> Result<T, ErrorCode> {
-    serde_json::from_str(body).map_err(ErrorCode::MalformedEventBody)
+async fn handle_event(
+    config: &Config,
+    services: &ServiceHandler,
+    delivery: &mut Delivery,
+    event: &str,
+    body: &str,
+) -> Result<(StatusCode, HeaderMap, String), ErrorCode> {
+    match event {
+        "ping" => handle_ping_event(config, services, parse_body(body)?).await,
+        "push" => handle_push_event(config, services, delivery, parse_body(body)?, body).await,
+        "delete" => handle_delete_event(delivery, parse_body(body)?).await,
+        other => Err(ErrorCode::UnsupportedEventHeader(other.to_string())),
+    }
 }
 
-#[tracing::instrument]
+#[tracing::instrument(skip(body))]
 async fn handle_push_event<'a>(
     config: &Config,
     services: &ServiceHandler,
+    delivery: &mut Delivery,
     push_event: PushEvent<'a>,
-) -> Result<(HeaderMap, String), ErrorCode> {
+    body: &str,
+) -> Result<(StatusCode, HeaderMap, String), ErrorCode> {
     let mut header_map = HeaderMap::new();
     header_map.insert(
         HeaderName::from_static("content-type"),
@@ -60,21 +77,56 @@ async fn handle_push_event<'a>(
     );
     let branch = RefType::try_from(push_event.reference)
         .map_err(|e| ErrorCode::MalformedEventBodyField("ref".into(), e.to_string()))?;
-    let repository_path = RepositoryPath::new(push_event.repository.full_name).map_err(|e| {
-        ErrorCode::MalformedEventBodyField("repository.full_name".into(), e.to_string())
-    })?;
-
-    RepoCloner::create_or_update_using_config(
-        config,
-        services,
-        GitBackend::GitHub,
-        &repository_path,
-        branch,
-    )
-    .await
-    .map_err(|e| ErrorCode::UnhandledError(e.to_string()))?;
-
-    Ok((header_map, serde_json::to_string(&push_event).unwrap()))
+    let repository_path = REPOSITORY_FIELD.parse_path(body)?;
+
+    let head_commit = push_event.head_commit.as_ref().and_then(|c| c.id);
+    let commit = match (push_event.after, head_commit) {
+        (Some(after), _) => Some(parse_commit("after", after)?),
+        (None, Some(id)) => Some(parse_commit("head_commit.id", id)?),
+        (None, None) => None,
+    };
+
+    if push_event.deleted {
+        cleanup_on_delete(config, services, delivery, &repository_path, branch).await?;
+    } else {
+        synchronize_on_push(
+            config,
+            services,
+            delivery,
+            JobRequest::synchronization(
+                clone_backend(config, "github", &repository_path)?,
+                repository_path.clone(),
+                branch,
+            )
+            .with_forced(push_event.forced)
+            .with_commit(commit),
+        )
+        .await?;
+    }
+
+    let (status, body) = push_event_response(&push_event, delivery);
+    Ok((status, header_map, body))
+}
+
+/// Deletions are also sent as push events with `deleted: true`, which are cleaned up instead,
+/// so a deleted reference is only cleaned up once.
+#[tracing::instrument]
+async fn handle_delete_event<'a>(
+    delivery: &mut Delivery,
+    delete_event: DeleteEvent<'a>,
+) -> Result<(StatusCode, HeaderMap, String), ErrorCode> {
+    let mut header_map = HeaderMap::new();
+    header_map.insert(
+        HeaderName::from_static("content-type"),
+        HeaderValue::from_static("application/json"),
+    );
+
+    let reason = "Deleted references are cleaned up from push events".to_string();
+    tracing::info!(message = "Skipping delete event", reason = %reason);
+    delivery.skipped.push(reason);
+
+    let (status, body) = push_event_response(&delete_event, delivery);
+    Ok((status, header_map, body))
 }
 
 #[tracing::instrument]
@@ -82,161 +134,68 @@ async fn handle_ping_event<'a>(
     config: &Config,
     services: &ServiceHandler,
     ping_event: PingEvent<'a>,
-) -> Result<(HeaderMap, String), ErrorCode> {
+) -> Result<(StatusCode, HeaderMap, String), ErrorCode> {
     let mut header_map = HeaderMap::new();
     header_map.insert(
         HeaderName::from_static("content-type"),
         HeaderValue::from_static("application/json"),
     );
-    Ok((header_map, serde_json::to_string(&ping_event).unwrap()))
+    Ok((
+        StatusCode::OK,
+        header_map,
+        serde_json::to_string(&ping_event).unwrap(),
+    ))
 }
 
 #[cfg(test)]
 mod tests {
-    use std::{
-        any::Any,
-        path::{Path, PathBuf},
-        sync::Arc,
-    };
-
     use assert_matches::assert_matches;
-    use async_trait::async_trait;
-    use axum::{body::Body, http::Request, response::Response, Router};
+    use axum::{body::Body, extract::ConnectInfo, http::Request, Router};
     use pretty_assertions::assert_eq;
-    use pseudo::Mock;
     use reqwest::StatusCode;
     use serde_json::json;
     use tower::ServiceExt;
+    use url::Url;
+    use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
 
     use crate::{
         backends::github::{Commit, CommitUser, Repository},
-        config::Config,
+        config::RefFilter,
         error::ErrorCode,
-        git::{GitError, GitService},
-        http::build_http_router,
-        service::ServiceHandler,
+        history::{Delivery, DeliveryCache, HistoryStore},
+        http::{build_http_router, IpAllowlist},
+        jobs::{JobKind, JobStatus},
+        testing::{
+            assert_response_is_error, create_failed_action_error, create_test_config,
+            create_test_services, create_test_services_with_history, extract_fake_action_service,
+            extract_fake_git_service, response_to_json, wait_for_response_job,
+        },
     };
 
     use super::{handle_push_event, PushEvent};
 
-    #[derive(Debug)]
-    struct FakeGitService {
-        pub clone_repository: Mock<(PathBuf, String, String, String), Result<String, GitError>>,
-        pub pull: Mock<PathBuf, Result<String, GitError>>,
-        pub checkout: Mock<(PathBuf, String), Result<String, GitError>>,
-        pub fetch: Mock<PathBuf, Result<String, GitError>>,
-    }
-
-    impl FakeGitService {
-        pub fn new() -> Self {
-            Self {
-                clone_repository: Mock::new(Ok("OK".into())),
-                pull: Mock::new(Ok("OK".into())),
-                checkout: Mock::new(Ok("OK".into())),
-                fetch: Mock::new(Ok("OK".into())),
-            }
-        }
-    }
-
-    #[async_trait]
-    impl GitService for FakeGitService {
-        async fn clone_repository(
-            &self,
-            working_dir: &Path,
-            reference: &str,
-            repo_path: &str,
-            folder_path: &str,
-        ) -> Result<String, GitError> {
-            self.clone_repository.call((
-                working_dir.to_owned(),
-                reference.to_owned(),
-                repo_path.to_owned(),
-                folder_path.to_owned(),
-            ))
-        }
-
-        async fn pull(&self, working_dir: &Path) -> Result<String, GitError> {
-            self.pull.call(working_dir.to_owned())
-        }
-
-        async fn checkout(&self, working_dir: &Path, reference: &str) -> Result<String, GitError> {
-            self.checkout
-                .call((working_dir.to_owned(), reference.to_owned()))
-        }
-
-        async fn fetch(&self, working_dir: &Path) -> Result<String, GitError> {
-            self.fetch.call(working_dir.to_owned())
-        }
-
-        fn as_any(&self) -> &dyn Any {
-            self
-        }
-    }
-
-    fn create_test_config() -> Config {
-        Config::empty()
-    }
-
-    fn create_test_services() -> ServiceHandler {
-        ServiceHandler::new(Arc::new(FakeGitService::new()))
-    }
-
     fn create_test_router() -> Router {
         let config = create_test_config();
         let services = create_test_services();
         build_http_router(config, services)
     }
 
-    fn extract_fake_git_service(services: &ServiceHandler) -> &FakeGitService {
-        services
-            .git()
-            .as_any()
-            .downcast_ref::<FakeGitService>()
-            .unwrap()
-    }
-
-    async fn response_to_string<T: hyper::body::HttpBody>(response: Response<T>) -> String
-    where
-        T::Error: std::fmt::Debug,
-    {
-        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
-        String::from_utf8(bytes.to_vec()).unwrap()
-    }
-
-    async fn response_to_json<T: hyper::body::HttpBody>(response: Response<T>) -> serde_json::Value
-    where
-        T::Error: std::fmt::Debug,
-    {
-        let string = response_to_string(response).await;
-        serde_json::from_str(&string).unwrap()
-    }
-
-    fn error_to_json(err: ErrorCode) -> serde_json::Value {
-        serde_json::to_value(err.details()).unwrap()
-    }
-
-    async fn assert_response_is_error<T: hyper::body::HttpBody>(
-        response: Response<T>,
-        error: ErrorCode,
-    ) where
-        T::Error: std::fmt::Debug,
-    {
-        let data = response_to_json(response).await;
-        assert_eq!(data, error_to_json(error));
-    }
-
     #[tokio::test]
     async fn test_handle_push_event_wrong_ref() {
         let config = create_test_config();
         let services = create_test_services();
 
         let event = PushEvent {
-            base_ref: "wrong",
+            base_ref: Some("wrong"),
             reference: "wrong",
-            head_commit: Commit {
-                message: "ooo",
+            after: None,
+            deleted: false,
+            forced: false,
+            head_commit: Some(Commit {
+                id: None,
+                message: "ooo".into(),
                 timestamp: "nope",
-            },
+            }),
             pusher: CommitUser {
                 email: "hello@local.test",
                 name: "hello",
@@ -247,7 +206,8 @@ mod tests {
             },
         };
 
-        let err = handle_push_event(&config, &services, event)
+        let mut delivery = Delivery::new("github", "push", None);
+        let err = handle_push_event(&config, &services, &mut delivery, event, "{}")
             .await
             .unwrap_err();
         assert_matches!(err, ErrorCode::MalformedEventBodyField(_, _));
@@ -298,6 +258,81 @@ mod tests {
         assert_eq!(status, StatusCode::BAD_REQUEST);
     }
 
+    #[tokio::test]
+    async fn test_verify_github_signature_middleware_malformed_signature() {
+        let mut config = create_test_config();
+        config.set_webhook_secret("secret");
+        let app = build_http_router(config, create_test_services());
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("User-Agent", "GitHub-Hookshot/value")
+                    .header("X-Hub-Signature-256", "sha256=not-hex")
+                    .uri("/webhook/github")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_response_is_error(response, ErrorCode::InvalidSignature).await;
+    }
+
+    #[tokio::test]
+    async fn test_verify_github_signature_middleware_legacy_signature() {
+        let mut config = create_test_config();
+        config.set_webhook_secret("new-secret");
+        config.set_previous_webhook_secrets(vec!["secret".into()]);
+
+        let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, b"secret");
+        let signature = format!("sha1={}", hex::encode(ring::hmac::sign(&key, b"{}")));
+        let request = || {
+            Request::builder()
+                .method("POST")
+                .header("User-Agent", "GitHub-Hookshot/value")
+                .header("X-Hub-Signature", &signature)
+                .uri("/webhook/github")
+                .body(Body::from("{}"))
+                .unwrap()
+        };
+
+        // SHA-1 signatures need to be explicitly allowed
+        let app = build_http_router(config.clone(), create_test_services());
+        let response = app.oneshot(request()).await.unwrap();
+        assert_response_is_error(response, ErrorCode::InvalidSignature).await;
+
+        config.set_legacy_github_signature(true);
+        let app = build_http_router(config, create_test_services());
+        let response = app.oneshot(request()).await.unwrap();
+        assert_response_is_error(response, ErrorCode::MissingEventHeader).await;
+    }
+
+    #[tokio::test]
+    async fn test_payload_too_large() {
+        let mut config = create_test_config();
+        config.set_max_body_size(16);
+        let app = build_http_router(config, create_test_services());
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("User-Agent", "GitHub-Hookshot/value")
+                    .header("X-GitHub-Event", "push")
+                    .uri("/webhook/github")
+                    .body(Body::from("{\"ref\": \"refs/heads/main\"}"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_response_is_error(response, ErrorCode::PayloadTooLarge(16)).await;
+    }
+
     #[tokio::test]
     async fn test_missing_event_header() {
         let app = create_test_router();
@@ -506,8 +541,8 @@ mod tests {
             .unwrap();
 
         let status = response.status();
-        let data = response_to_json(response).await;
-        assert_eq!(data, json_data);
+        let job = wait_for_response_job(&services, response).await;
+        assert_eq!(job.status, JobStatus::Succeeded);
 
         let fake_git_service = extract_fake_git_service(&services);
         assert!(fake_git_service.clone_repository.called());
@@ -515,7 +550,99 @@ mod tests {
         assert!(!fake_git_service.checkout.called());
         assert!(!fake_git_service.pull.called());
 
-        assert_eq!(status, StatusCode::OK);
+        assert_eq!(status, StatusCode::ACCEPTED);
+    }
+
+    #[tokio::test]
+    async fn test_push_event_malformed_commit() {
+        let services = create_test_services();
+        let app = build_http_router(create_test_config(), services.clone());
+
+        let json_data = json!({
+            "ref": "refs/heads/sample",
+            "after": "--upload-pack=touch /tmp/pwned",
+            "head_commit": {
+                "message": "sample",
+                "timestamp": "sample"
+            },
+            "repository": {
+                "full_name": "Srynetix/things",
+                "name": "things"
+            },
+            "pusher": {
+                "name": "sample",
+                "email": "sample"
+            }
+        });
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("User-Agent", "GitHub-Hookshot/value")
+                    .header("X-GitHub-Event", "push")
+                    .uri("/webhook/github")
+                    .body(json_data.to_string().into())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let status = response.status();
+        assert_response_is_error(
+            response,
+            ErrorCode::MalformedEventBodyField(
+                "after".into(),
+                "Malformed commit hash: --upload-pack=touch /tmp/pwned".into(),
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let fake_git_service = extract_fake_git_service(&services);
+        assert!(!fake_git_service.clone_repository.called());
+    }
+
+    #[tokio::test]
+    async fn test_push_event_real_branch_payload() {
+        let config = create_test_config();
+        let services = create_test_services();
+        let app = build_http_router(config, services.clone());
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("User-Agent", "GitHub-Hookshot/value")
+                    .header("X-GitHub-Event", "push")
+                    .uri("/webhook/github")
+                    .body(include_str!("../tests/push_branch_sample.json").into())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let status = response.status();
+        let job = wait_for_response_job(&services, response).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(job.status, JobStatus::Succeeded);
+        assert_eq!(job.reference, "main");
+        assert_eq!(
+            job.commit.as_deref(),
+            Some("a10867b14bb761a232cd80139fbd4c0d33264240")
+        );
+        assert_eq!(
+            job.report.unwrap().head_commit.as_deref(),
+            Some("0d1a26e67d8f5eaf1f6ba5c57fc3c7d91ac0fd1c")
+        );
+
+        let fake_git_service = extract_fake_git_service(&services);
+        let calls = fake_git_service.clone_repository.calls();
+        assert_eq!(calls[0].1.as_deref(), Some("main"));
+        assert_eq!(calls[0].3, "Hello-World");
+        assert_eq!(
+            fake_git_service.reset_hard.calls()[0].1,
+            "a10867b14bb761a232cd80139fbd4c0d33264240"
+        );
     }
 
     #[tokio::test]
@@ -559,15 +686,564 @@ mod tests {
             .unwrap();
 
         let status = response.status();
-        let data = response_to_json(response).await;
-        assert_eq!(data, json_data);
+        let job = wait_for_response_job(&services, response).await;
+        assert_eq!(job.status, JobStatus::Succeeded);
 
         let fake_git_service = extract_fake_git_service(&services);
         assert!(!fake_git_service.clone_repository.called());
         assert!(fake_git_service.fetch.called());
         assert!(fake_git_service.checkout.called());
-        assert!(fake_git_service.pull.called());
+        assert!(!fake_git_service.pull.called());
+        assert_eq!(
+            fake_git_service.reset_hard.calls()[0].1,
+            "origin/sample".to_string()
+        );
+        assert!(!fake_git_service.clean.called());
+
+        assert_eq!(status, StatusCode::ACCEPTED);
+    }
+
+    #[tokio::test]
+    async fn test_push_event_actions() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tempdir.path().join("things")).unwrap();
+
+        let mut config = create_test_config();
+        config.set_working_dir(tempdir.path());
+        config
+            .set_repo_actions(
+                &json!({
+                    "Srynetix/things": [
+                        {"command": "npm ci"},
+                        {"command": "npm run build", "timeout": 60}
+                    ]
+                })
+                .to_string(),
+            )
+            .unwrap();
+
+        let services = create_test_services();
+        let app = build_http_router(config, services.clone());
+
+        let json_data = json!({
+            "ref": "refs/branches/sample",
+            "base_ref": "refs/branches/sample",
+            "head_commit": {
+                "message": "sample",
+                "timestamp": "sample"
+            },
+            "repository": {
+                "full_name": "Srynetix/things",
+                "name": "things"
+            },
+            "pusher": {
+                "name": "sample",
+                "email": "sample"
+            }
+        });
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("User-Agent", "GitHub-Hookshot/value")
+                    .header("X-GitHub-Event", "push")
+                    .uri("/webhook/github")
+                    .body(json_data.to_string().into())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        wait_for_response_job(&services, response).await;
+
+        let fake_action_service = extract_fake_action_service(&services);
+        let calls = fake_action_service.run.calls();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].0, tempdir.path().join("things"));
+        assert_eq!(calls[0].1.command, "npm ci");
+        assert_eq!(calls[1].1.command, "npm run build");
+        assert_eq!(calls[1].1.timeout, Some(60));
+    }
+
+    #[tokio::test]
+    async fn test_push_event_action_failure() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tempdir.path().join("things")).unwrap();
+
+        let mut config = create_test_config();
+        config.set_working_dir(tempdir.path());
+        config
+            .set_repo_actions(&json!({"Srynetix/things": [{"command": "false"}]}).to_string())
+            .unwrap();
+
+        let services = create_test_services();
+        extract_fake_action_service(&services)
+            .run
+            .return_value(Err(create_failed_action_error("false")));
+        let app = build_http_router(config, services.clone());
+
+        let json_data = json!({
+            "ref": "refs/branches/sample",
+            "base_ref": "refs/branches/sample",
+            "head_commit": {
+                "message": "sample",
+                "timestamp": "sample"
+            },
+            "repository": {
+                "full_name": "Srynetix/things",
+                "name": "things"
+            },
+            "pusher": {
+                "name": "sample",
+                "email": "sample"
+            }
+        });
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("User-Agent", "GitHub-Hookshot/value")
+                    .header("X-GitHub-Event", "push")
+                    .uri("/webhook/github")
+                    .body(json_data.to_string().into())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        let job = wait_for_response_job(&services, response).await;
+        assert_eq!(job.status, JobStatus::Failed);
+        assert_eq!(
+            job.error.as_deref(),
+            Some("Command 'false' failed with status code Some(1): ")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_push_event_branch_not_allowed() {
+        let mut config = create_test_config();
+        config
+            .repository_mut("Srynetix/things")
+            .unwrap()
+            .set_branches(RefFilter::new(&["main", "release/*"], &[]).unwrap());
+
+        let services = create_test_services();
+        let app = build_http_router(config, services.clone());
+
+        let json_data = json!({
+            "ref": "refs/branches/sample",
+            "base_ref": "refs/branches/sample",
+            "head_commit": {
+                "message": "sample",
+                "timestamp": "sample"
+            },
+            "repository": {
+                "full_name": "Srynetix/things",
+                "name": "things"
+            },
+            "pusher": {
+                "name": "sample",
+                "email": "sample"
+            }
+        });
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("User-Agent", "GitHub-Hookshot/value")
+                    .header("X-GitHub-Event", "push")
+                    .uri("/webhook/github")
+                    .body(json_data.to_string().into())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response_to_json(response).await,
+            json!({
+                "status": "skipped",
+                "reasons": ["Reference 'sample' is not allowed for repository 'Srynetix/things'"]
+            })
+        );
+
+        let fake_git_service = extract_fake_git_service(&services);
+        assert!(!fake_git_service.clone_repository.called());
+        assert!(!fake_git_service.fetch.called());
+    }
+
+    #[tokio::test]
+    async fn test_push_event_tag_ignored() {
+        let mut config = create_test_config();
+        config
+            .repository_mut("Codertocat/Hello-World")
+            .unwrap()
+            .set_tags(RefFilter::new(&[], &["simple-*"]).unwrap());
+
+        let services = create_test_services();
+        let app = build_http_router(config, services.clone());
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("User-Agent", "GitHub-Hookshot/value")
+                    .header("X-GitHub-Event", "push")
+                    .uri("/webhook/github")
+                    .body(include_str!("../tests/push_sample.json").into())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response_to_json(response).await["status"], json!("skipped"));
+        assert!(!extract_fake_git_service(&services)
+            .clone_repository
+            .called());
+    }
+
+    fn github_request(event: &str, body: &str) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .header("User-Agent", "GitHub-Hookshot/value")
+            .header("X-GitHub-Event", event)
+            .uri("/webhook/github")
+            .body(body.to_owned().into())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_push_event_deleted_branch_cleanup() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(tempdir.path().join("previews/feature-x-217d2bf5")).unwrap();
+
+        let mut config = create_test_config();
+        config
+            .repository_mut("Codertocat/Hello-World")
+            .unwrap()
+            .set_target_dir(tempdir.path().join("previews/{branch}"));
+
+        let services = create_test_services();
+        let app = build_http_router(config, services.clone());
+        let response = app
+            .oneshot(github_request(
+                "push",
+                include_str!("../tests/push_delete_sample.json"),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let job = wait_for_response_job(&services, response).await;
+        assert_eq!(job.status, JobStatus::Succeeded);
+        assert_eq!(job.kind, JobKind::Cleanup);
+        assert!(!tempdir.path().join("previews/feature-x-217d2bf5").exists());
+        assert!(!extract_fake_git_service(&services)
+            .clone_repository
+            .called());
+    }
+
+    #[tokio::test]
+    async fn test_delete_event_skipped() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        // The matching push event does the cleanup
+        let mut config = create_test_config();
+        config
+            .repository_mut("Codertocat/Hello-World")
+            .unwrap()
+            .set_target_dir(tempdir.path().join("previews/{branch}"));
 
-        assert_eq!(status, StatusCode::OK);
+        let services = create_test_services();
+        let app = build_http_router(config, services.clone());
+        let response = app
+            .oneshot(github_request(
+                "delete",
+                include_str!("../tests/delete_sample.json"),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response_to_json(response).await["status"], json!("skipped"));
+        assert!(services.jobs().list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_push_event_history() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let services =
+            create_test_services_with_history(HistoryStore::open(tempdir.path(), 10).unwrap());
+        let app = build_http_router(create_test_config(), services.clone());
+
+        let json_data = json!({
+            "ref": "refs/branches/sample",
+            "base_ref": "refs/branches/sample",
+            "after": "0d1a26e67d8f5eaf1f6ba5c57fc3c7d91ac0fd1c",
+            "head_commit": {
+                "message": "sample",
+                "timestamp": "sample"
+            },
+            "repository": {
+                "full_name": "Srynetix/things",
+                "name": "things"
+            },
+            "pusher": {
+                "name": "sample",
+                "email": "sample"
+            }
+        });
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("User-Agent", "GitHub-Hookshot/value")
+                    .header("X-GitHub-Event", "push")
+                    .header("X-GitHub-Delivery", "72d3162e-cc78-11e3-81ab-4c9367dc0958")
+                    .uri("/webhook/github")
+                    .body(json_data.to_string().into())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let job = wait_for_response_job(&services, response).await;
+
+        let deliveries = services.history().deliveries().unwrap();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].id, "72d3162e-cc78-11e3-81ab-4c9367dc0958");
+        assert_eq!(deliveries[0].event, "push");
+        assert_eq!(deliveries[0].repository.as_deref(), Some("Srynetix/things"));
+        assert_eq!(deliveries[0].reference.as_deref(), Some("sample"));
+        assert_eq!(
+            deliveries[0].head_commit.as_deref(),
+            Some("0d1a26e67d8f5eaf1f6ba5c57fc3c7d91ac0fd1c")
+        );
+        assert_eq!(deliveries[0].job_ids, vec![job.id.clone()]);
+
+        let jobs = services.history().jobs().unwrap();
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].id, job.id);
+        assert_eq!(jobs[0].status, JobStatus::Succeeded);
+        assert!(jobs[0].duration_ms.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_replayed_delivery() {
+        let services = create_test_services().with_delivery_cache(DeliveryCache::new(10));
+        let app = build_http_router(create_test_config(), services.clone());
+
+        let push_request = |body: String| {
+            Request::builder()
+                .method("POST")
+                .header("User-Agent", "GitHub-Hookshot/value")
+                .header("X-GitHub-Event", "push")
+                .header("X-GitHub-Delivery", "72d3162e-cc78-11e3-81ab-4c9367dc0958")
+                .uri("/webhook/github")
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        // Failed deliveries can be redelivered
+        for _ in 0..2 {
+            let response = app
+                .clone()
+                .oneshot(push_request("{}".into()))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+
+        let json_data = json!({
+            "ref": "refs/heads/main",
+            "after": "0d1a26e67d8f5eaf1f6ba5c57fc3c7d91ac0fd1c",
+            "repository": {
+                "full_name": "Srynetix/things",
+                "name": "things"
+            },
+            "pusher": {
+                "name": "sample",
+                "email": "sample"
+            }
+        });
+        let response = app
+            .clone()
+            .oneshot(push_request(json_data.to_string()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        wait_for_response_job(&services, response).await;
+
+        let response = app
+            .oneshot(push_request(json_data.to_string()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_response_is_error(
+            response,
+            ErrorCode::ReplayedDelivery("72d3162e-cc78-11e3-81ab-4c9367dc0958".into()),
+        )
+        .await;
+        assert_eq!(
+            extract_fake_git_service(&services)
+                .clone_repository
+                .calls()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_missing_delivery_header() {
+        let mut config = create_test_config();
+        config.set_webhook_secret("secret");
+        let services = create_test_services().with_delivery_cache(DeliveryCache::new(10));
+        let app = build_http_router(config, services.clone());
+
+        let body = json!({
+            "ref": "refs/heads/main",
+            "after": "0d1a26e67d8f5eaf1f6ba5c57fc3c7d91ac0fd1c",
+            "repository": {
+                "full_name": "Srynetix/things",
+                "name": "things"
+            },
+            "pusher": {
+                "name": "sample",
+                "email": "sample"
+            }
+        })
+        .to_string();
+        let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, b"secret");
+        let signature = format!(
+            "sha256={}",
+            hex::encode(ring::hmac::sign(&key, body.as_bytes()))
+        );
+
+        // A captured signed body can not be replayed by dropping the delivery header
+        for _ in 0..2 {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .header("User-Agent", "GitHub-Hookshot/value")
+                        .header("X-GitHub-Event", "push")
+                        .header("X-Hub-Signature-256", &signature)
+                        .uri("/webhook/github")
+                        .body(body.clone().into())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            assert_response_is_error(
+                response,
+                ErrorCode::MissingDeliveryHeader("x-github-delivery".into()),
+            )
+            .await;
+        }
+        assert!(!extract_fake_git_service(&services)
+            .clone_repository
+            .called());
+    }
+
+    #[tokio::test]
+    async fn test_source_ip_allowlist() {
+        let services = create_test_services().with_ip_allowlist(IpAllowlist::new(
+            vec!["10.0.0.0/8".parse().unwrap()],
+            false,
+            vec!["127.0.0.1/32".parse().unwrap()],
+        ));
+        let app = build_http_router(create_test_config(), services);
+
+        let request_from = |forwarded_for: &str| {
+            Request::builder()
+                .method("POST")
+                .header("User-Agent", "GitHub-Hookshot/value")
+                .header("X-Forwarded-For", forwarded_for)
+                .uri("/webhook/github")
+                .extension(ConnectInfo(
+                    "127.0.0.1:4000".parse::<std::net::SocketAddr>().unwrap(),
+                ))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(request_from("192.168.1.1"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_response_is_error(response, ErrorCode::ForbiddenSourceIp("192.168.1.1".into()))
+            .await;
+
+        let response = app.oneshot(request_from("10.1.2.3")).await.unwrap();
+        assert_response_is_error(response, ErrorCode::MissingEventHeader).await;
+    }
+
+    #[tokio::test]
+    async fn test_github_hook_ips_only_allow_github() {
+        let server = MockServer::start().await;
+        let mut config = create_test_config();
+        config.set_github_api_url(Url::parse(&server.uri()).unwrap());
+
+        Mock::given(matchers::method("GET"))
+            .and(matchers::path("/meta"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "hooks": ["192.30.252.0/22"]
+            })))
+            .mount(&server)
+            .await;
+
+        let allowlist = IpAllowlist::new(vec!["10.0.0.0/8".parse().unwrap()], true, vec![]);
+        assert!(allowlist.refresh_github_hook_ips(&config).await);
+        let services = create_test_services().with_ip_allowlist(allowlist);
+        let app = build_http_router(config, services);
+
+        let request_from = |uri: &str, client: &str| {
+            Request::builder()
+                .method("POST")
+                .uri(uri)
+                .extension(ConnectInfo(
+                    format!("{}:4000", client)
+                        .parse::<std::net::SocketAddr>()
+                        .unwrap(),
+                ))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        // Anyone can send requests from GitHub hook ranges, through a GitHub webhook
+        let response = app
+            .clone()
+            .oneshot(request_from("/webhook/github", "192.30.252.1"))
+            .await
+            .unwrap();
+        assert_response_is_error(response, ErrorCode::InvalidUserAgent).await;
+
+        for uri in ["/webhook/gitlab", "/webhook/gitea", "/webhook/bitbucket"] {
+            let response = app
+                .clone()
+                .oneshot(request_from(uri, "192.30.252.1"))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+            assert_response_is_error(
+                response,
+                ErrorCode::ForbiddenSourceIp("192.30.252.1".into()),
+            )
+            .await;
+        }
+
+        let response = app
+            .oneshot(request_from("/webhook/gitlab", "10.1.2.3"))
+            .await
+            .unwrap();
+        assert_response_is_error(response, ErrorCode::InvalidUserAgent).await;
     }
 }
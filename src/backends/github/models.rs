@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -16,15 +18,41 @@ pub struct Repository<'a> {
 pub struct PushEvent<'a> {
     #[serde(rename = "ref")]
     pub reference: &'a str,
-    pub base_ref: &'a str,
-    pub head_commit: Commit<'a>,
+    /// Only set on tag pushes
+    #[serde(default, borrow)]
+    pub base_ref: Option<&'a str>,
+    #[serde(default, borrow, skip_serializing_if = "Option::is_none")]
+    pub after: Option<&'a str>,
+    /// Set when the branch or tag was deleted
+    #[serde(default)]
+    pub deleted: bool,
+    /// Set when the branch or tag was force-pushed
+    #[serde(default)]
+    pub forced: bool,
+    /// Missing when the branch or tag was deleted
+    #[serde(borrow)]
+    pub head_commit: Option<Commit<'a>>,
     pub repository: Repository<'a>,
     pub pusher: CommitUser<'a>,
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DeleteEvent<'a> {
+    /// Short branch or tag name (e.g. `main`)
+    #[serde(rename = "ref")]
+    pub reference: &'a str,
+    /// `branch` or `tag`
+    pub ref_type: &'a str,
+    pub repository: Repository<'a>,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Commit<'a> {
-    pub message: &'a str,
+    #[serde(default, borrow, skip_serializing_if = "Option::is_none")]
+    pub id: Option<&'a str>,
+    /// Commit messages usually contain escaped characters, so they can not always be borrowed
+    #[serde(borrow)]
+    pub message: Cow<'a, str>,
     pub timestamp: &'a str,
 }
 
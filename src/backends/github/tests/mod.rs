@@ -1,4 +1,4 @@
-use super::models::{PingEvent, PushEvent};
+use super::models::{DeleteEvent, PingEvent, PushEvent};
 
 #[test]
 fn test_parse_ping_event() {
@@ -9,5 +9,34 @@ fn test_parse_ping_event() {
 #[test]
 fn test_parse_push_event() {
     let event_str = include_str!("./push_sample.json");
-    let _: PushEvent = serde_json::from_str(event_str).expect("should deserialize");
+    let event: PushEvent = serde_json::from_str(event_str).expect("should deserialize");
+    assert_eq!(event.base_ref, Some("refs/heads/main"));
+}
+
+#[test]
+fn test_parse_push_branch_event() {
+    let event_str = include_str!("./push_branch_sample.json");
+    let event: PushEvent = serde_json::from_str(event_str).expect("should deserialize");
+    assert_eq!(event.reference, "refs/heads/main");
+    assert_eq!(event.base_ref, None);
+    assert_eq!(
+        event.head_commit.unwrap().message,
+        "Update README.md\n\nAdd installation instructions"
+    );
+}
+
+#[test]
+fn test_parse_push_delete_event() {
+    let event_str = include_str!("./push_delete_sample.json");
+    let event: PushEvent = serde_json::from_str(event_str).expect("should deserialize");
+    assert!(event.deleted);
+    assert!(event.head_commit.is_none());
+}
+
+#[test]
+fn test_parse_delete_event() {
+    let event_str = include_str!("./delete_sample.json");
+    let event: DeleteEvent = serde_json::from_str(event_str).expect("should deserialize");
+    assert_eq!(event.reference, "simple-tag");
+    assert_eq!(event.ref_type, "tag");
 }
@@ -0,0 +1,368 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{
+    config::Config,
+    server_info::{APP_NAME, APP_VERSION},
+};
+
+use super::error::GitLabError;
+
+const TOKEN_HEADER: &str = "PRIVATE-TOKEN";
+
+pub struct Client {
+    token: String,
+}
+
+#[derive(Deserialize)]
+pub struct Webhook {
+    id: u32,
+    url: String,
+}
+
+impl Client {
+    pub fn new<T: Into<String>>(token: T) -> Self {
+        Self {
+            token: token.into(),
+        }
+    }
+
+    pub async fn try_register_webhook(
+        &self,
+        config: &Config,
+        owner: &str,
+        repo: &str,
+        url: &Url,
+    ) -> Result<u32, GitLabError> {
+        if let Some(u) = self.check_webhook_url(config, owner, repo, url).await? {
+            tracing::warn!(
+                id = u,
+                message = "Webhook already registered",
+                owner = owner,
+                repo = repo,
+                url = %url
+            );
+            Ok(u)
+        } else {
+            Ok(self.register_webhook(config, owner, repo, url).await?.id)
+        }
+    }
+
+    pub async fn try_unregister_webhook(
+        &self,
+        config: &Config,
+        owner: &str,
+        repo: &str,
+        url: &Url,
+    ) -> Result<(), GitLabError> {
+        if let Some(u) = self.check_webhook_url(config, owner, repo, url).await? {
+            self.unregister_webhook(config, owner, repo, u).await
+        } else {
+            tracing::error!(
+                message = "Unknown webhook",
+                owner = owner,
+                repo = repo,
+                url = %url
+            );
+            Ok(())
+        }
+    }
+
+    fn create_client(&self) -> reqwest::Client {
+        reqwest::ClientBuilder::new()
+            .connect_timeout(Duration::from_secs(10))
+            .user_agent(format!("{APP_NAME}/{APP_VERSION}"))
+            .build()
+            .unwrap()
+    }
+
+    /// Build a project hooks API URL.
+    /// Projects are identified using their URL-encoded full path.
+    fn hooks_url(config: &Config, owner: &str, repo: &str, suffix: &str) -> Url {
+        let mut api_url = config.gitlab_api_url().clone();
+        if !api_url.path().ends_with('/') {
+            api_url.set_path(&format!("{}/", api_url.path()));
+        }
+
+        let project_id = format!("{owner}/{repo}").replace('/', "%2F");
+        api_url
+            .join(&format!("projects/{project_id}/hooks{suffix}"))
+            .unwrap()
+    }
+
+    async fn register_webhook(
+        &self,
+        config: &Config,
+        owner: &str,
+        repo: &str,
+        url: &Url,
+    ) -> Result<Webhook, GitLabError> {
+        #[derive(Serialize)]
+        struct Data<'a> {
+            url: String,
+            push_events: bool,
+            tag_push_events: bool,
+            token: Option<&'a str>,
+        }
+
+        let data = Data {
+            url: url.to_string(),
+            push_events: true,
+            tag_push_events: true,
+            token: config.webhook_secret(),
+        };
+
+        let resp = self
+            .create_client()
+            .post(Self::hooks_url(config, owner, repo, ""))
+            .header(TOKEN_HEADER, &self.token)
+            .json(&data)
+            .send()
+            .await
+            .map_err(GitLabError::CouldNotRegisterWebhook)?;
+
+        let webhook: Webhook = resp
+            .error_for_status()
+            .map_err(GitLabError::BadStatusCode)?
+            .json()
+            .await
+            .map_err(GitLabError::MalformedResponse)?;
+
+        tracing::info!(
+            id = webhook.id,
+            message = "New webhook installed",
+            owner = owner,
+            repo = repo,
+            url = %url
+        );
+
+        Ok(webhook)
+    }
+
+    async fn list_webhooks(
+        &self,
+        config: &Config,
+        owner: &str,
+        repo: &str,
+    ) -> Result<Vec<Webhook>, GitLabError> {
+        let resp = self
+            .create_client()
+            .get(Self::hooks_url(config, owner, repo, ""))
+            .header(TOKEN_HEADER, &self.token)
+            .send()
+            .await
+            .map_err(GitLabError::CouldNotListWebhooks)?;
+
+        let data: Vec<Webhook> = resp
+            .error_for_status()
+            .map_err(GitLabError::BadStatusCode)?
+            .json()
+            .await
+            .map_err(GitLabError::MalformedResponse)?;
+        Ok(data)
+    }
+
+    async fn check_webhook_url(
+        &self,
+        config: &Config,
+        owner: &str,
+        repo: &str,
+        url: &Url,
+    ) -> Result<Option<u32>, GitLabError> {
+        Ok(self
+            .list_webhooks(config, owner, repo)
+            .await?
+            .into_iter()
+            .find(|w| Url::parse(&w.url).ok().as_ref() == Some(url))
+            .map(|w| w.id))
+    }
+
+    async fn unregister_webhook(
+        &self,
+        config: &Config,
+        owner: &str,
+        repo: &str,
+        id: u32,
+    ) -> Result<(), GitLabError> {
+        let resp = self
+            .create_client()
+            .delete(Self::hooks_url(config, owner, repo, &format!("/{id}")))
+            .header(TOKEN_HEADER, &self.token)
+            .send()
+            .await
+            .map_err(GitLabError::CouldNotUnregisterWebhook)?;
+
+        resp.error_for_status()
+            .map_err(GitLabError::BadStatusCode)?;
+
+        tracing::info!(
+            id = id,
+            message = "Webhook unregistered",
+            owner = owner,
+            repo = repo,
+        );
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+
+    use super::Client;
+    use url::Url;
+
+    async fn test_config() -> (MockServer, Config) {
+        let mut config = Config::empty();
+        let server = MockServer::start().await;
+        config.set_gitlab_api_url(Url::parse(&format!("{}/api/v4", server.uri())).unwrap());
+
+        (server, config)
+    }
+
+    fn create_url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_register_webhook() {
+        let (server, mut config) = test_config().await;
+        config.set_webhook_secret("secret");
+        let client = Client::new("token");
+
+        Mock::given(matchers::method("POST"))
+            .and(matchers::path("/api/v4/projects/owner%2Frepo/hooks"))
+            .and(matchers::header("PRIVATE-TOKEN", "token"))
+            .and(matchers::body_json(json!({
+                "url": "http://url/",
+                "push_events": true,
+                "tag_push_events": true,
+                "token": "secret"
+            })))
+            .respond_with(ResponseTemplate::new(201).set_body_json(json!({
+                "id": 1234u32,
+                "url": "http://url"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        assert_eq!(
+            client
+                .register_webhook(&config, "owner", "repo", &create_url("http://url"))
+                .await
+                .unwrap()
+                .id,
+            1234
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_webhook() {
+        let (server, config) = test_config().await;
+        let client = Client::new("token");
+
+        Mock::given(matchers::method("GET"))
+            .and(matchers::path("/api/v4/projects/owner%2Frepo/hooks"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+                {
+                    "id": 1234u32,
+                    "url": "http://url"
+                }
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        assert_eq!(
+            client
+                .list_webhooks(&config, "owner", "repo")
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unregister_webhook() {
+        let (server, config) = test_config().await;
+        let client = Client::new("token");
+
+        Mock::given(matchers::method("DELETE"))
+            .and(matchers::path("/api/v4/projects/owner%2Frepo/hooks/1234"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        client
+            .unregister_webhook(&config, "owner", "repo", 1234)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_try_register_webhook_present() {
+        let (server, config) = test_config().await;
+        let client = Client::new("token");
+
+        Mock::given(matchers::method("GET"))
+            .and(matchers::path(
+                "/api/v4/projects/group%2Fsubgroup%2Frepo/hooks",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+                {
+                    "id": 1234u32,
+                    "url": "http://url"
+                }
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        assert_eq!(
+            client
+                .try_register_webhook(&config, "group/subgroup", "repo", &create_url("http://url"))
+                .await
+                .unwrap(),
+            1234
+        );
+    }
+
+    #[tokio::test]
+    async fn test_try_unregister_webhook_present() {
+        let (server, config) = test_config().await;
+        let client = Client::new("token");
+
+        Mock::given(matchers::method("DELETE"))
+            .and(matchers::path("/api/v4/projects/owner%2Frepo/hooks/1234"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        Mock::given(matchers::method("GET"))
+            .and(matchers::path("/api/v4/projects/owner%2Frepo/hooks"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+                {
+                    "id": 1234,
+                    "url": "http://url"
+                }
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        client
+            .try_unregister_webhook(&config, "owner", "repo", &create_url("http://url"))
+            .await
+            .unwrap();
+    }
+}
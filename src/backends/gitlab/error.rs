@@ -0,0 +1,19 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum GitLabError {
+    #[error("error while registering webhook.")]
+    CouldNotRegisterWebhook(#[source] reqwest::Error),
+
+    #[error("error while listing webhooks.")]
+    CouldNotListWebhooks(#[source] reqwest::Error),
+
+    #[error("error while unregistering webhook.")]
+    CouldNotUnregisterWebhook(#[source] reqwest::Error),
+
+    #[error("error code received from GitLab.")]
+    BadStatusCode(#[source] reqwest::Error),
+
+    #[error("error while parsing GitLab response.")]
+    MalformedResponse(#[source] reqwest::Error),
+}
@@ -0,0 +1,112 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use axum::{
+    body::Body,
+    http::{Method, Request},
+    response::{IntoResponse, Response},
+};
+
+use tower::{Layer, Service};
+
+use crate::{crypto::WebhookVerifier, error::ErrorCode, http::buffer_request_body};
+
+const GITLAB_TOKEN_HEADER: &str = "X-Gitlab-Token";
+const GITLAB_USER_AGENT: &str = "GitLab/";
+
+pub struct VerifyGitLabTokenLayer {
+    verifier: WebhookVerifier,
+}
+
+impl VerifyGitLabTokenLayer {
+    pub fn new(verifier: WebhookVerifier) -> Self {
+        Self { verifier }
+    }
+}
+
+impl<S> Layer<S> for VerifyGitLabTokenLayer {
+    type Service = VerifyGitLabTokenMiddleware<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        VerifyGitLabTokenMiddleware::new(self.verifier.clone(), inner)
+    }
+}
+
+#[derive(Clone)]
+pub struct VerifyGitLabTokenMiddleware<S> {
+    verifier: WebhookVerifier,
+    inner: S,
+}
+
+impl<S> VerifyGitLabTokenMiddleware<S> {
+    pub fn new(verifier: WebhookVerifier, inner: S) -> Self {
+        Self { verifier, inner }
+    }
+}
+
+type BoxFuture<'a, Output> = Pin<Box<dyn Future<Output = Output> + Send + 'a>>;
+
+impl<S> Service<Request<Body>> for VerifyGitLabTokenMiddleware<S>
+where
+    S: Service<Request<Body>, Response = Response> + Send + 'static + Clone,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<Body>) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let verifier = self.verifier.clone();
+        let fut = async move {
+            if request.method() == Method::POST {
+                let headers = request.headers();
+
+                // Check for useragent
+                if headers
+                    .get("User-Agent")
+                    .and_then(|v| v.to_str().ok())
+                    .filter(|v| v.starts_with(GITLAB_USER_AGENT))
+                    .is_none()
+                {
+                    return Ok(ErrorCode::InvalidUserAgent.into_response());
+                }
+
+                let body = match buffer_request_body(&mut request, verifier.max_body_size()).await {
+                    Ok(b) => b,
+                    Err(e) => return Ok(e.into_response()),
+                };
+
+                // Check for token
+                if verifier.is_enabled() {
+                    let token = request
+                        .headers()
+                        .get(GITLAB_TOKEN_HEADER)
+                        .and_then(|v| v.to_str().ok())
+                        .unwrap_or_default();
+
+                    if let Err(e) =
+                        verifier.verify_token(verifier.repository_name(&body).as_deref(), token)
+                    {
+                        return Ok(e.into_response());
+                    }
+                }
+
+                *request.body_mut() = body.into();
+            }
+
+            let future = inner.call(request);
+            let response: Response = future.await?;
+            Ok(response)
+        };
+
+        Box::pin(fut)
+    }
+}
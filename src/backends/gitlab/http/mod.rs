@@ -0,0 +1,445 @@
+pub mod middleware;
+
+use axum::{
+    extract::Extension,
+    http::{header::HeaderName, HeaderMap, HeaderValue, StatusCode},
+};
+
+use crate::{
+    backends::{
+        cleanup_on_delete, clone_backend, header_to_str, is_null_commit, parse_body, parse_commit,
+        pretty_print_json, push_event_response, record_delivery, synchronize_on_push,
+        RepositoryField,
+    },
+    config::Config,
+    error::ErrorCode,
+    git::RefType,
+    history::Delivery,
+    jobs::JobRequest,
+    service::ServiceHandler,
+};
+
+use super::PushEvent;
+
+/// Payload field of the synchronized repository, also used to verify deliveries.
+pub const REPOSITORY_FIELD: RepositoryField = RepositoryField::PathWithNamespace;
+
+#[tracing::instrument(skip(config), fields(body_pretty = %pretty_print_json(&body)))]
+pub async fn webhook(
+    headers: HeaderMap,
+    body: String,
+    config: Extension<Config>,
+    services: Extension<ServiceHandler>,
+) -> Result<(StatusCode, HeaderMap, String), ErrorCode> {
+    let event = headers
+        .get("x-gitlab-event")
+        .ok_or(ErrorCode::MissingEventHeader)?
+        .to_str()
+        .map_err(|_| ErrorCode::MalformedEventHeader)?;
+
+    let mut delivery = Delivery::new(
+        "gitlab",
+        event,
+        header_to_str(&headers, "x-gitlab-event-uuid"),
+    );
+    let result = handle_event(&config, &services, &mut delivery, event, &body).await;
+    record_delivery(&services, delivery, &result);
+    result
+}
+
+async fn handle_event(
+    config: &Config,
+    services: &ServiceHandler,
+    delivery: &mut Delivery,
+    event: &str,
+    body: &str,
+) -> Result<(StatusCode, HeaderMap, String), ErrorCode> {
+    match event {
+        "Push Hook" | "Tag Push Hook" => {
+            handle_push_event(config, services, delivery, parse_body(body)?, body).await
+        }
+        other => Err(ErrorCode::UnsupportedEventHeader(other.to_string())),
+    }
+}
+
+#[tracing::instrument(skip(body))]
+async fn handle_push_event<'a>(
+    config: &Config,
+    services: &ServiceHandler,
+    delivery: &mut Delivery,
+    push_event: PushEvent<'a>,
+    body: &str,
+) -> Result<(StatusCode, HeaderMap, String), ErrorCode> {
+    let mut header_map = HeaderMap::new();
+    header_map.insert(
+        HeaderName::from_static("content-type"),
+        HeaderValue::from_static("application/json"),
+    );
+    let reference = RefType::try_from(push_event.reference)
+        .map_err(|e| ErrorCode::MalformedEventBodyField("ref".into(), e.to_string()))?;
+    let repository_path = REPOSITORY_FIELD.parse_path(body)?;
+
+    if is_null_commit(push_event.after) {
+        cleanup_on_delete(config, services, delivery, &repository_path, reference).await?;
+    } else {
+        synchronize_on_push(
+            config,
+            services,
+            delivery,
+            JobRequest::synchronization(
+                clone_backend(config, "gitlab", &repository_path)?,
+                repository_path.clone(),
+                reference,
+            )
+            .with_commit(Some(parse_commit("after", push_event.after)?)),
+        )
+        .await?;
+    }
+
+    let (status, body) = push_event_response(&push_event, delivery);
+    Ok((status, header_map, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request, Router};
+    use pretty_assertions::assert_eq;
+    use reqwest::StatusCode;
+    use serde_json::json;
+    use tower::ServiceExt;
+    use url::Url;
+
+    use crate::{
+        error::ErrorCode,
+        git::GitBackend,
+        http::build_http_router,
+        jobs::{JobKind, JobStatus},
+        testing::{
+            assert_response_is_error, create_test_config, create_test_services,
+            extract_fake_git_service, wait_for_response_job,
+        },
+    };
+
+    fn create_test_router() -> Router {
+        let config = create_test_config();
+        let services = create_test_services();
+        build_http_router(config, services)
+    }
+
+    fn push_event_json(reference: &str, web_url: &str) -> serde_json::Value {
+        json!({
+            "object_kind": "push",
+            "ref": reference,
+            "before": "95790bf891e76fee5e1747ab589903a6a1f80f22",
+            "after": "da1560886d4f094c3e6c9ef40349f7d38b5d27d7",
+            "checkout_sha": "da1560886d4f094c3e6c9ef40349f7d38b5d27d7",
+            "user_name": "sample",
+            "project": {
+                "id": 15,
+                "name": "things",
+                "path_with_namespace": "Srynetix/things",
+                "web_url": web_url
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_verify_gitlab_token_middleware_invalid_user_agent() {
+        let app = create_test_router();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/webhook/gitlab")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let status = response.status();
+        assert_response_is_error(response, ErrorCode::InvalidUserAgent).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_verify_gitlab_token_middleware_invalid_token() {
+        let mut config = create_test_config();
+        // This configuration will enable token verification
+        config.set_webhook_secret("secret");
+        let services = create_test_services();
+        let app = build_http_router(config, services);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("User-Agent", "GitLab/15.0.0")
+                    .header("X-Gitlab-Token", "wrong")
+                    .uri("/webhook/gitlab")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let status = response.status();
+        assert_response_is_error(response, ErrorCode::InvalidToken).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_repository_webhook_secret() {
+        let mut config = create_test_config();
+        config.set_webhook_secret("secret");
+        config
+            .set_backend_webhook_secret("gitlab", "gitlab-secret")
+            .unwrap();
+        config
+            .repository_mut("Srynetix/things")
+            .unwrap()
+            .set_webhook_secret("repo-secret");
+        config.repository_mut("Srynetix/other").unwrap();
+        config.set_strict_webhook_secrets(true);
+        let services = create_test_services();
+        let app = build_http_router(config, services.clone());
+
+        let push_request = |project: &str, token: &str| {
+            let mut json_data = push_event_json(
+                "refs/heads/main",
+                &format!("https://gitlab.example.com/{}", project),
+            );
+            json_data["project"]["path_with_namespace"] = project.into();
+
+            Request::builder()
+                .method("POST")
+                .header("User-Agent", "GitLab/15.0.0")
+                .header("X-Gitlab-Event", "Push Hook")
+                .header("X-Gitlab-Token", token)
+                .uri("/webhook/gitlab")
+                .body(Body::from(json_data.to_string()))
+                .unwrap()
+        };
+
+        // Repository secrets take precedence
+        for token in ["secret", "gitlab-secret"] {
+            let response = app
+                .clone()
+                .oneshot(push_request("Srynetix/things", token))
+                .await
+                .unwrap();
+            assert_response_is_error(response, ErrorCode::InvalidToken).await;
+        }
+
+        let response = app
+            .clone()
+            .oneshot(push_request("Srynetix/things", "repo-secret"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        wait_for_response_job(&services, response).await;
+
+        // Then the backend secret
+        let response = app
+            .clone()
+            .oneshot(push_request("Srynetix/other", "gitlab-secret"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        wait_for_response_job(&services, response).await;
+
+        // Strict mode rejects repositories missing from the configuration
+        let response = app
+            .oneshot(push_request("Srynetix/unknown", "gitlab-secret"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_response_is_error(
+            response,
+            ErrorCode::UnconfiguredRepository("Srynetix/unknown".into()),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_token_uses_synchronized_repository() {
+        let mut config = create_test_config();
+        config
+            .repository_mut("Srynetix/things")
+            .unwrap()
+            .set_webhook_secret("things-secret");
+        config
+            .repository_mut("Srynetix/other")
+            .unwrap()
+            .set_webhook_secret("other-secret");
+        let services = create_test_services();
+        let app = build_http_router(config, services.clone());
+
+        // The payload names another repository than the one being synchronized
+        let mut json_data = push_event_json(
+            "refs/heads/main",
+            "https://gitlab.example.com/Srynetix/other",
+        );
+        json_data["project"]["path_with_namespace"] = "Srynetix/other".into();
+        json_data["repository"] = json!({ "full_name": "Srynetix/things" });
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("User-Agent", "GitLab/15.0.0")
+                    .header("X-Gitlab-Event", "Push Hook")
+                    .header("X-Gitlab-Token", "things-secret")
+                    .uri("/webhook/gitlab")
+                    .body(Body::from(json_data.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let status = response.status();
+        assert_response_is_error(response, ErrorCode::InvalidToken).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let fake_git_service = extract_fake_git_service(&services);
+        assert!(!fake_git_service.clone_repository.called());
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_event_header() {
+        let app = create_test_router();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("User-Agent", "GitLab/15.0.0")
+                    .header("X-Gitlab-Event", "Issue Hook")
+                    .uri("/webhook/gitlab")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let status = response.status();
+        assert_response_is_error(
+            response,
+            ErrorCode::UnsupportedEventHeader("Issue Hook".into()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_push_event_clone() {
+        let mut config = create_test_config();
+        config.set_webhook_secret("secret");
+        config.set_gitlab_api_url(Url::parse("https://gitlab.example.com/api/v4/").unwrap());
+        let services = create_test_services();
+        let app = build_http_router(config, services.clone());
+
+        // The clone URL never comes from the payload
+        let json_data = push_event_json(
+            "refs/branches/sample",
+            "https://attacker.example.org/Srynetix/things",
+        );
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("User-Agent", "GitLab/15.0.0")
+                    .header("X-Gitlab-Event", "Push Hook")
+                    .header("X-Gitlab-Token", "secret")
+                    .uri("/webhook/gitlab")
+                    .body(json_data.to_string().into())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        wait_for_response_job(&services, response).await;
+
+        let fake_git_service = extract_fake_git_service(&services);
+        let calls = fake_git_service.clone_repository.calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].1.as_deref(), Some("sample"));
+        assert_eq!(calls[0].2, "https://gitlab.example.com/Srynetix/things");
+    }
+
+    #[tokio::test]
+    async fn test_tag_push_event_clone() {
+        let mut config = create_test_config();
+        config
+            .repository_mut("Srynetix/things")
+            .unwrap()
+            .set_backend(GitBackend::GitLab);
+        let services = create_test_services();
+        let app = build_http_router(config, services.clone());
+
+        let json_data = push_event_json("refs/tags/v1.0.0", "https://gitlab.com/Srynetix/things");
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("User-Agent", "GitLab/15.0.0")
+                    .header("X-Gitlab-Event", "Tag Push Hook")
+                    .uri("/webhook/gitlab")
+                    .body(json_data.to_string().into())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        wait_for_response_job(&services, response).await;
+
+        let fake_git_service = extract_fake_git_service(&services);
+        let calls = fake_git_service.clone_repository.calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].1.as_deref(), Some("v1.0.0"));
+        assert_eq!(calls[0].2, "https://gitlab.com/Srynetix/things");
+    }
+
+    #[tokio::test]
+    async fn test_push_event_deleted_branch_cleanup() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(tempdir.path().join("previews/feature-x-217d2bf5")).unwrap();
+
+        let mut config = create_test_config();
+        config
+            .repository_mut("Srynetix/things")
+            .unwrap()
+            .set_target_dir(tempdir.path().join("previews/{branch}"));
+        let services = create_test_services();
+        let app = build_http_router(config, services.clone());
+
+        let mut json_data =
+            push_event_json("refs/heads/feature/x", "https://gitlab.com/Srynetix/things");
+        json_data["after"] = "0000000000000000000000000000000000000000".into();
+        json_data["checkout_sha"] = serde_json::Value::Null;
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .header("User-Agent", "GitLab/15.0.0")
+                    .header("X-Gitlab-Event", "Push Hook")
+                    .uri("/webhook/gitlab")
+                    .body(json_data.to_string().into())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let job = wait_for_response_job(&services, response).await;
+        assert_eq!(job.kind, JobKind::Cleanup);
+        assert_eq!(job.status, JobStatus::Succeeded);
+        assert!(!tempdir.path().join("previews/feature-x-217d2bf5").exists());
+        assert!(!extract_fake_git_service(&services)
+            .clone_repository
+            .called());
+    }
+}
@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+
+/// Push event, also used for tag push events.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PushEvent<'a> {
+    pub object_kind: &'a str,
+    #[serde(rename = "ref")]
+    pub reference: &'a str,
+    pub before: &'a str,
+    pub after: &'a str,
+    pub checkout_sha: Option<&'a str>,
+    pub user_name: &'a str,
+    pub project: Project<'a>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Project<'a> {
+    pub id: u64,
+    pub name: &'a str,
+    pub path_with_namespace: &'a str,
+    pub web_url: &'a str,
+}
@@ -0,0 +1,15 @@
+use super::models::PushEvent;
+
+#[test]
+fn test_parse_push_event() {
+    let event_str = include_str!("./push_sample.json");
+    let event: PushEvent = serde_json::from_str(event_str).expect("should deserialize");
+    assert_eq!(event.project.path_with_namespace, "mike/diaspora");
+    assert_eq!(event.reference, "refs/heads/master");
+}
+
+#[test]
+fn test_parse_tag_push_event() {
+    let event_str = include_str!("./tag_push_sample.json");
+    let _: PushEvent = serde_json::from_str(event_str).expect("should deserialize");
+}
@@ -1 +1,389 @@
+pub mod bitbucket;
+pub mod gitea;
 pub mod github;
+pub mod gitlab;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::http::{HeaderMap, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    config::Config,
+    error::ErrorCode,
+    git::{GitBackend, RefType, RepositoryPath},
+    history::Delivery,
+    jobs::{JobId, JobRequest},
+    service::ServiceHandler,
+};
+
+/// Commit hash sent as the new revision of a deleted branch or tag.
+const NULL_COMMIT: &str = "0000000000000000000000000000000000000000";
+
+/// Check if a push event revision marks a deleted reference.
+pub(crate) fn is_null_commit(commit: &str) -> bool {
+    commit == NULL_COMMIT
+}
+
+/// Check a push event revision, before it is passed to Git.
+pub(crate) fn parse_commit<'a>(field: &str, commit: &'a str) -> Result<&'a str, ErrorCode> {
+    if RefType::is_full_sha(commit) {
+        Ok(commit)
+    } else {
+        Err(ErrorCode::MalformedEventBodyField(
+            field.into(),
+            format!("Malformed commit hash: {}", commit),
+        ))
+    }
+}
+
+pub(crate) fn pretty_print_json(s: &str) -> String {
+    serde_json::from_str::<Value>(s)
+        .and_then(|n| serde_json::to_string_pretty(&n))
+        .unwrap_or_default()
+}
+
+pub(crate) fn parse_body<'a, T: Deserialize<'a>>(body: &'a str) -> Result<T, ErrorCode> {
+    serde_json::from_str(body).map_err(ErrorCode::MalformedEventBody)
+}
+
+/// Payload field naming the repository of a delivery.
+/// It is read by the verification middlewares, before the body is trusted, to find the secrets
+/// of the repository, and by the handlers to know what to synchronize: both need to read the
+/// same field, so a delivery verified for a repository can never deploy another one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepositoryField {
+    /// `repository.full_name`: GitHub, Gitea and Bitbucket Cloud
+    FullName,
+    /// `project.path_with_namespace`: GitLab
+    PathWithNamespace,
+    /// `repository.project.key` and `repository.slug`: Bitbucket Server
+    ProjectKeyAndSlug,
+}
+
+impl RepositoryField {
+    /// Get the repository full name of a webhook payload, before verifying it.
+    pub fn parse(self, body: &[u8]) -> Option<String> {
+        let payload: Value = serde_json::from_slice(body).ok()?;
+        let name = match self {
+            Self::FullName => payload["repository"]["full_name"].as_str()?.to_owned(),
+            Self::PathWithNamespace => payload["project"]["path_with_namespace"]
+                .as_str()?
+                .to_owned(),
+            Self::ProjectKeyAndSlug => format!(
+                "{}/{}",
+                payload["repository"]["project"]["key"].as_str()?,
+                payload["repository"]["slug"].as_str()?
+            ),
+        };
+
+        Some(name)
+    }
+
+    /// Get the repository path of a webhook payload, to synchronize it.
+    pub(crate) fn parse_path(self, body: &str) -> Result<RepositoryPath, ErrorCode> {
+        let name = self.parse(body.as_bytes()).ok_or_else(|| {
+            ErrorCode::MalformedEventBodyField(self.to_string(), "Missing repository".into())
+        })?;
+
+        RepositoryPath::new(&name)
+            .map_err(|e| ErrorCode::MalformedEventBodyField(self.to_string(), e.to_string()))
+    }
+}
+
+impl Default for RepositoryField {
+    fn default() -> Self {
+        Self::FullName
+    }
+}
+
+impl std::fmt::Display for RepositoryField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::FullName => f.write_str("repository.full_name"),
+            Self::PathWithNamespace => f.write_str("project.path_with_namespace"),
+            Self::ProjectKeyAndSlug => f.write_str("repository"),
+        }
+    }
+}
+
+pub(crate) fn header_to_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+/// Reject a delivery whose date, as sent by the backend, is too far from now.
+/// Dates are expected in the `2017-09-19T09:45:32+1000` format.
+pub(crate) fn check_delivery_date(config: &Config, date: &str) -> Result<(), ErrorCode> {
+    let max_age = match config.max_delivery_age() {
+        Some(a) => a,
+        None => return Ok(()),
+    };
+
+    let timestamp = parse_timestamp(date).ok_or_else(|| {
+        ErrorCode::MalformedEventBodyField("date".into(), format!("Malformed date: '{}'", date))
+    })?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+
+    if (now - timestamp).unsigned_abs() > max_age {
+        return Err(ErrorCode::ExpiredDelivery(date.into()));
+    }
+
+    Ok(())
+}
+
+/// Parse a `YYYY-MM-DDTHH:MM:SS±HHMM` date (or `±HH:MM`, or `Z`) to a Unix timestamp.
+fn parse_timestamp(date: &str) -> Option<i64> {
+    let (date, time) = date.split_once('T')?;
+    let mut date_parts = date.splitn(3, '-').map(|p| p.parse::<i64>().ok());
+    let (year, month, day) = (
+        date_parts.next()??,
+        date_parts.next()??,
+        date_parts.next()??,
+    );
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let (time, offset) = match time.find(['+', '-', 'Z']) {
+        Some(i) => time.split_at(i),
+        None => return None,
+    };
+    // Ignore fractional seconds
+    let time = time.split('.').next()?;
+    let mut time_parts = time.splitn(3, ':').map(|p| p.parse::<i64>().ok());
+    let (hours, minutes, seconds) = (
+        time_parts.next()??,
+        time_parts.next()??,
+        time_parts.next()??,
+    );
+    let offset = match offset {
+        "Z" => 0,
+        o => {
+            let sign = if o.starts_with('-') { -1 } else { 1 };
+            let digits = o[1..].replace(':', "");
+            if digits.len() != 4 {
+                return None;
+            }
+            let (h, m) = digits.split_at(2);
+            sign * (h.parse::<i64>().ok()? * 3600 + m.parse::<i64>().ok()? * 60)
+        }
+    };
+
+    // Days since the Unix epoch, from the proleptic Gregorian calendar
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    Some(days * 86_400 + hours * 3600 + minutes * 60 + seconds - offset)
+}
+
+/// Get the Git backend to clone a repository from, following a webhook event.
+/// The backend comes from the repository configuration, else from the webhook backend, but never
+/// from the payload: a payload URL would send the clone, and the repository credentials, to any host.
+/// Self-hosted only backends (Gitea, Bitbucket Server) need a configured backend.
+pub(crate) fn clone_backend(
+    config: &Config,
+    webhook_backend: &str,
+    repository_path: &RepositoryPath,
+) -> Result<GitBackend, ErrorCode> {
+    configured_backend(config, repository_path).or_else(|e| match webhook_backend {
+        "github" => Ok(GitBackend::GitHub),
+        "gitlab" => Ok(config.gitlab_backend()),
+        "bitbucket" => Ok(GitBackend::Bitbucket),
+        _ => Err(e),
+    })
+}
+
+/// Get the Git backend configured for a repository, when no webhook event tells where it is hosted.
+pub(crate) fn configured_backend(
+    config: &Config,
+    repository_path: &RepositoryPath,
+) -> Result<GitBackend, ErrorCode> {
+    config
+        .repository(&repository_path.full_name())
+        .and_then(|r| r.backend())
+        .cloned()
+        .ok_or_else(|| ErrorCode::UnconfiguredBackend(repository_path.full_name()))
+}
+
+/// Record a delivery in history, with the error it led to, if any.
+pub(crate) fn record_delivery<T>(
+    services: &ServiceHandler,
+    mut delivery: Delivery,
+    result: &Result<T, ErrorCode>,
+) {
+    if let Err(e) = result {
+        delivery.error = Some(e.to_string());
+    }
+
+    services.history().record_delivery(&delivery);
+}
+
+/// Queue a repository synchronization following a push event, whatever the Git hosting backend is.
+/// The delivery is updated with the synchronization target and the queued job.
+/// Returns `None` when the synchronization is skipped, the reason being added to the delivery.
+#[tracing::instrument]
+pub(crate) async fn synchronize_on_push(
+    config: &Config,
+    services: &ServiceHandler,
+    delivery: &mut Delivery,
+    request: JobRequest,
+) -> Result<Option<JobId>, ErrorCode> {
+    delivery.set_target(
+        &request.repository.full_name(),
+        &request.reference.to_string(),
+        request.commit.as_deref(),
+    );
+
+    Ok(enqueue_job(config, services, delivery, request))
+}
+
+/// Queue the removal of a reference checkout following a branch or tag deletion.
+/// Only repositories using per-branch target directories are cleaned up, so a shared checkout
+/// is never removed.
+/// Returns `None` when the cleanup is skipped, the reason being added to the delivery.
+#[tracing::instrument]
+pub(crate) async fn cleanup_on_delete(
+    config: &Config,
+    services: &ServiceHandler,
+    delivery: &mut Delivery,
+    repository_path: &RepositoryPath,
+    reference: RefType,
+) -> Result<Option<JobId>, ErrorCode> {
+    delivery.set_target(&repository_path.full_name(), &reference.to_string(), None);
+
+    let per_branch = config
+        .repository(&repository_path.full_name())
+        .map(|r| r.has_per_branch_target_dir())
+        .unwrap_or(false);
+    if !per_branch {
+        let reason = format!(
+            "Repository '{}' does not use per-branch target directories, nothing to clean up",
+            repository_path.full_name()
+        );
+        tracing::info!(message = "Skipping cleanup", reason = %reason);
+        delivery.skipped.push(reason);
+        return Ok(None);
+    }
+
+    Ok(enqueue_job(
+        config,
+        services,
+        delivery,
+        JobRequest::cleanup(
+            config.repo_backend(&repository_path.full_name()),
+            repository_path.clone(),
+            reference,
+        ),
+    ))
+}
+
+/// Queue a job if its reference is allowed by the repository filters.
+fn enqueue_job(
+    config: &Config,
+    services: &ServiceHandler,
+    delivery: &mut Delivery,
+    request: JobRequest,
+) -> Option<JobId> {
+    if let Some(repository) = config.repository(&request.repository.full_name()) {
+        if !repository.is_reference_allowed(&request.reference) {
+            let reason = format!(
+                "Reference '{}' is not allowed for repository '{}'",
+                request.reference.to_string(),
+                request.repository.full_name()
+            );
+            tracing::info!(message = "Skipping job", reason = %reason);
+            delivery.skipped.push(reason);
+            return None;
+        }
+    }
+
+    let job_id = services.jobs().enqueue(config, request);
+    delivery.job_ids.push(job_id.clone());
+
+    Some(job_id)
+}
+
+/// Build the response to a push event: `202 Accepted` with the queued job identifiers,
+/// a "skipped" status with the reasons when every synchronization was filtered out,
+/// or the event itself when no synchronization was needed.
+pub(crate) fn push_event_response<T: Serialize>(
+    event: &T,
+    delivery: &Delivery,
+) -> (StatusCode, String) {
+    match delivery.job_ids.as_slice() {
+        [] if !delivery.skipped.is_empty() => (
+            StatusCode::OK,
+            json!({ "status": "skipped", "reasons": delivery.skipped }).to_string(),
+        ),
+        [] => (StatusCode::OK, serde_json::to_string(event).unwrap()),
+        [job_id] => (
+            StatusCode::ACCEPTED,
+            json!({ "job_id": job_id }).to_string(),
+        ),
+        job_ids => (
+            StatusCode::ACCEPTED,
+            json!({ "job_ids": job_ids }).to_string(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{parse_timestamp, RepositoryField};
+
+    #[test]
+    fn test_repository_field_parse() {
+        let body = br#"{
+            "repository": {"full_name": "org/repo", "slug": "slug", "project": {"key": "PROJ"}},
+            "project": {"path_with_namespace": "group/repo"}
+        }"#;
+
+        assert_eq!(
+            RepositoryField::FullName.parse(body).as_deref(),
+            Some("org/repo")
+        );
+        assert_eq!(
+            RepositoryField::PathWithNamespace.parse(body).as_deref(),
+            Some("group/repo")
+        );
+        assert_eq!(
+            RepositoryField::ProjectKeyAndSlug.parse(body).as_deref(),
+            Some("PROJ/slug")
+        );
+        assert_eq!(
+            RepositoryField::PathWithNamespace
+                .parse(br#"{"repository": {"full_name": "org/repo"}}"#),
+            None
+        );
+        assert_eq!(RepositoryField::FullName.parse(b"nope"), None);
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(
+            parse_timestamp("2017-09-19T09:45:32+1000"),
+            Some(1_505_778_332)
+        );
+        assert_eq!(
+            parse_timestamp("2017-09-19T09:45:32.123+10:00"),
+            Some(1_505_778_332)
+        );
+        assert_eq!(
+            parse_timestamp("2024-02-29T23:00:00-0100"),
+            Some(1_709_251_200)
+        );
+        assert_eq!(parse_timestamp("2017-09-19 09:45:32"), None);
+        assert_eq!(parse_timestamp("2017-13-19T09:45:32Z"), None);
+    }
+}
@@ -3,12 +3,16 @@ use std::{net::SocketAddr, path::PathBuf};
 use clap::Parser;
 use url::Url;
 
-use crate::git::{GitBackend, RefType, RepositoryPath};
+use crate::git::{GitBackend, GitImplementation, RefType, RepositoryPath};
 
 /// Execute actions on Git hosting webhooks
 #[derive(Parser, Debug)]
 #[clap(author, version, about)]
 pub struct Args {
+    /// Configuration file (TOML)
+    #[clap(long)]
+    pub config: Option<PathBuf>,
+
     /// Telemetry URL (disabled as default)
     #[clap(long)]
     pub telemetry_url: Option<Url>,
@@ -17,18 +21,38 @@ pub struct Args {
     #[clap(long)]
     pub github_api_url: Option<Url>,
 
+    /// GitLab API URL (https://gitlab.com/api/v4 as default)
+    #[clap(long)]
+    pub gitlab_api_url: Option<Url>,
+
     /// Working directory (current directory as default)
     #[clap(long)]
     pub working_dir: Option<PathBuf>,
 
+    /// Directory where checkouts of deleted branches are moved to (removed as default)
+    #[clap(long)]
+    pub trash_dir: Option<PathBuf>,
+
+    /// Git implementation: executable or native (executable as default)
+    #[clap(long)]
+    pub git_implementation: Option<GitImplementation>,
+
     /// Webhook secret (disabled as default)
     #[clap(long)]
     pub webhook_secret: Option<String>,
 
+    /// API bearer token, needed to trigger synchronizations through the API (disabled as default)
+    #[clap(long)]
+    pub api_token: Option<String>,
+
     /// Repository mapping configuration
     #[clap(long)]
     pub repo_mapping: Option<String>,
 
+    /// Repository actions configuration (JSON, executed after each synchronization)
+    #[clap(long)]
+    pub repo_actions: Option<String>,
+
     /// Command
     #[clap(subcommand)]
     pub command: SubCommand,
@@ -44,6 +68,8 @@ pub enum SubCommand {
     Uninstall(InstallCommand),
     /// Synchronize
     Synchronize(SynchronizeCommand),
+    /// Roll back to a previous release
+    Rollback(RollbackCommand),
 }
 
 #[derive(Parser, Debug)]
@@ -51,6 +77,10 @@ pub struct ServeCommand {
     /// Bind IP
     #[clap(long)]
     pub bind_ip: Option<SocketAddr>,
+
+    /// Maximum number of synchronization jobs running at once
+    #[clap(long)]
+    pub workers: Option<usize>,
 }
 
 #[derive(Parser, Debug)]
@@ -93,15 +123,30 @@ pub struct UninstallCommand {
 
 #[derive(Parser, Debug)]
 pub struct SynchronizeCommand {
-    /// Git hosting backend
-    #[clap(long, default_value = "github")]
-    pub backend: GitBackend,
+    /// Git hosting backend (repository configuration or github as default)
+    #[clap(long)]
+    pub backend: Option<GitBackend>,
+
+    /// Repository full name
+    #[clap(long)]
+    pub repository: RepositoryPath,
 
+    /// Git reference (e.g. refs/heads/my-branch, refs/tags/my-tag or a full commit SHA)
+    #[clap(name = "ref", long)]
+    pub reference: RefType,
+}
+
+#[derive(Parser, Debug)]
+pub struct RollbackCommand {
     /// Repository full name
     #[clap(long)]
     pub repository: RepositoryPath,
 
-    /// Git reference name (e.g. refs/branches/my-branch or refs/tags/my-tag)
+    /// Git reference, locating per-branch target directories
     #[clap(name = "ref", long)]
     pub reference: RefType,
+
+    /// Release name (the release before the current one as default)
+    #[clap(long)]
+    pub release: Option<String>,
 }
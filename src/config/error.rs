@@ -0,0 +1,41 @@
+use std::path::PathBuf;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ConfigError {
+    #[error("Missing working directory: '{0}'. Make sure it exists on disk.")]
+    MissingWorkingDirectory(PathBuf),
+    #[error("Could not read configuration file '{0}': {1}")]
+    CouldNotReadConfigFile(PathBuf, String),
+    #[error("Malformed configuration file '{0}': {1}")]
+    MalformedConfigFile(PathBuf, String),
+    #[error("Malformed URL for '{0}': '{1}'")]
+    MalformedUrl(String, String),
+    #[error("Malformed repository mapping entry: '{0}'. Expected syntax is 'org/repo=./folder'.")]
+    MalformedRepoMapping(String),
+    #[error("Malformed repository actions configuration: {0}")]
+    MalformedRepoActions(String),
+    #[error("Malformed repository name: '{0}'. Expected syntax is 'org/repo'.")]
+    MalformedRepositoryName(String),
+    #[error("Unsupported Git implementation '{0}'. Expected 'executable' or 'native'.")]
+    UnsupportedGitImplementation(String),
+    #[error("Unsupported backend '{1}' for repository '{0}'")]
+    UnsupportedBackend(String, String),
+    #[error("Unsupported synchronization strategy '{1}' for repository '{0}'. Expected 'pull', 'reset' or 'reset-clean'.")]
+    UnsupportedSyncStrategy(String, String),
+    #[error("Unsupported Git protocol '{1}' for repository '{0}'. Expected 'https' or 'ssh'.")]
+    UnsupportedProtocol(String, String),
+    #[error("Conflicting credentials for repository '{0}': {1}")]
+    ConflictingCredentials(String, String),
+    #[error("Empty action command for repository '{0}'")]
+    EmptyActionCommand(String),
+    #[error("Malformed branch or tag pattern for repository '{0}': {1}")]
+    MalformedRefPattern(String, String),
+    #[error("Malformed integer for '{0}': '{1}'")]
+    MalformedInteger(String, String),
+    #[error("Unknown webhook backend '{0}'. Expected 'github', 'gitlab', 'gitea', 'bitbucket' or 'bitbucket-server'.")]
+    UnknownWebhookBackend(String),
+    #[error("Malformed IP range for '{0}': '{1}'. Expected an IP address or a CIDR range, like '10.0.0.0/8'.")]
+    MalformedIpRange(String, String),
+    #[error("Malformed boolean for '{0}': '{1}'. Expected 'true' or 'false'.")]
+    MalformedBoolean(String, String),
+}
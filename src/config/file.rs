@@ -0,0 +1,136 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    actions::Action,
+    git::{CloneOptions, ReleaseOptions},
+};
+
+use super::ConfigError;
+
+/// TOML configuration file model.
+///
+/// ```toml
+/// working_dir = "./_work"
+/// webhook_secret = "my-secret"
+/// previous_webhook_secrets = ["my-old-secret"]
+/// max_body_size = 26214400
+/// legacy_github_signature = false
+/// backend_webhook_secrets = { gitlab = "my-gitlab-secret" }
+/// strict_webhook_secrets = false
+/// history_retention = 1000
+/// replay_cache_size = 1000
+/// persist_replay_cache = true
+/// max_delivery_age = 300
+/// allowed_ips = ["10.0.0.0/8"]
+/// allow_github_hook_ips = true
+/// trusted_proxies = ["127.0.0.1"]
+/// git_implementation = "executable"
+///
+/// [repositories."org/repo-name"]
+/// target_dir = "./site"
+/// backend = "github"
+/// sync_strategy = "reset"
+/// clone = { depth = 1, filter = "blob:none", single_branch = true, sparse_paths = ["site"] }
+/// submodules = true
+/// lfs = true
+/// credentials = { token = "ghp_xxx" }
+/// releases = { keep = 5 }
+/// webhook_secret = "my-repository-secret"
+/// branches = ["main", "release/*"]
+/// ignored_branches = ["release/*-rc*"]
+/// ignored_tags = ["*"]
+///
+/// [[repositories."org/repo-name".actions]]
+/// command = "npm ci && npm run build"
+/// timeout = 300
+///
+/// [[repositories."org/repo-name".teardown_actions]]
+/// command = "docker compose down"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ConfigFile {
+    pub telemetry_url: Option<String>,
+    pub github_api_url: Option<String>,
+    pub gitlab_api_url: Option<String>,
+    pub webhook_secret: Option<String>,
+    pub previous_webhook_secrets: Option<Vec<String>>,
+    pub max_body_size: Option<usize>,
+    pub legacy_github_signature: Option<bool>,
+    #[serde(default)]
+    pub backend_webhook_secrets: HashMap<String, String>,
+    pub strict_webhook_secrets: Option<bool>,
+    pub api_token: Option<String>,
+    pub working_dir: Option<PathBuf>,
+    pub trash_dir: Option<PathBuf>,
+    pub git_implementation: Option<String>,
+    pub history_retention: Option<usize>,
+    pub replay_cache_size: Option<usize>,
+    pub persist_replay_cache: Option<bool>,
+    pub max_delivery_age: Option<u64>,
+    pub allowed_ips: Option<Vec<String>>,
+    pub allow_github_hook_ips: Option<bool>,
+    pub trusted_proxies: Option<Vec<String>>,
+    #[serde(default)]
+    pub repositories: HashMap<String, RepositoryFile>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RepositoryFile {
+    pub target_dir: Option<PathBuf>,
+    pub backend: Option<String>,
+    pub sync_strategy: Option<String>,
+    #[serde(default)]
+    pub clone: CloneOptions,
+    #[serde(default)]
+    pub submodules: bool,
+    #[serde(default)]
+    pub lfs: bool,
+    #[serde(default)]
+    pub credentials: CredentialsFile,
+    pub releases: Option<ReleaseOptions>,
+    #[serde(default)]
+    pub branches: Vec<String>,
+    #[serde(default)]
+    pub ignored_branches: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub ignored_tags: Vec<String>,
+    #[serde(default)]
+    pub actions: Vec<Action>,
+    #[serde(default)]
+    pub teardown_actions: Vec<Action>,
+    pub webhook_secret: Option<String>,
+}
+
+/// Credentials of a private repository: an HTTPS token or an SSH key.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct CredentialsFile {
+    pub username: Option<String>,
+    pub token: Option<String>,
+    pub ssh_key: Option<PathBuf>,
+    /// Clone URL protocol, `https` or `ssh` (`ssh` when an SSH key is set).
+    pub protocol: Option<String>,
+}
+
+impl ConfigFile {
+    pub fn from_path(path: &Path) -> Result<Self, ConfigError> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| ConfigError::CouldNotReadConfigFile(path.to_owned(), e.to_string()))?;
+
+        Self::parse(path, &content)
+    }
+
+    pub fn parse(path: &Path, content: &str) -> Result<Self, ConfigError> {
+        toml::from_str(content)
+            .map_err(|e| ConfigError::MalformedConfigFile(path.to_owned(), e.to_string()))
+    }
+}
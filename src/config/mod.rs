@@ -0,0 +1,1193 @@
+mod error;
+mod file;
+mod ref_filter;
+mod repository;
+
+use ipnet::IpNet;
+use once_cell::sync::Lazy;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use url::Url;
+
+use crate::{
+    actions::Action,
+    git::{
+        CloneOptions, GitBackend, GitCredentials, GitImplementation, GitProtocol, RepositoryPath,
+        SyncStrategy,
+    },
+};
+
+use self::file::{ConfigFile, CredentialsFile};
+
+pub use self::error::ConfigError;
+pub use self::ref_filter::RefFilter;
+pub use self::repository::RepositoryConfig;
+
+static DEFAULT_URL: Lazy<Url> = Lazy::new(|| Url::parse("http://localhost").unwrap());
+static DEFAULT_GITHUB_API_URL: Lazy<Url> =
+    Lazy::new(|| Url::parse("https://api.github.com").unwrap());
+static DEFAULT_GITLAB_API_URL: Lazy<Url> =
+    Lazy::new(|| Url::parse("https://gitlab.com/api/v4/").unwrap());
+static DEFAULT_BIND_IP: Lazy<SocketAddr> =
+    Lazy::new(|| SocketAddr::from_str("0.0.0.0:3000").unwrap());
+const DEFAULT_WORKERS: usize = 4;
+const DEFAULT_HISTORY_RETENTION: usize = 1000;
+const DEFAULT_REPLAY_CACHE_SIZE: usize = 1000;
+// GitHub caps payloads at 25 MB
+const DEFAULT_MAX_BODY_SIZE: usize = 25 * 1024 * 1024;
+/// Webhook routes, as `/webhook/<backend>`.
+pub const WEBHOOK_BACKENDS: [&str; 5] =
+    ["github", "gitlab", "gitea", "bitbucket", "bitbucket-server"];
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    telemetry_url: Option<Url>,
+    github_api_url: Url,
+    gitlab_api_url: Url,
+    webhook_secret: Option<String>,
+    previous_webhook_secrets: Vec<String>,
+    max_body_size: usize,
+    legacy_github_signature: bool,
+    backend_webhook_secrets: HashMap<String, String>,
+    strict_webhook_secrets: bool,
+    api_token: Option<String>,
+    working_dir: Option<PathBuf>,
+    trash_dir: Option<PathBuf>,
+    git_implementation: GitImplementation,
+    history_retention: usize,
+    replay_cache_size: usize,
+    persist_replay_cache: bool,
+    max_delivery_age: Option<u64>,
+    allowed_ips: Vec<IpNet>,
+    allow_github_hook_ips: bool,
+    trusted_proxies: Vec<IpNet>,
+    repositories: HashMap<String, RepositoryConfig>,
+}
+
+impl Config {
+    /// Load configuration, using this precedence order (highest first):
+    /// environment variables, configuration file, default values.
+    ///
+    /// Command-line arguments are applied on top of it by the caller.
+    pub fn load(config_path: Option<&Path>) -> Result<Self, ConfigError> {
+        let mut config = match config_path {
+            Some(path) => Self::from_file(path)?,
+            None => Self::defaults(),
+        };
+
+        config.apply_env()?;
+        Ok(config)
+    }
+
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::load(None)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let mut config = Self::defaults();
+        config.apply_file(ConfigFile::from_path(path)?)?;
+        Ok(config)
+    }
+
+    pub fn empty() -> Self {
+        Self {
+            telemetry_url: None,
+            github_api_url: DEFAULT_URL.clone(),
+            gitlab_api_url: DEFAULT_URL.clone(),
+            webhook_secret: None,
+            previous_webhook_secrets: vec![],
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            legacy_github_signature: false,
+            backend_webhook_secrets: HashMap::new(),
+            strict_webhook_secrets: false,
+            api_token: None,
+            working_dir: None,
+            trash_dir: None,
+            git_implementation: GitImplementation::default(),
+            history_retention: DEFAULT_HISTORY_RETENTION,
+            replay_cache_size: DEFAULT_REPLAY_CACHE_SIZE,
+            persist_replay_cache: false,
+            max_delivery_age: None,
+            allowed_ips: vec![],
+            allow_github_hook_ips: false,
+            trusted_proxies: vec![],
+            repositories: HashMap::new(),
+        }
+    }
+
+    fn defaults() -> Self {
+        Self {
+            github_api_url: DEFAULT_GITHUB_API_URL.clone(),
+            gitlab_api_url: DEFAULT_GITLAB_API_URL.clone(),
+            ..Self::empty()
+        }
+    }
+
+    fn apply_file(&mut self, file: ConfigFile) -> Result<(), ConfigError> {
+        if let Some(u) = file.telemetry_url {
+            self.telemetry_url = Some(parse_url("telemetry_url", &u)?);
+        }
+
+        if let Some(u) = file.github_api_url {
+            self.github_api_url = parse_url("github_api_url", &u)?;
+        }
+
+        if let Some(u) = file.gitlab_api_url {
+            self.gitlab_api_url = parse_url("gitlab_api_url", &u)?;
+        }
+
+        if let Some(s) = file.webhook_secret {
+            self.set_webhook_secret(s);
+        }
+
+        if let Some(s) = file.previous_webhook_secrets {
+            self.set_previous_webhook_secrets(s);
+        }
+
+        if let Some(m) = file.max_body_size {
+            self.max_body_size = m;
+        }
+
+        if let Some(l) = file.legacy_github_signature {
+            self.legacy_github_signature = l;
+        }
+
+        for (backend, secret) in file.backend_webhook_secrets {
+            self.set_backend_webhook_secret(&backend, secret)?;
+        }
+
+        if let Some(s) = file.strict_webhook_secrets {
+            self.strict_webhook_secrets = s;
+        }
+
+        if let Some(t) = file.api_token {
+            self.api_token = Some(t);
+        }
+
+        if let Some(w) = file.working_dir {
+            self.working_dir = Some(w);
+        }
+
+        if let Some(t) = file.trash_dir {
+            self.trash_dir = Some(t);
+        }
+
+        if let Some(g) = file.git_implementation {
+            self.git_implementation = parse_git_implementation(g)?;
+        }
+
+        if let Some(r) = file.history_retention {
+            self.history_retention = r;
+        }
+
+        if let Some(s) = file.replay_cache_size {
+            self.replay_cache_size = s;
+        }
+
+        if let Some(p) = file.persist_replay_cache {
+            self.persist_replay_cache = p;
+        }
+
+        if let Some(a) = file.max_delivery_age {
+            self.max_delivery_age = Some(a);
+        }
+
+        if let Some(a) = file.allowed_ips {
+            self.allowed_ips = parse_ip_ranges("allowed_ips", a.iter().map(String::as_str))?;
+        }
+
+        if let Some(g) = file.allow_github_hook_ips {
+            self.allow_github_hook_ips = g;
+        }
+
+        if let Some(p) = file.trusted_proxies {
+            self.trusted_proxies =
+                parse_ip_ranges("trusted_proxies", p.iter().map(String::as_str))?;
+        }
+
+        for (name, repository) in file.repositories {
+            let repository_config = self.repository_mut(&name)?;
+            if let Some(t) = repository.target_dir {
+                repository_config.set_target_dir(t);
+            }
+
+            if let Some(b) = repository.backend {
+                repository_config.set_backend(
+                    GitBackend::from_str(&b)
+                        .map_err(|_| ConfigError::UnsupportedBackend(name.clone(), b))?,
+                );
+            }
+
+            if let Some(s) = repository.sync_strategy {
+                repository_config.set_sync_strategy(
+                    SyncStrategy::from_str(&s)
+                        .map_err(|_| ConfigError::UnsupportedSyncStrategy(name.clone(), s))?,
+                );
+            }
+
+            repository_config.set_clone_options(repository.clone);
+            repository_config.set_submodules(repository.submodules);
+            repository_config.set_lfs(repository.lfs);
+            Self::apply_credentials_file(repository_config, &name, repository.credentials)?;
+            if let Some(r) = repository.releases {
+                repository_config.set_releases(r);
+            }
+            repository_config.set_branches(
+                RefFilter::new(&repository.branches, &repository.ignored_branches)
+                    .map_err(|e| ConfigError::MalformedRefPattern(name.clone(), e.to_string()))?,
+            );
+            repository_config.set_tags(
+                RefFilter::new(&repository.tags, &repository.ignored_tags)
+                    .map_err(|e| ConfigError::MalformedRefPattern(name.clone(), e.to_string()))?,
+            );
+            repository_config.set_actions(repository.actions);
+            repository_config.set_teardown_actions(repository.teardown_actions);
+            if let Some(s) = repository.webhook_secret {
+                repository_config.set_webhook_secret(s);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn apply_credentials_file(
+        repository_config: &mut RepositoryConfig,
+        name: &str,
+        file: CredentialsFile,
+    ) -> Result<(), ConfigError> {
+        let conflict =
+            |reason: &str| ConfigError::ConflictingCredentials(name.into(), reason.into());
+
+        if let Some(p) = file.protocol {
+            repository_config.set_protocol(
+                GitProtocol::from_str(&p)
+                    .map_err(|_| ConfigError::UnsupportedProtocol(name.into(), p))?,
+            );
+        }
+
+        let credentials = match (file.token, file.ssh_key) {
+            (Some(_), Some(_)) => return Err(conflict("both a token and an SSH key are set")),
+            (Some(token), None) => GitCredentials::Token {
+                username: file.username,
+                token,
+                url: None,
+            },
+            (None, Some(key)) => GitCredentials::SshKey(key),
+            (None, None) => GitCredentials::None,
+        };
+
+        repository_config.set_credentials(credentials);
+        match (
+            repository_config.credentials(),
+            repository_config.protocol(),
+        ) {
+            (GitCredentials::Token { .. }, GitProtocol::Ssh) => {
+                Err(conflict("a token can not be used with the SSH protocol"))
+            }
+            (GitCredentials::SshKey(_), GitProtocol::Https) => Err(conflict(
+                "an SSH key can not be used with the HTTPS protocol",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn apply_env(&mut self) -> Result<(), ConfigError> {
+        if let Some(u) = env_to_url("HR_TELEMETRY_URL")? {
+            self.telemetry_url = Some(u);
+        }
+
+        if let Some(u) = env_to_url("HR_GITHUB_API_URL")? {
+            self.github_api_url = u;
+        }
+
+        if let Some(u) = env_to_url("HR_GITLAB_API_URL")? {
+            self.gitlab_api_url = u;
+        }
+
+        if let Some(s) = env_to_str("HR_WEBHOOK_SECRET") {
+            self.set_webhook_secret(s);
+        }
+
+        if let Some(s) = env_to_str("HR_PREVIOUS_WEBHOOK_SECRETS") {
+            self.set_previous_webhook_secrets(split_secrets(&s));
+        }
+
+        if let Some(m) = env_to_str("HR_MAX_BODY_SIZE") {
+            self.max_body_size = m
+                .parse()
+                .map_err(|_| ConfigError::MalformedInteger("HR_MAX_BODY_SIZE".into(), m))?;
+        }
+
+        if let Some(l) = env_to_str("HR_LEGACY_GITHUB_SIGNATURE") {
+            self.legacy_github_signature = l.parse().map_err(|_| {
+                ConfigError::MalformedBoolean("HR_LEGACY_GITHUB_SIGNATURE".into(), l)
+            })?;
+        }
+
+        if let Some(s) = env_to_str("HR_STRICT_WEBHOOK_SECRETS") {
+            self.strict_webhook_secrets = s.parse().map_err(|_| {
+                ConfigError::MalformedBoolean("HR_STRICT_WEBHOOK_SECRETS".into(), s)
+            })?;
+        }
+
+        if let Some(t) = env_to_str("HR_API_TOKEN") {
+            self.api_token = Some(t);
+        }
+
+        if let Some(w) = env_to_pathbuf("HR_WORKING_DIR") {
+            self.working_dir = Some(w);
+        }
+
+        if let Some(t) = env_to_pathbuf("HR_TRASH_DIR") {
+            self.trash_dir = Some(t);
+        }
+
+        if let Some(g) = env_to_str("HR_GIT_IMPLEMENTATION") {
+            self.git_implementation = parse_git_implementation(g)?;
+        }
+
+        if let Some(r) = env_to_str("HR_HISTORY_RETENTION") {
+            self.history_retention = r
+                .parse()
+                .map_err(|_| ConfigError::MalformedInteger("HR_HISTORY_RETENTION".into(), r))?;
+        }
+
+        if let Some(s) = env_to_str("HR_REPLAY_CACHE_SIZE") {
+            self.replay_cache_size = s
+                .parse()
+                .map_err(|_| ConfigError::MalformedInteger("HR_REPLAY_CACHE_SIZE".into(), s))?;
+        }
+
+        if let Some(p) = env_to_str("HR_PERSIST_REPLAY_CACHE") {
+            self.persist_replay_cache = p
+                .parse()
+                .map_err(|_| ConfigError::MalformedBoolean("HR_PERSIST_REPLAY_CACHE".into(), p))?;
+        }
+
+        if let Some(a) = env_to_str("HR_MAX_DELIVERY_AGE") {
+            self.max_delivery_age = Some(
+                a.parse()
+                    .map_err(|_| ConfigError::MalformedInteger("HR_MAX_DELIVERY_AGE".into(), a))?,
+            );
+        }
+
+        if let Some(a) = env_to_str("HR_ALLOWED_IPS") {
+            self.allowed_ips = parse_ip_ranges("HR_ALLOWED_IPS", a.split(','))?;
+        }
+
+        if let Some(g) = env_to_str("HR_ALLOW_GITHUB_HOOK_IPS") {
+            self.allow_github_hook_ips = g
+                .parse()
+                .map_err(|_| ConfigError::MalformedBoolean("HR_ALLOW_GITHUB_HOOK_IPS".into(), g))?;
+        }
+
+        if let Some(p) = env_to_str("HR_TRUSTED_PROXIES") {
+            self.trusted_proxies = parse_ip_ranges("HR_TRUSTED_PROXIES", p.split(','))?;
+        }
+
+        if let Some(m) = env_to_str("HR_REPO_MAPPING") {
+            self.set_repo_mapping(&m)?;
+        }
+
+        if let Some(a) = env_to_str("HR_REPO_ACTIONS") {
+            self.set_repo_actions(&a)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn github_api_url(&self) -> &Url {
+        &self.github_api_url
+    }
+
+    pub fn gitlab_api_url(&self) -> &Url {
+        &self.gitlab_api_url
+    }
+
+    /// Get the Git hosting backend of the GitLab instance, from its API URL.
+    pub fn gitlab_backend(&self) -> GitBackend {
+        let root_url = self
+            .gitlab_api_url
+            .as_str()
+            .trim_end_matches('/')
+            .trim_end_matches("/api/v4");
+
+        if root_url == GitBackend::GitLab.root_url() {
+            GitBackend::GitLab
+        } else {
+            GitBackend::Custom(root_url.into())
+        }
+    }
+
+    pub fn repositories(&self) -> &HashMap<String, RepositoryConfig> {
+        &self.repositories
+    }
+
+    pub fn repository(&self, repo_full_name: &str) -> Option<&RepositoryConfig> {
+        self.repositories.get(repo_full_name)
+    }
+
+    /// Get a mutable repository configuration, creating it if needed.
+    pub fn repository_mut(
+        &mut self,
+        repo_full_name: &str,
+    ) -> Result<&mut RepositoryConfig, ConfigError> {
+        RepositoryPath::new(repo_full_name)
+            .map_err(|_| ConfigError::MalformedRepositoryName(repo_full_name.into()))?;
+
+        Ok(self.repositories.entry(repo_full_name.into()).or_default())
+    }
+
+    /// Get the Git hosting backend of a repository (GitHub as default).
+    pub fn repo_backend(&self, repo_full_name: &str) -> GitBackend {
+        self.repository(repo_full_name)
+            .and_then(|r| r.backend().cloned())
+            .unwrap_or(GitBackend::GitHub)
+    }
+
+    /// Get the synchronization strategy of a repository (`reset` as default).
+    pub fn repo_sync_strategy(&self, repo_full_name: &str) -> SyncStrategy {
+        self.repository(repo_full_name)
+            .map(|r| r.sync_strategy())
+            .unwrap_or_default()
+    }
+
+    /// Get the clone options of a repository (full clone as default).
+    pub fn repo_clone_options(&self, repo_full_name: &str) -> CloneOptions {
+        self.repository(repo_full_name)
+            .map(|r| r.clone_options().clone())
+            .unwrap_or_default()
+    }
+
+    /// Get the credentials of a repository (none as default).
+    pub fn repo_credentials(&self, repo_full_name: &str) -> GitCredentials {
+        self.repository(repo_full_name)
+            .map(|r| r.credentials().clone())
+            .unwrap_or_default()
+    }
+
+    /// Get the clone URL protocol of a repository (HTTPS as default).
+    pub fn repo_protocol(&self, repo_full_name: &str) -> GitProtocol {
+        self.repository(repo_full_name)
+            .map(|r| r.protocol())
+            .unwrap_or_default()
+    }
+
+    pub fn repo_actions(&self, repo_full_name: &str) -> &[Action] {
+        self.repository(repo_full_name)
+            .map(|r| r.actions())
+            .unwrap_or(&[])
+    }
+
+    /// Get the actions to run before removing a checkout of a deleted branch or tag.
+    pub fn repo_teardown_actions(&self, repo_full_name: &str) -> &[Action] {
+        self.repository(repo_full_name)
+            .map(|r| r.teardown_actions())
+            .unwrap_or(&[])
+    }
+
+    pub fn working_dir(&self) -> Option<&Path> {
+        self.working_dir.as_deref()
+    }
+
+    /// Directory where removed checkouts are moved to, instead of being deleted.
+    pub fn trash_dir(&self) -> Option<&Path> {
+        self.trash_dir.as_deref()
+    }
+
+    /// Git implementation running the Git operations.
+    pub fn git_implementation(&self) -> GitImplementation {
+        self.git_implementation
+    }
+
+    /// Maximum number of deliveries and jobs kept in history (0 disables the history).
+    pub fn history_retention(&self) -> usize {
+        self.history_retention
+    }
+
+    /// Maximum number of delivery identifiers remembered to reject replays (0 disables the check).
+    pub fn replay_cache_size(&self) -> usize {
+        self.replay_cache_size
+    }
+
+    /// Check if seen delivery identifiers are persisted in the working directory.
+    pub fn persist_replay_cache(&self) -> bool {
+        self.persist_replay_cache
+    }
+
+    /// Maximum age of deliveries, in seconds, for backends sending a delivery timestamp.
+    pub fn max_delivery_age(&self) -> Option<u64> {
+        self.max_delivery_age
+    }
+
+    /// IP ranges allowed to send webhook deliveries. Every source is allowed when empty,
+    /// unless GitHub hook ranges are allowed.
+    pub fn allowed_ips(&self) -> &[IpNet] {
+        &self.allowed_ips
+    }
+
+    /// Check if the hook IP ranges published by the GitHub `/meta` API are allowed.
+    pub fn allow_github_hook_ips(&self) -> bool {
+        self.allow_github_hook_ips
+    }
+
+    /// Reverse proxies trusted to set the `X-Forwarded-For` header.
+    pub fn trusted_proxies(&self) -> &[IpNet] {
+        &self.trusted_proxies
+    }
+
+    pub fn telemetry_url(&self) -> Option<&Url> {
+        self.telemetry_url.as_ref()
+    }
+
+    pub fn webhook_secret(&self) -> Option<&str> {
+        self.webhook_secret.as_deref()
+    }
+
+    /// Secrets accepted for webhook deliveries: the current secret, then the previous ones,
+    /// still accepted while webhooks are updated. Previous secrets are ignored without a current one.
+    pub fn webhook_secrets(&self) -> Vec<&str> {
+        match &self.webhook_secret {
+            Some(s) => std::iter::once(s.as_str())
+                .chain(self.previous_webhook_secrets.iter().map(String::as_str))
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// Secret of a webhook backend (see [`WEBHOOK_BACKENDS`]), used instead of the global secrets.
+    pub fn backend_webhook_secret(&self, backend: &str) -> Option<&str> {
+        self.backend_webhook_secrets
+            .get(backend)
+            .map(String::as_str)
+    }
+
+    /// Check if deliveries are rejected for repositories missing from the configuration,
+    /// or without any webhook secret.
+    pub fn strict_webhook_secrets(&self) -> bool {
+        self.strict_webhook_secrets
+    }
+
+    /// Maximum size of webhook delivery bodies, in bytes.
+    pub fn max_body_size(&self) -> usize {
+        self.max_body_size
+    }
+
+    /// Check if legacy GitHub SHA-1 signatures (`X-Hub-Signature`) are accepted.
+    pub fn legacy_github_signature(&self) -> bool {
+        self.legacy_github_signature
+    }
+
+    pub fn api_token(&self) -> Option<&str> {
+        self.api_token.as_deref()
+    }
+
+    pub fn set_github_api_url(&mut self, value: Url) {
+        self.github_api_url = value;
+    }
+
+    pub fn set_gitlab_api_url(&mut self, value: Url) {
+        self.gitlab_api_url = value;
+    }
+
+    pub fn set_working_dir<T: AsRef<Path>>(&mut self, value: T) {
+        self.working_dir = Some(value.as_ref().to_owned());
+    }
+
+    pub fn set_trash_dir<T: AsRef<Path>>(&mut self, value: T) {
+        self.trash_dir = Some(value.as_ref().to_owned());
+    }
+
+    pub fn set_git_implementation(&mut self, value: GitImplementation) {
+        self.git_implementation = value;
+    }
+
+    pub fn set_history_retention(&mut self, value: usize) {
+        self.history_retention = value;
+    }
+
+    pub fn set_replay_cache_size(&mut self, value: usize) {
+        self.replay_cache_size = value;
+    }
+
+    pub fn set_persist_replay_cache(&mut self, value: bool) {
+        self.persist_replay_cache = value;
+    }
+
+    pub fn set_max_delivery_age(&mut self, value: u64) {
+        self.max_delivery_age = Some(value);
+    }
+
+    pub fn set_allowed_ips(&mut self, value: Vec<IpNet>) {
+        self.allowed_ips = value;
+    }
+
+    pub fn set_allow_github_hook_ips(&mut self, value: bool) {
+        self.allow_github_hook_ips = value;
+    }
+
+    pub fn set_trusted_proxies(&mut self, value: Vec<IpNet>) {
+        self.trusted_proxies = value;
+    }
+
+    pub fn set_telemetry_url(&mut self, value: Url) {
+        self.telemetry_url = Some(value);
+    }
+
+    /// Set the webhook secret. An empty secret unsets it.
+    pub fn set_webhook_secret<T: Into<String>>(&mut self, value: T) {
+        self.webhook_secret = non_empty_secret(value.into());
+    }
+
+    /// Set the previous webhook secrets, skipping empty ones.
+    pub fn set_previous_webhook_secrets(&mut self, value: Vec<String>) {
+        self.previous_webhook_secrets = value.into_iter().filter_map(non_empty_secret).collect();
+    }
+
+    pub fn set_backend_webhook_secret<T: Into<String>>(
+        &mut self,
+        backend: &str,
+        value: T,
+    ) -> Result<(), ConfigError> {
+        if !WEBHOOK_BACKENDS.contains(&backend) {
+            return Err(ConfigError::UnknownWebhookBackend(backend.into()));
+        }
+
+        match non_empty_secret(value.into()) {
+            Some(secret) => self.backend_webhook_secrets.insert(backend.into(), secret),
+            None => self.backend_webhook_secrets.remove(backend),
+        };
+        Ok(())
+    }
+
+    pub fn set_strict_webhook_secrets(&mut self, value: bool) {
+        self.strict_webhook_secrets = value;
+    }
+
+    pub fn set_max_body_size(&mut self, value: usize) {
+        self.max_body_size = value;
+    }
+
+    pub fn set_legacy_github_signature(&mut self, value: bool) {
+        self.legacy_github_signature = value;
+    }
+
+    pub fn set_api_token<T: Into<String>>(&mut self, value: T) {
+        self.api_token = Some(value.into());
+    }
+
+    /// Set repository target directories.
+    /// Syntax is like that:
+    ///
+    /// ```text
+    /// org/repo-name=./local/folder,org2/repo-name2=./target/folder
+    /// ```
+    pub fn set_repo_mapping(&mut self, conf: &str) -> Result<(), ConfigError> {
+        for (name, target_dir) in parse_repo_mapping(conf)? {
+            self.repository_mut(&name)?.set_target_dir(target_dir);
+        }
+
+        Ok(())
+    }
+
+    /// Set repository actions.
+    /// Syntax is a JSON object, keyed by repository full name:
+    ///
+    /// ```text
+    /// {"org/repo-name": [{"command": "npm ci && npm run build", "timeout": 300}]}
+    /// ```
+    pub fn set_repo_actions(&mut self, conf: &str) -> Result<(), ConfigError> {
+        for (name, actions) in parse_repo_actions(conf)? {
+            self.repository_mut(&name)?.set_actions(actions);
+        }
+
+        Ok(())
+    }
+
+    pub fn validate_configuration(&self) -> Result<(), ConfigError> {
+        // Check if working directory exists
+        if let Some(w) = &self.working_dir {
+            let path = PathBuf::from(w);
+            if !path.exists() {
+                return Err(ConfigError::MissingWorkingDirectory(path));
+            }
+        }
+
+        // Check repository actions
+        for (name, repository) in &self.repositories {
+            if repository
+                .actions()
+                .iter()
+                .chain(repository.teardown_actions())
+                .any(|a| a.command.trim().is_empty())
+            {
+                return Err(ConfigError::EmptyActionCommand(name.clone()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct ServerConfig {
+    bind_ip: SocketAddr,
+    workers: usize,
+}
+
+impl ServerConfig {
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let bind_ip = env_to_str("HR_BIND_IP")
+            .and_then(|v| {
+                SocketAddr::from_str(&v[..])
+                    .map_err(|e| {
+                        tracing::error!(
+                            "error while parsing bind ip '{}' from environment variable HR_BIND_IP, will use default value '{}'",
+                            v,
+                            *DEFAULT_BIND_IP
+                        );
+                        e
+                    })
+                    .ok()
+            })
+            .unwrap_or(*DEFAULT_BIND_IP);
+
+        let workers = env_to_str("HR_WORKERS")
+            .and_then(|v| {
+                v.parse::<usize>()
+                    .ok()
+                    .filter(|&w| w > 0)
+                    .or_else(|| {
+                        tracing::error!(
+                            "error while parsing workers count '{}' from environment variable HR_WORKERS, will use default value '{}'",
+                            v,
+                            DEFAULT_WORKERS
+                        );
+                        None
+                    })
+            })
+            .unwrap_or(DEFAULT_WORKERS);
+
+        Ok(Self { bind_ip, workers })
+    }
+
+    pub fn empty() -> Self {
+        Self {
+            bind_ip: *DEFAULT_BIND_IP,
+            workers: DEFAULT_WORKERS,
+        }
+    }
+
+    pub fn bind_ip(&self) -> &SocketAddr {
+        &self.bind_ip
+    }
+
+    /// Maximum number of synchronization jobs running at once.
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    pub fn set_bind_ip(&mut self, value: SocketAddr) {
+        self.bind_ip = value;
+    }
+
+    pub fn set_workers(&mut self, value: usize) {
+        self.workers = value.max(1);
+    }
+}
+
+/// Ignore empty secrets, as they would let anyone sign deliveries.
+fn non_empty_secret(secret: String) -> Option<String> {
+    Some(secret).filter(|s| !s.trim().is_empty())
+}
+
+/// Split a comma-separated list of secrets, skipping empty ones.
+fn split_secrets(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+fn env_to_str(env_key: &str) -> Option<String> {
+    std::env::var(env_key).ok().filter(|s| !s.is_empty())
+}
+
+fn env_to_url(env_key: &str) -> Result<Option<Url>, ConfigError> {
+    env_to_str(env_key)
+        .map(|x| parse_url(env_key, &x))
+        .transpose()
+}
+
+fn env_to_pathbuf(env_key: &str) -> Option<PathBuf> {
+    env_to_str(env_key).and_then(|x| PathBuf::from_str(&x[..]).ok())
+}
+
+fn parse_url(key: &str, value: &str) -> Result<Url, ConfigError> {
+    Url::parse(value).map_err(|_| ConfigError::MalformedUrl(key.into(), value.into()))
+}
+
+/// Parse IP ranges, accepting single addresses too.
+fn parse_ip_ranges<'a>(
+    key: &str,
+    values: impl Iterator<Item = &'a str>,
+) -> Result<Vec<IpNet>, ConfigError> {
+    values
+        .map(str::trim)
+        .map(|value| {
+            IpNet::from_str(value)
+                .or_else(|_| IpAddr::from_str(value).map(IpNet::from))
+                .map_err(|_| ConfigError::MalformedIpRange(key.into(), value.into()))
+        })
+        .collect()
+}
+
+fn parse_git_implementation(value: String) -> Result<GitImplementation, ConfigError> {
+    GitImplementation::from_str(&value)
+        .map_err(|_| ConfigError::UnsupportedGitImplementation(value))
+}
+
+fn parse_repo_mapping(conf: &str) -> Result<Vec<(String, PathBuf)>, ConfigError> {
+    conf.split(',')
+        .map(|entry| {
+            let (name, target_dir) = entry
+                .split_once('=')
+                .map(|(n, t)| (n.trim(), t.trim()))
+                .filter(|(n, t)| !n.is_empty() && !t.is_empty())
+                .ok_or_else(|| ConfigError::MalformedRepoMapping(entry.into()))?;
+
+            Ok((name.to_owned(), PathBuf::from(target_dir)))
+        })
+        .collect()
+}
+
+fn parse_repo_actions(conf: &str) -> Result<HashMap<String, Vec<Action>>, ConfigError> {
+    serde_json::from_str(conf).map_err(|e| ConfigError::MalformedRepoActions(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        num::NonZeroU32,
+        path::{Path, PathBuf},
+    };
+
+    use assert_matches::assert_matches;
+    use pretty_assertions::assert_eq;
+
+    use crate::git::{GitBackend, GitCredentials, GitProtocol, RefType, SyncStrategy};
+
+    use super::{file::ConfigFile, split_secrets, Config, ConfigError};
+
+    fn config_from_toml(content: &str) -> Result<Config, ConfigError> {
+        let mut config = Config::defaults();
+        config.apply_file(ConfigFile::parse(Path::new("test.toml"), content)?)?;
+        Ok(config)
+    }
+
+    #[test]
+    fn test_parse_config_file() {
+        let config = config_from_toml(
+            r#"
+            working_dir = "./_work"
+            webhook_secret = "secret"
+            previous_webhook_secrets = ["old-secret"]
+            max_body_size = 1024
+            legacy_github_signature = true
+            history_retention = 50
+            replay_cache_size = 500
+            persist_replay_cache = true
+            max_delivery_age = 300
+            allowed_ips = ["10.0.0.0/8", "192.168.1.1"]
+            allow_github_hook_ips = true
+            trusted_proxies = ["127.0.0.1"]
+            backend_webhook_secrets = { gitlab = "gitlab-secret" }
+            strict_webhook_secrets = true
+
+            [repositories."org/repo"]
+            target_dir = "./site"
+            webhook_secret = "repo-secret"
+            backend = "gitlab"
+            sync_strategy = "reset-clean"
+            clone = { depth = 1, sparse_paths = ["site"] }
+            submodules = true
+            releases = { keep = 3 }
+            branches = ["main", "release/*"]
+            ignored_branches = ["release/*-rc*"]
+            ignored_tags = ["*"]
+
+            [[repositories."org/repo".actions]]
+            command = "npm ci"
+
+            [[repositories."org/repo".actions]]
+            command = "npm run build"
+            timeout = 300
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.working_dir(), Some(Path::new("./_work")));
+        assert_eq!(config.webhook_secret(), Some("secret"));
+        assert_eq!(config.webhook_secrets(), ["secret", "old-secret"]);
+        assert_eq!(config.max_body_size(), 1024);
+        assert!(config.legacy_github_signature());
+        assert_eq!(config.history_retention(), 50);
+        assert_eq!(config.replay_cache_size(), 500);
+        assert!(config.persist_replay_cache());
+        assert_eq!(config.max_delivery_age(), Some(300));
+        assert_eq!(
+            config.allowed_ips(),
+            [
+                "10.0.0.0/8".parse().unwrap(),
+                "192.168.1.1/32".parse().unwrap()
+            ]
+        );
+        assert!(config.allow_github_hook_ips());
+        assert_eq!(config.trusted_proxies(), ["127.0.0.1/32".parse().unwrap()]);
+        assert_eq!(
+            config.backend_webhook_secret("gitlab"),
+            Some("gitlab-secret")
+        );
+        assert_eq!(config.backend_webhook_secret("github"), None);
+        assert!(config.strict_webhook_secrets());
+        assert_eq!(config.github_api_url().as_str(), "https://api.github.com/");
+
+        let repository = config.repository("org/repo").unwrap();
+        assert_eq!(repository.target_dir(), Some(Path::new("./site")));
+        assert_eq!(repository.backend(), Some(&GitBackend::GitLab));
+        assert_eq!(repository.webhook_secret(), Some("repo-secret"));
+        assert_eq!(repository.sync_strategy(), SyncStrategy::ResetClean);
+        assert_eq!(repository.clone_options().depth, NonZeroU32::new(1));
+        assert_eq!(repository.clone_options().sparse_paths, ["site"]);
+        assert!(repository.submodules());
+        assert!(!repository.lfs());
+        assert_eq!(repository.releases().map(|r| r.keep), Some(3));
+        assert_eq!(config.repo_sync_strategy("org/other"), SyncStrategy::Reset);
+        assert!(repository.is_reference_allowed(&RefType::Branch("main".into())));
+        assert!(repository.is_reference_allowed(&RefType::Branch("release/1.0".into())));
+        assert!(!repository.is_reference_allowed(&RefType::Branch("release/1.0-rc1".into())));
+        assert!(!repository.is_reference_allowed(&RefType::Branch("feature".into())));
+        assert!(!repository.is_reference_allowed(&RefType::Tag("v1.0".into())));
+        assert_eq!(config.repo_actions("org/repo").len(), 2);
+        assert_eq!(config.repo_actions("org/repo")[1].timeout, Some(300));
+    }
+
+    #[test]
+    fn test_empty_webhook_secrets_ignored() {
+        assert_eq!(split_secrets("new,"), ["new"]);
+        assert_eq!(split_secrets("a,, b ,"), ["a", "b"]);
+
+        let config = config_from_toml(
+            r#"
+            webhook_secret = "new"
+            previous_webhook_secrets = ["", " ", "old"]
+            backend_webhook_secrets = { gitlab = "" }
+
+            [repositories."org/repo"]
+            webhook_secret = ""
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.webhook_secrets(), ["new", "old"]);
+        assert_eq!(config.backend_webhook_secret("gitlab"), None);
+        assert_eq!(
+            config.repository("org/repo").unwrap().webhook_secret(),
+            None
+        );
+
+        let config = config_from_toml(r#"webhook_secret = """#).unwrap();
+        assert_eq!(config.webhook_secret(), None);
+        assert!(config.webhook_secrets().is_empty());
+    }
+
+    #[test]
+    fn test_parse_config_file_unknown_field() {
+        assert_matches!(
+            config_from_toml("unknown = true"),
+            Err(ConfigError::MalformedConfigFile(_, _))
+        );
+    }
+
+    #[test]
+    fn test_parse_config_file_malformed_url() {
+        assert_matches!(
+            config_from_toml(r#"github_api_url = "nope""#),
+            Err(ConfigError::MalformedUrl(key, _)) if key == "github_api_url"
+        );
+    }
+
+    #[test]
+    fn test_parse_config_file_unknown_webhook_backend() {
+        assert_matches!(
+            config_from_toml(r#"backend_webhook_secrets = { gitgud = "secret" }"#),
+            Err(ConfigError::UnknownWebhookBackend(backend)) if backend == "gitgud"
+        );
+    }
+
+    #[test]
+    fn test_parse_config_file_malformed_ip_range() {
+        assert_matches!(
+            config_from_toml(r#"allowed_ips = ["10.0.0.0/33"]"#),
+            Err(ConfigError::MalformedIpRange(key, value)) if key == "allowed_ips" && value == "10.0.0.0/33"
+        );
+    }
+
+    #[test]
+    fn test_parse_config_file_malformed_repository_name() {
+        assert_matches!(
+            config_from_toml(
+                r#"
+                [repositories.repo]
+                target_dir = "./site"
+                "#
+            ),
+            Err(ConfigError::MalformedRepositoryName(name)) if name == "repo"
+        );
+    }
+
+    #[test]
+    fn test_parse_config_file_unsupported_backend() {
+        assert_matches!(
+            config_from_toml(
+                r#"
+                [repositories."org/repo"]
+                backend = "unknown"
+                "#
+            ),
+            Err(ConfigError::UnsupportedBackend(_, backend)) if backend == "unknown"
+        );
+    }
+
+    #[test]
+    fn test_parse_config_file_unsupported_sync_strategy() {
+        assert_matches!(
+            config_from_toml(
+                r#"
+                [repositories."org/repo"]
+                sync_strategy = "merge"
+                "#
+            ),
+            Err(ConfigError::UnsupportedSyncStrategy(_, strategy)) if strategy == "merge"
+        );
+    }
+
+    #[test]
+    fn test_parse_config_file_credentials() {
+        let config = config_from_toml(
+            r#"
+            [repositories."org/private"]
+            credentials = { token = "secret-token" }
+
+            [repositories."org/deploy"]
+            credentials = { ssh_key = "/keys/deploy" }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.repo_credentials("org/private"),
+            GitCredentials::Token {
+                username: None,
+                token: "secret-token".into(),
+                url: None,
+            }
+        );
+        assert_eq!(config.repo_protocol("org/private"), GitProtocol::Https);
+        assert_eq!(
+            config.repo_credentials("org/deploy"),
+            GitCredentials::SshKey(PathBuf::from("/keys/deploy"))
+        );
+        assert_eq!(config.repo_protocol("org/deploy"), GitProtocol::Ssh);
+        assert_eq!(config.repo_credentials("org/public"), GitCredentials::None);
+    }
+
+    #[test]
+    fn test_parse_config_file_conflicting_credentials() {
+        assert_matches!(
+            config_from_toml(
+                r#"
+                [repositories."org/repo"]
+                credentials = { token = "secret-token", protocol = "ssh" }
+                "#
+            ),
+            Err(ConfigError::ConflictingCredentials(name, _)) if name == "org/repo"
+        );
+        assert_matches!(
+            config_from_toml(
+                r#"
+                [repositories."org/repo"]
+                credentials = { protocol = "git" }
+                "#
+            ),
+            Err(ConfigError::UnsupportedProtocol(_, protocol)) if protocol == "git"
+        );
+    }
+
+    #[test]
+    fn test_parse_config_file_malformed_ref_pattern() {
+        assert_matches!(
+            config_from_toml(
+                r#"
+                [repositories."org/repo"]
+                branches = ["[main"]
+                "#
+            ),
+            Err(ConfigError::MalformedRefPattern(name, _)) if name == "org/repo"
+        );
+    }
+
+    #[test]
+    fn test_repo_mapping_overrides_file() {
+        let mut config = config_from_toml(
+            r#"
+            [repositories."org/repo"]
+            target_dir = "./site"
+            branches = ["main"]
+            "#,
+        )
+        .unwrap();
+        config
+            .set_repo_mapping("org/repo=./other,org/repo2=./folder")
+            .unwrap();
+
+        let repository = config.repository("org/repo").unwrap();
+        assert_eq!(repository.target_dir(), Some(Path::new("./other")));
+        assert_eq!(repository.branches().allowed(), ["main"]);
+        assert_eq!(
+            config.repository("org/repo2").unwrap().target_dir(),
+            Some(Path::new("./folder"))
+        );
+    }
+
+    #[test]
+    fn test_malformed_repo_mapping() {
+        let mut config = Config::empty();
+        assert_matches!(
+            config.set_repo_mapping("org/repo=./site,org/repo2"),
+            Err(ConfigError::MalformedRepoMapping(entry)) if entry == "org/repo2"
+        );
+        assert_matches!(
+            config.set_repo_mapping("repo=./site"),
+            Err(ConfigError::MalformedRepositoryName(_))
+        );
+    }
+
+    #[test]
+    fn test_validate_empty_action_command() {
+        let mut config = Config::empty();
+        config
+            .set_repo_actions(r#"{"org/repo": [{"command": " "}]}"#)
+            .unwrap();
+
+        assert_matches!(
+            config.validate_configuration(),
+            Err(ConfigError::EmptyActionCommand(name)) if name == "org/repo"
+        );
+    }
+}
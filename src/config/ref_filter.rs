@@ -0,0 +1,72 @@
+use glob::{Pattern, PatternError};
+
+/// Allow and deny glob patterns for branch or tag names (e.g. `main`, `release/*`).
+///
+/// A name is allowed when it matches no denied pattern, and matches at least one allowed
+/// pattern (or when no allowed pattern is configured).
+#[derive(Debug, Clone, Default)]
+pub struct RefFilter {
+    allowed: Vec<Pattern>,
+    denied: Vec<Pattern>,
+}
+
+impl RefFilter {
+    pub fn new<S: AsRef<str>>(allowed: &[S], denied: &[S]) -> Result<Self, PatternError> {
+        Ok(Self {
+            allowed: Self::compile(allowed)?,
+            denied: Self::compile(denied)?,
+        })
+    }
+
+    fn compile<S: AsRef<str>>(patterns: &[S]) -> Result<Vec<Pattern>, PatternError> {
+        patterns.iter().map(|p| Pattern::new(p.as_ref())).collect()
+    }
+
+    pub fn allowed(&self) -> Vec<&str> {
+        self.allowed.iter().map(Pattern::as_str).collect()
+    }
+
+    pub fn denied(&self) -> Vec<&str> {
+        self.denied.iter().map(Pattern::as_str).collect()
+    }
+
+    pub fn is_allowed(&self, name: &str) -> bool {
+        !self.denied.iter().any(|p| p.matches(name))
+            && (self.allowed.is_empty() || self.allowed.iter().any(|p| p.matches(name)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RefFilter;
+
+    #[test]
+    fn test_empty_filter() {
+        let filter = RefFilter::default();
+        assert!(filter.is_allowed("main"));
+        assert!(filter.is_allowed("feature/sample"));
+    }
+
+    #[test]
+    fn test_allowed_patterns() {
+        let filter = RefFilter::new(&["main", "release/*"], &[]).unwrap();
+        assert!(filter.is_allowed("main"));
+        assert!(filter.is_allowed("release/1.0"));
+        assert!(!filter.is_allowed("mainline"));
+        assert!(!filter.is_allowed("feature/sample"));
+    }
+
+    #[test]
+    fn test_denied_patterns() {
+        let filter = RefFilter::new(&["*"], &["wip-*", "release/*-rc*"]).unwrap();
+        assert!(filter.is_allowed("main"));
+        assert!(filter.is_allowed("release/1.0"));
+        assert!(!filter.is_allowed("wip-sample"));
+        assert!(!filter.is_allowed("release/1.0-rc1"));
+    }
+
+    #[test]
+    fn test_malformed_pattern() {
+        assert!(RefFilter::new(&["[main"], &[]).is_err());
+    }
+}
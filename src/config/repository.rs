@@ -0,0 +1,169 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    actions::Action,
+    git::{
+        CloneOptions, GitBackend, GitCredentials, GitProtocol, RefType, ReleaseOptions,
+        SyncStrategy,
+    },
+};
+
+use super::RefFilter;
+
+/// Repository-specific configuration.
+#[derive(Debug, Clone, Default)]
+pub struct RepositoryConfig {
+    target_dir: Option<PathBuf>,
+    backend: Option<GitBackend>,
+    sync_strategy: SyncStrategy,
+    clone_options: CloneOptions,
+    submodules: bool,
+    lfs: bool,
+    credentials: GitCredentials,
+    protocol: Option<GitProtocol>,
+    releases: Option<ReleaseOptions>,
+    branches: RefFilter,
+    tags: RefFilter,
+    actions: Vec<Action>,
+    teardown_actions: Vec<Action>,
+    webhook_secret: Option<String>,
+}
+
+impl RepositoryConfig {
+    pub fn target_dir(&self) -> Option<&Path> {
+        self.target_dir.as_deref()
+    }
+
+    /// Check if the target directory is templated with the `{branch}` placeholder.
+    pub fn has_per_branch_target_dir(&self) -> bool {
+        self.target_dir
+            .as_ref()
+            .map(|d| d.to_string_lossy().contains("{branch}"))
+            .unwrap_or(false)
+    }
+
+    pub fn backend(&self) -> Option<&GitBackend> {
+        self.backend.as_ref()
+    }
+
+    pub fn sync_strategy(&self) -> SyncStrategy {
+        self.sync_strategy
+    }
+
+    pub fn clone_options(&self) -> &CloneOptions {
+        &self.clone_options
+    }
+
+    /// Check if submodules are updated after each synchronization.
+    pub fn submodules(&self) -> bool {
+        self.submodules
+    }
+
+    /// Check if Git LFS files are pulled after each synchronization.
+    pub fn lfs(&self) -> bool {
+        self.lfs
+    }
+
+    pub fn credentials(&self) -> &GitCredentials {
+        &self.credentials
+    }
+
+    /// Get the clone URL protocol, explicitly set or implied by the credentials.
+    pub fn protocol(&self) -> GitProtocol {
+        self.protocol.unwrap_or_else(|| self.credentials.protocol())
+    }
+
+    /// Get the release deployment options, when releases are enabled.
+    pub fn releases(&self) -> Option<&ReleaseOptions> {
+        self.releases.as_ref()
+    }
+
+    pub fn branches(&self) -> &RefFilter {
+        &self.branches
+    }
+
+    pub fn tags(&self) -> &RefFilter {
+        &self.tags
+    }
+
+    pub fn actions(&self) -> &[Action] {
+        &self.actions
+    }
+
+    /// Actions to run before removing a checkout of a deleted branch or tag.
+    pub fn teardown_actions(&self) -> &[Action] {
+        &self.teardown_actions
+    }
+
+    /// Secret of the repository webhook, used instead of the backend or global secrets.
+    pub fn webhook_secret(&self) -> Option<&str> {
+        self.webhook_secret.as_deref()
+    }
+
+    /// Check if a reference is allowed to be synchronized, using the branch and tag filters.
+    /// Commits are always allowed.
+    pub fn is_reference_allowed(&self, reference: &RefType) -> bool {
+        match reference {
+            RefType::Branch(b) => self.branches.is_allowed(b),
+            RefType::Tag(t) => self.tags.is_allowed(t),
+            RefType::Commit(_) => true,
+        }
+    }
+
+    pub fn set_target_dir<T: AsRef<Path>>(&mut self, value: T) {
+        self.target_dir = Some(value.as_ref().to_owned());
+    }
+
+    pub fn set_backend(&mut self, value: GitBackend) {
+        self.backend = Some(value);
+    }
+
+    pub fn set_sync_strategy(&mut self, value: SyncStrategy) {
+        self.sync_strategy = value;
+    }
+
+    pub fn set_clone_options(&mut self, value: CloneOptions) {
+        self.clone_options = value;
+    }
+
+    pub fn set_submodules(&mut self, value: bool) {
+        self.submodules = value;
+    }
+
+    pub fn set_lfs(&mut self, value: bool) {
+        self.lfs = value;
+    }
+
+    pub fn set_credentials(&mut self, value: GitCredentials) {
+        self.credentials = value;
+    }
+
+    pub fn set_protocol(&mut self, value: GitProtocol) {
+        self.protocol = Some(value);
+    }
+
+    pub fn set_releases(&mut self, value: ReleaseOptions) {
+        self.releases = Some(value);
+    }
+
+    pub fn set_branches(&mut self, value: RefFilter) {
+        self.branches = value;
+    }
+
+    pub fn set_tags(&mut self, value: RefFilter) {
+        self.tags = value;
+    }
+
+    pub fn set_actions(&mut self, value: Vec<Action>) {
+        self.actions = value;
+    }
+
+    pub fn set_teardown_actions(&mut self, value: Vec<Action>) {
+        self.teardown_actions = value;
+    }
+
+    /// Set the repository webhook secret. An empty secret unsets it.
+    pub fn set_webhook_secret<T: Into<String>>(&mut self, value: T) {
+        self.webhook_secret = super::non_empty_secret(value.into());
+    }
+}
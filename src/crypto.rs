@@ -1,13 +1,437 @@
+use std::collections::HashMap;
+
 use hmac::{Hmac, Mac};
+use ring::{constant_time::verify_slices_are_equal, hmac as ring_hmac};
 use sha2::Sha256;
 
-pub fn is_valid_signature<'a>(signature: &str, body: &'a [u8], secret: &str) -> bool {
-    let mut hmac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
-    hmac.update(body);
+use crate::{backends::RepositoryField, config::Config, error::ErrorCode};
+
+/// HMAC algorithm of a webhook signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureAlgorithm {
+    Sha256,
+    /// Only used by the legacy GitHub `X-Hub-Signature` header.
+    Sha1,
+}
+
+/// Check a hex-encoded HMAC signature of a body.
+/// Malformed signatures, and signatures with an empty secret, are invalid.
+pub fn is_valid_signature(
+    signature: &str,
+    body: &[u8],
+    secret: &str,
+    algorithm: SignatureAlgorithm,
+) -> bool {
+    if secret.is_empty() {
+        return false;
+    }
+
+    let decoded = match hex::decode(signature) {
+        Ok(d) => d,
+        Err(_) => return false,
+    };
+
+    match algorithm {
+        SignatureAlgorithm::Sha256 => {
+            let mut hmac = match Hmac::<Sha256>::new_from_slice(secret.as_bytes()) {
+                Ok(h) => h,
+                Err(_) => return false,
+            };
+            hmac.update(body);
+            hmac.verify_slice(&decoded).is_ok()
+        }
+        SignatureAlgorithm::Sha1 => {
+            let key =
+                ring_hmac::Key::new(ring_hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, secret.as_bytes());
+            ring_hmac::verify(&key, body, &decoded).is_ok()
+        }
+    }
+}
+
+/// Check a token against a secret, in constant time. An empty secret matches no token.
+pub fn is_valid_token(token: &str, secret: &str) -> bool {
+    !secret.is_empty() && verify_slices_are_equal(token.as_bytes(), secret.as_bytes()).is_ok()
+}
+
+/// Webhook verification settings of a backend, shared by the backend middlewares.
+///
+/// The secrets of a delivery are resolved from its repository: the repository secret,
+/// then the backend secret, then the global secrets.
+#[derive(Debug, Clone)]
+pub struct WebhookVerifier {
+    secrets: Vec<String>,
+    backend_secret: Option<String>,
+    /// Configured repositories, with their secret.
+    repository_secrets: HashMap<String, Option<String>>,
+    strict: bool,
+    repository_field: RepositoryField,
+    max_body_size: usize,
+    legacy_github_signature: bool,
+}
+
+impl WebhookVerifier {
+    /// Create a verifier accepting any of the global `secrets`.
+    /// Verification is disabled when there is no secret at all.
+    pub fn new(secrets: Vec<String>, max_body_size: usize) -> Self {
+        Self {
+            secrets,
+            backend_secret: None,
+            repository_secrets: HashMap::new(),
+            strict: false,
+            repository_field: RepositoryField::default(),
+            max_body_size,
+            legacy_github_signature: false,
+        }
+    }
+
+    /// Create the verifier of a webhook backend (see [`crate::config::WEBHOOK_BACKENDS`]).
+    pub fn from_config(config: &Config, backend: &str) -> Self {
+        Self::new(
+            config
+                .webhook_secrets()
+                .into_iter()
+                .map(ToOwned::to_owned)
+                .collect(),
+            config.max_body_size(),
+        )
+        .with_backend_secret(
+            config
+                .backend_webhook_secret(backend)
+                .map(ToOwned::to_owned),
+        )
+        .with_repository_secrets(
+            config
+                .repositories()
+                .iter()
+                .map(|(name, r)| (name.clone(), r.webhook_secret().map(ToOwned::to_owned)))
+                .collect(),
+        )
+        .with_strict(config.strict_webhook_secrets())
+        .with_legacy_github_signature(config.legacy_github_signature())
+    }
+
+    pub fn with_backend_secret(mut self, value: Option<String>) -> Self {
+        self.backend_secret = value;
+        self
+    }
+
+    pub fn with_repository_secrets(mut self, value: HashMap<String, Option<String>>) -> Self {
+        self.repository_secrets = value;
+        self
+    }
+
+    /// Reject deliveries of repositories missing from the configuration, or without any secret.
+    pub fn with_strict(mut self, value: bool) -> Self {
+        self.strict = value;
+        self
+    }
+
+    /// Read the repository of deliveries from this payload field, the one synchronized by the backend.
+    pub fn with_repository_field(mut self, value: RepositoryField) -> Self {
+        self.repository_field = value;
+        self
+    }
+
+    /// Accept GitHub SHA-1 signatures from the `X-Hub-Signature` header.
+    pub fn with_legacy_github_signature(mut self, value: bool) -> Self {
+        self.legacy_github_signature = value;
+        self
+    }
+
+    /// Check if deliveries need to be verified, so their repository needs to be parsed.
+    pub fn is_enabled(&self) -> bool {
+        self.strict
+            || !self.secrets.is_empty()
+            || self.backend_secret.is_some()
+            || self.repository_secrets.values().any(Option::is_some)
+    }
+
+    /// Get the repository full name of a delivery, before verifying it.
+    pub fn repository_name(&self, body: &[u8]) -> Option<String> {
+        self.repository_field.parse(body)
+    }
+
+    pub fn max_body_size(&self) -> usize {
+        self.max_body_size
+    }
+
+    pub fn legacy_github_signature(&self) -> bool {
+        self.legacy_github_signature
+    }
+
+    /// Get the secrets accepted for a repository delivery.
+    /// Deliveries are only left unverified when no secret is configured at all: once one is,
+    /// deliveries of unknown repositories, or repositories without a secret, are rejected.
+    fn resolve_secrets(&self, repository: Option<&str>) -> Result<Vec<&str>, ErrorCode> {
+        let unconfigured =
+            || ErrorCode::UnconfiguredRepository(repository.unwrap_or_default().into());
+        let configured = repository.and_then(|r| self.repository_secrets.get(r));
+        if self.strict && configured.is_none() {
+            return Err(unconfigured());
+        }
+
+        let secrets = match configured
+            .and_then(Option::as_deref)
+            .or(self.backend_secret.as_deref())
+        {
+            Some(secret) => vec![secret],
+            None => self.secrets.iter().map(String::as_str).collect(),
+        };
+
+        if secrets.is_empty() && self.is_enabled() {
+            return Err(unconfigured());
+        }
+
+        Ok(secrets)
+    }
+
+    /// Check the signature of a repository delivery against each of its secrets, without
+    /// stopping at the first match, so the response time does not depend on which secret matched.
+    pub fn verify_signature(
+        &self,
+        repository: Option<&str>,
+        signature: Option<&str>,
+        body: &[u8],
+        algorithm: SignatureAlgorithm,
+    ) -> Result<(), ErrorCode> {
+        let secrets = self.resolve_secrets(repository)?;
+        if secrets.is_empty() {
+            return Ok(());
+        }
+
+        let signature = signature.ok_or(ErrorCode::InvalidSignature)?;
+        let valid = secrets.iter().fold(false, |valid, secret| {
+            is_valid_signature(signature, body, secret, algorithm) | valid
+        });
+
+        if valid {
+            Ok(())
+        } else {
+            Err(ErrorCode::InvalidSignature)
+        }
+    }
+
+    /// Check the token of a repository delivery, like [`Self::verify_signature`].
+    pub fn verify_token(&self, repository: Option<&str>, token: &str) -> Result<(), ErrorCode> {
+        let secrets = self.resolve_secrets(repository)?;
+        if secrets.is_empty() {
+            return Ok(());
+        }
+
+        let valid = secrets
+            .iter()
+            .fold(false, |valid, secret| is_valid_token(token, secret) | valid);
+
+        if valid {
+            Ok(())
+        } else {
+            Err(ErrorCode::InvalidToken)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+
+    use crate::error::ErrorCode;
+
+    use super::{is_valid_signature, is_valid_token, SignatureAlgorithm, WebhookVerifier};
+
+    // HMAC of "body" with the "secret" key
+    const SHA256_SIGNATURE: &str =
+        "dc46983557fea127b43af721467eb9b3fde2338fe3e14f51952aa8478c13d355";
+    const SHA1_SIGNATURE: &str = "a18991ff7e4513a1c2d2ee51e3a8e99ca891d9cd";
+
+    #[test]
+    fn test_malformed_signature() {
+        for signature in ["", "nope", "abc", &SHA256_SIGNATURE[2..]] {
+            assert!(!is_valid_signature(
+                signature,
+                b"body",
+                "secret",
+                SignatureAlgorithm::Sha256
+            ));
+        }
+    }
+
+    #[test]
+    fn test_verify_signature_with_rotated_secrets() {
+        let verifier = WebhookVerifier::new(vec!["new".into(), "secret".into()], 1024);
+        assert_matches!(
+            verifier.verify_signature(
+                None,
+                Some(SHA256_SIGNATURE),
+                b"body",
+                SignatureAlgorithm::Sha256
+            ),
+            Ok(())
+        );
+        assert_matches!(
+            verifier.verify_signature(
+                None,
+                Some(SHA256_SIGNATURE),
+                b"other",
+                SignatureAlgorithm::Sha256
+            ),
+            Err(ErrorCode::InvalidSignature)
+        );
+
+        let verifier = WebhookVerifier::new(vec!["new".into()], 1024);
+        assert_matches!(
+            verifier.verify_signature(
+                None,
+                Some(SHA256_SIGNATURE),
+                b"body",
+                SignatureAlgorithm::Sha256
+            ),
+            Err(ErrorCode::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_verify_legacy_signature() {
+        let verifier = WebhookVerifier::new(vec!["secret".into()], 1024);
+        assert_matches!(
+            verifier.verify_signature(
+                None,
+                Some(SHA1_SIGNATURE),
+                b"body",
+                SignatureAlgorithm::Sha1
+            ),
+            Ok(())
+        );
+        assert_matches!(
+            verifier.verify_signature(
+                None,
+                Some(SHA256_SIGNATURE),
+                b"body",
+                SignatureAlgorithm::Sha1
+            ),
+            Err(ErrorCode::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_empty_secret() {
+        let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, b"");
+        let signature = hex::encode(ring::hmac::sign(&key, b"{}"));
+        assert!(!is_valid_signature(
+            &signature,
+            b"{}",
+            "",
+            SignatureAlgorithm::Sha256
+        ));
+        assert!(!is_valid_token("", ""));
+    }
+
+    #[test]
+    fn test_verify_token() {
+        let verifier = WebhookVerifier::new(vec!["new".into(), "old".into()], 1024);
+        assert_matches!(verifier.verify_token(None, "old"), Ok(()));
+        assert_matches!(
+            verifier.verify_token(None, "nope"),
+            Err(ErrorCode::InvalidToken)
+        );
+    }
+
+    #[test]
+    fn test_repository_secrets() {
+        let verifier = WebhookVerifier::new(vec!["global".into()], 1024)
+            .with_backend_secret(Some("backend".into()))
+            .with_repository_secrets(
+                [
+                    ("org/repo".to_string(), Some("secret".to_string())),
+                    ("org/other".to_string(), None),
+                ]
+                .into_iter()
+                .collect(),
+            );
+        assert_matches!(verifier.resolve_secrets(Some("org/repo")), Ok(s) if s == ["secret"]);
+        assert_matches!(verifier.resolve_secrets(Some("org/other")), Ok(s) if s == ["backend"]);
+        assert_matches!(verifier.resolve_secrets(Some("org/unknown")), Ok(s) if s == ["backend"]);
+
+        let verifier = verifier.with_backend_secret(None);
+        assert_matches!(verifier.resolve_secrets(None), Ok(s) if s == ["global"]);
+        assert_matches!(
+            verifier.verify_signature(
+                Some("org/repo"),
+                Some(SHA256_SIGNATURE),
+                b"body",
+                SignatureAlgorithm::Sha256
+            ),
+            Ok(())
+        );
+        assert_matches!(
+            verifier.verify_signature(
+                Some("org/other"),
+                Some(SHA256_SIGNATURE),
+                b"body",
+                SignatureAlgorithm::Sha256
+            ),
+            Err(ErrorCode::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_repository_secrets_only() {
+        // Without strict mode, deliveries of other repositories are still verified
+        let verifier = WebhookVerifier::new(vec![], 1024).with_repository_secrets(
+            [
+                ("org/repo".to_string(), Some("secret".to_string())),
+                ("org/other".to_string(), None),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        assert!(verifier.is_enabled());
+        assert_matches!(verifier.resolve_secrets(Some("org/repo")), Ok(s) if s == ["secret"]);
+        for repository in [Some("org/other"), Some("org/unknown"), None] {
+            assert_matches!(
+                verifier.verify_signature(
+                    repository,
+                    Some(SHA256_SIGNATURE),
+                    b"body",
+                    SignatureAlgorithm::Sha256
+                ),
+                Err(ErrorCode::UnconfiguredRepository(_))
+            );
+            assert_matches!(
+                verifier.verify_token(repository, "secret"),
+                Err(ErrorCode::UnconfiguredRepository(_))
+            );
+        }
+
+        // Nothing is verified without any secret
+        let verifier = WebhookVerifier::new(vec![], 1024);
+        assert_matches!(verifier.verify_token(Some("org/repo"), ""), Ok(()));
+    }
 
-    let decoded = hex::decode(signature).unwrap();
-    match hmac.verify_slice(&decoded) {
-        Ok(()) => true,
-        Err(_) => false,
+    #[test]
+    fn test_strict_repository_secrets() {
+        let verifier = WebhookVerifier::new(vec![], 1024)
+            .with_repository_secrets(
+                [
+                    ("org/repo".to_string(), Some("secret".to_string())),
+                    ("org/other".to_string(), None),
+                ]
+                .into_iter()
+                .collect(),
+            )
+            .with_strict(true);
+        assert!(verifier.is_enabled());
+        assert_matches!(verifier.resolve_secrets(Some("org/repo")), Ok(s) if s == ["secret"]);
+        assert_matches!(
+            verifier.resolve_secrets(Some("org/other")),
+            Err(ErrorCode::UnconfiguredRepository(r)) if r == "org/other"
+        );
+        assert_matches!(
+            verifier.resolve_secrets(Some("org/unknown")),
+            Err(ErrorCode::UnconfiguredRepository(r)) if r == "org/unknown"
+        );
+        assert_matches!(
+            verifier.resolve_secrets(None),
+            Err(ErrorCode::UnconfiguredRepository(_))
+        );
     }
 }
@@ -6,11 +6,21 @@ use thiserror::Error;
 pub enum ErrorCode {
     MissingEventHeader,
     InvalidSignature,
+    InvalidToken,
     InvalidUserAgent,
     MalformedEventHeader,
     UnsupportedEventHeader(String),
     MalformedEventBody(#[from] serde_json::Error),
     MalformedEventBodyField(String, String),
+    NotFound(String),
+    InvalidApiToken,
+    ReplayedDelivery(String),
+    ExpiredDelivery(String),
+    ForbiddenSourceIp(String),
+    PayloadTooLarge(usize),
+    UnconfiguredRepository(String),
+    UnconfiguredBackend(String),
+    MissingDeliveryHeader(String),
     UnhandledError(String),
 }
 
@@ -45,6 +55,26 @@ impl ErrorCodeDetail {
         Self::with_status_code(StatusCode::BAD_REQUEST, internal_code, message)
     }
 
+    pub fn unauthorized<T: Into<String>>(internal_code: u32, message: T) -> Self {
+        Self::with_status_code(StatusCode::UNAUTHORIZED, internal_code, message)
+    }
+
+    pub fn forbidden<T: Into<String>>(internal_code: u32, message: T) -> Self {
+        Self::with_status_code(StatusCode::FORBIDDEN, internal_code, message)
+    }
+
+    pub fn not_found<T: Into<String>>(internal_code: u32, message: T) -> Self {
+        Self::with_status_code(StatusCode::NOT_FOUND, internal_code, message)
+    }
+
+    pub fn conflict<T: Into<String>>(internal_code: u32, message: T) -> Self {
+        Self::with_status_code(StatusCode::CONFLICT, internal_code, message)
+    }
+
+    pub fn payload_too_large<T: Into<String>>(internal_code: u32, message: T) -> Self {
+        Self::with_status_code(StatusCode::PAYLOAD_TOO_LARGE, internal_code, message)
+    }
+
     pub fn server_error<T: Into<String>>(internal_code: u32, message: T) -> Self {
         Self::with_status_code(StatusCode::INTERNAL_SERVER_ERROR, internal_code, message)
     }
@@ -61,10 +91,8 @@ impl ErrorCodeDetail {
 impl From<&ErrorCode> for ErrorCodeDetail {
     fn from(value: &ErrorCode) -> Self {
         match value {
-            ErrorCode::MissingEventHeader => Self::bad_request(1, "Missing X-GitHub-Event header"),
-            ErrorCode::InvalidSignature => {
-                Self::bad_request(2, "Invalid X-Hub-Signature-256 signature")
-            }
+            ErrorCode::MissingEventHeader => Self::bad_request(1, "Missing event header"),
+            ErrorCode::InvalidSignature => Self::bad_request(2, "Invalid signature"),
             ErrorCode::InvalidUserAgent => Self::bad_request(3, "Invalid User-Agent"),
             ErrorCode::MalformedEventHeader => Self::bad_request(4, "Malformed event header"),
             ErrorCode::UnsupportedEventHeader(event) => {
@@ -77,6 +105,37 @@ impl From<&ErrorCode> for ErrorCodeDetail {
                 7,
                 format!("Malformed event body field '{}': '{}'", field, e),
             ),
+            ErrorCode::InvalidToken => Self::bad_request(8, "Invalid X-Gitlab-Token token"),
+            ErrorCode::NotFound(e) => Self::not_found(9, format!("Not found: '{}'", e)),
+            ErrorCode::InvalidApiToken => Self::unauthorized(10, "Missing or invalid API token"),
+            ErrorCode::ReplayedDelivery(id) => {
+                Self::conflict(11, format!("Delivery already received: '{}'", id))
+            }
+            ErrorCode::ExpiredDelivery(date) => Self::bad_request(
+                12,
+                format!("Delivery date outside of the acceptance window: '{}'", date),
+            ),
+            ErrorCode::ForbiddenSourceIp(ip) => {
+                Self::forbidden(13, format!("Source IP not allowed: '{}'", ip))
+            }
+            ErrorCode::PayloadTooLarge(max) => Self::payload_too_large(
+                14,
+                format!("Payload too large, the maximum size is {} bytes", max),
+            ),
+            ErrorCode::UnconfiguredRepository(repository) => Self::forbidden(
+                15,
+                format!(
+                    "Repository not configured or without webhook secret: '{}'",
+                    repository
+                ),
+            ),
+            ErrorCode::UnconfiguredBackend(repository) => Self::forbidden(
+                16,
+                format!("No Git backend configured for repository: '{}'", repository),
+            ),
+            ErrorCode::MissingDeliveryHeader(header) => {
+                Self::bad_request(17, format!("Missing delivery header: '{}'", header))
+            }
             ErrorCode::UnhandledError(e) => {
                 Self::server_error(99, format!("Unhandled error: '{}'", e))
             }
@@ -1,11 +1,15 @@
-use std::{borrow::Cow, str::FromStr};
+use std::{borrow::Cow, fmt, str::FromStr};
 
-use super::GitError;
+use url::Url;
 
-#[derive(Debug)]
+use super::{GitError, GitProtocol};
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum GitBackend {
     GitHub,
     GitLab,
+    Bitbucket,
+    Gitea(String),
     Custom(String),
 }
 
@@ -14,7 +18,49 @@ impl GitBackend {
         match self {
             Self::GitHub => Cow::Borrowed("https://github.com"),
             Self::GitLab => Cow::Borrowed("https://gitlab.com"),
-            Self::Custom(url) => Cow::Owned(url.into()),
+            Self::Bitbucket => Cow::Borrowed("https://bitbucket.org"),
+            Self::Gitea(url) | Self::Custom(url) => Cow::Owned(url.into()),
+        }
+    }
+
+    /// Get the URL to clone a repository with, using HTTPS or SSH.
+    pub fn clone_url(&self, repo_full_name: &str, protocol: GitProtocol) -> String {
+        match protocol {
+            GitProtocol::Https => format!("{}/{}", self.root_url(), repo_full_name),
+            GitProtocol::Ssh => format!("git@{}:{}.git", self.ssh_host(), repo_full_name),
+        }
+    }
+
+    /// Get the origin (scheme, host and port) of the root URL, which credentials are bound to.
+    pub fn origin(&self) -> String {
+        let root_url = self.root_url();
+        match Url::parse(&root_url).map(|u| u.origin()) {
+            Ok(origin) if origin.is_tuple() => origin.ascii_serialization(),
+            _ => root_url.into_owned(),
+        }
+    }
+
+    fn ssh_host(&self) -> Cow<str> {
+        match self {
+            Self::GitHub => Cow::Borrowed("github.com"),
+            Self::GitLab => Cow::Borrowed("gitlab.com"),
+            Self::Bitbucket => Cow::Borrowed("bitbucket.org"),
+            Self::Gitea(url) | Self::Custom(url) => Url::parse(url)
+                .ok()
+                .and_then(|u| u.host_str().map(|h| Cow::Owned(h.to_owned())))
+                .unwrap_or_else(|| Cow::Owned(url.into())),
+        }
+    }
+}
+
+impl fmt::Display for GitBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::GitHub => f.write_str("github"),
+            Self::GitLab => f.write_str("gitlab"),
+            Self::Bitbucket => f.write_str("bitbucket"),
+            Self::Gitea(url) => write!(f, "gitea:{}", url),
+            Self::Custom(url) => write!(f, "custom:{}", url),
         }
     }
 }
@@ -26,10 +72,46 @@ impl FromStr for GitBackend {
         match &s.to_lowercase()[..] {
             "github" => Ok(Self::GitHub),
             "gitlab" => Ok(Self::GitLab),
-            other => match s.strip_prefix("custom:") {
-                Some(url) => Ok(Self::Custom(url.into())),
-                None => Err(GitError::UnsupportedGitBackend(other.into())),
-            },
+            "bitbucket" => Ok(Self::Bitbucket),
+            other => {
+                if let Some(url) = s.strip_prefix("gitea:") {
+                    Ok(Self::Gitea(url.trim_end_matches('/').into()))
+                } else if let Some(url) = s.strip_prefix("custom:") {
+                    Ok(Self::Custom(url.into()))
+                } else {
+                    Err(GitError::UnsupportedGitBackend(other.into()))
+                }
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::git::GitProtocol;
+
+    use super::GitBackend;
+
+    #[test]
+    fn test_clone_url() {
+        assert_eq!(
+            GitBackend::GitHub.clone_url("org/repo", GitProtocol::Https),
+            "https://github.com/org/repo"
+        );
+        assert_eq!(
+            GitBackend::GitHub.clone_url("org/repo", GitProtocol::Ssh),
+            "git@github.com:org/repo.git"
+        );
+        assert_eq!(
+            GitBackend::Bitbucket.clone_url("org/repo", GitProtocol::Ssh),
+            "git@bitbucket.org:org/repo.git"
+        );
+        assert_eq!(
+            GitBackend::Gitea("https://git.example.com:3000".into())
+                .clone_url("org/repo", GitProtocol::Ssh),
+            "git@git.example.com:org/repo.git"
+        );
+    }
+}
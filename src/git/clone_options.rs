@@ -0,0 +1,99 @@
+use std::num::NonZeroU32;
+
+use serde::Deserialize;
+
+/// Options reducing what is downloaded and checked out, applied to clones and fetches.
+///
+/// ```toml
+/// [repositories."org/repo-name".clone]
+/// depth = 1
+/// filter = "blob:none"
+/// single_branch = true
+/// sparse_paths = ["site", "assets"]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CloneOptions {
+    /// History depth (`--depth`), full history as default.
+    pub depth: Option<NonZeroU32>,
+    /// Partial clone filter (`--filter`, e.g. `blob:none`).
+    pub filter: Option<String>,
+    /// Only fetch the synchronized branch (`--single-branch`).
+    #[serde(default)]
+    pub single_branch: bool,
+    /// Directories to check out with a sparse checkout, everything as default.
+    #[serde(default)]
+    pub sparse_paths: Vec<String>,
+}
+
+impl CloneOptions {
+    /// Arguments to add to `git clone`.
+    pub fn clone_args(&self) -> Vec<String> {
+        let mut args = self.transfer_args();
+        if self.single_branch {
+            args.push("--single-branch".into());
+        }
+        if self.is_sparse() {
+            args.push("--sparse".into());
+        }
+
+        args
+    }
+
+    /// Arguments to add to `git fetch`.
+    pub fn fetch_args(&self) -> Vec<String> {
+        self.transfer_args()
+    }
+
+    pub fn is_sparse(&self) -> bool {
+        !self.sparse_paths.is_empty()
+    }
+
+    fn transfer_args(&self) -> Vec<String> {
+        let mut args = vec![];
+        if let Some(depth) = self.depth {
+            args.push(format!("--depth={}", depth));
+        }
+        if let Some(filter) = &self.filter {
+            args.push(format!("--filter={}", filter));
+        }
+
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use pretty_assertions::assert_eq;
+
+    use super::CloneOptions;
+
+    #[test]
+    fn test_default_args() {
+        let options = CloneOptions::default();
+        assert!(options.clone_args().is_empty());
+        assert!(options.fetch_args().is_empty());
+    }
+
+    #[test]
+    fn test_args() {
+        let options = CloneOptions {
+            depth: NonZeroU32::new(1),
+            filter: Some("blob:none".into()),
+            single_branch: true,
+            sparse_paths: vec!["site".into()],
+        };
+        assert_eq!(
+            options.clone_args(),
+            [
+                "--depth=1",
+                "--filter=blob:none",
+                "--single-branch",
+                "--sparse"
+            ]
+        );
+        assert_eq!(options.fetch_args(), ["--depth=1", "--filter=blob:none"]);
+    }
+}
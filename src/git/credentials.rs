@@ -0,0 +1,155 @@
+use std::{fmt, path::PathBuf, str::FromStr};
+
+use super::{GitBackend, GitError};
+
+/// Credentials used to reach a remote repository.
+#[derive(Clone, PartialEq)]
+pub enum GitCredentials {
+    /// Public repository, or credentials configured outside of hookrunner.
+    None,
+    /// HTTPS access token, with an optional username (backend-specific as default).
+    /// The token is only sent to `url`, the backend origin, once bound with [`Self::for_backend`].
+    Token {
+        username: Option<String>,
+        token: String,
+        url: Option<String>,
+    },
+    /// SSH deploy key path.
+    SshKey(PathBuf),
+}
+
+impl GitCredentials {
+    /// Bind the token to the backend origin, filling its username with the backend default
+    /// when none is configured.
+    pub fn for_backend(self, backend: &GitBackend) -> Self {
+        match self {
+            Self::Token {
+                username, token, ..
+            } => Self::Token {
+                username: username.or_else(|| Some(Self::default_token_username(backend).into())),
+                token,
+                url: Some(backend.origin()),
+            },
+            other => other,
+        }
+    }
+
+    /// Check if the token may be sent to a remote URL, i.e. if the URL is on the bound origin.
+    pub fn allows_url(&self, remote_url: &str) -> bool {
+        match self {
+            Self::Token { url: Some(url), .. } => {
+                remote_url == url
+                    || remote_url
+                        .strip_prefix(url.as_str())
+                        .map(|path| path.starts_with('/'))
+                        .unwrap_or(false)
+            }
+            _ => true,
+        }
+    }
+
+    fn default_token_username(backend: &GitBackend) -> &'static str {
+        match backend {
+            GitBackend::GitHub => "x-access-token",
+            GitBackend::GitLab => "oauth2",
+            GitBackend::Bitbucket => "x-token-auth",
+            GitBackend::Gitea(_) | GitBackend::Custom(_) => "git",
+        }
+    }
+
+    /// Protocol matching the credentials: SSH for deploy keys, HTTPS otherwise.
+    pub fn protocol(&self) -> GitProtocol {
+        match self {
+            Self::SshKey(_) => GitProtocol::Ssh,
+            Self::None | Self::Token { .. } => GitProtocol::Https,
+        }
+    }
+}
+
+impl Default for GitCredentials {
+    fn default() -> Self {
+        Self::None
+    }
+}
+
+// Tokens must never end up in logs
+impl fmt::Debug for GitCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::None => f.write_str("None"),
+            Self::Token { username, url, .. } => f
+                .debug_struct("Token")
+                .field("username", username)
+                .field("token", &"<redacted>")
+                .field("url", url)
+                .finish(),
+            Self::SshKey(path) => f.debug_tuple("SshKey").field(path).finish(),
+        }
+    }
+}
+
+/// Protocol used in clone URLs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GitProtocol {
+    Https,
+    Ssh,
+}
+
+impl Default for GitProtocol {
+    fn default() -> Self {
+        Self::Https
+    }
+}
+
+impl FromStr for GitProtocol {
+    type Err = GitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "https" => Ok(Self::Https),
+            "ssh" => Ok(Self::Ssh),
+            other => Err(GitError::UnsupportedProtocol(other.into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{GitBackend, GitCredentials};
+
+    #[test]
+    fn test_debug_redacts_token() {
+        let credentials = GitCredentials::Token {
+            username: None,
+            token: "super-secret".into(),
+            url: None,
+        };
+        let output = format!("{:?}", credentials);
+        assert!(!output.contains("super-secret"));
+        assert!(output.contains("<redacted>"));
+    }
+
+    #[test]
+    fn test_for_backend_binds_origin() {
+        let credentials = GitCredentials::Token {
+            username: None,
+            token: "secret-token".into(),
+            url: None,
+        }
+        .for_backend(&GitBackend::Gitea("https://gitea.example.com/git".into()));
+
+        assert_eq!(
+            credentials,
+            GitCredentials::Token {
+                username: Some("git".into()),
+                token: "secret-token".into(),
+                url: Some("https://gitea.example.com".into()),
+            }
+        );
+        assert!(credentials.allows_url("https://gitea.example.com/git/org/repo"));
+        assert!(!credentials.allows_url("https://gitea.example.com.evil.org/org/repo"));
+        assert!(!credentials.allows_url("https://evil.org/org/repo"));
+    }
+}
@@ -1,17 +1,72 @@
 use thiserror::Error;
 
+use crate::actions::ActionError;
+
+use super::SyncReport;
+
 #[derive(Error, Debug, Clone)]
 pub enum GitError {
     #[error("Missing Git binary. Make sure Git is installed on your system and is globally accessible (present in PATH).")]
     MissingGitBinary,
     #[error("Error while executing git: {0}")]
     GitExecutionError(String),
+    #[error("Error while updating submodules: {0}")]
+    SubmoduleUpdateError(String),
+    #[error("Error while pulling Git LFS files: {0}")]
+    LfsPullError(String),
     #[error("I/O error: {0}")]
     IoError(String),
     #[error("Unsupported Git reference type: {0}")]
     UnsupportedRefType(String),
     #[error("Unsupported Git backend: {0}")]
     UnsupportedGitBackend(String),
+    #[error("Releases are not enabled for repository '{0}'")]
+    ReleasesDisabled(String),
+    #[error("Unknown release: {0}")]
+    UnknownRelease(String),
+    #[error("No release to roll back to")]
+    NoPreviousRelease,
+    #[error("Unsupported Git implementation: {0}. Expected 'executable' or 'native'.")]
+    UnsupportedGitImplementation(String),
+    #[error("Unsupported operation with the native Git implementation: {0}")]
+    UnsupportedOperation(String),
+    #[error("Unsupported Git protocol: {0}. Expected 'https' or 'ssh'.")]
+    UnsupportedProtocol(String),
+    #[error("Unsupported synchronization strategy: {0}")]
+    UnsupportedSyncStrategy(String),
     #[error("Malformed repository path: {0}")]
     MalformedRepositoryPath(String),
+    #[error("Invalid target directory: {0}")]
+    InvalidTargetDir(String),
+}
+
+#[derive(Error, Debug, Clone)]
+pub enum SyncError {
+    #[error(transparent)]
+    Git(#[from] GitError),
+    #[error(transparent)]
+    Action(#[from] ActionError),
+}
+
+/// Failed synchronization, with the outputs collected until the failure.
+#[derive(Error, Debug, Clone)]
+#[error("{error}")]
+pub struct SyncFailure {
+    pub error: SyncError,
+    pub report: Box<SyncReport>,
+}
+
+impl SyncFailure {
+    pub fn new<E: Into<SyncError>>(error: E, report: SyncReport) -> Self {
+        Self {
+            error: error.into(),
+            report: Box::new(report),
+        }
+    }
+}
+
+impl From<SyncError> for SyncFailure {
+    fn from(error: SyncError) -> Self {
+        Self::new(error, SyncReport::default())
+    }
 }
@@ -0,0 +1,39 @@
+use std::{fmt, str::FromStr};
+
+use super::GitError;
+
+/// Which Git implementation runs the Git operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GitImplementation {
+    /// The `git` binary, found in `PATH`.
+    Executable,
+    /// libgit2, available with the `native-git` cargo feature.
+    Native,
+}
+
+impl Default for GitImplementation {
+    fn default() -> Self {
+        Self::Executable
+    }
+}
+
+impl fmt::Display for GitImplementation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Executable => f.write_str("executable"),
+            Self::Native => f.write_str("native"),
+        }
+    }
+}
+
+impl FromStr for GitImplementation {
+    type Err = GitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "executable" => Ok(Self::Executable),
+            "native" => Ok(Self::Native),
+            other => Err(GitError::UnsupportedGitImplementation(other.into())),
+        }
+    }
+}
@@ -1,13 +1,27 @@
 mod backend;
+mod clone_options;
+mod credentials;
 mod error;
+mod implementation;
+#[cfg(feature = "native-git")]
+mod native;
 mod ref_type;
+mod releases;
 mod repo_cloner;
 mod repository_path;
 mod service;
+mod sync_strategy;
 
 pub use self::backend::GitBackend;
-pub use self::error::GitError;
+pub use self::clone_options::CloneOptions;
+pub use self::credentials::{GitCredentials, GitProtocol};
+pub use self::error::{GitError, SyncError, SyncFailure};
+pub use self::implementation::GitImplementation;
+#[cfg(feature = "native-git")]
+pub use self::native::GitNative;
 pub use self::ref_type::RefType;
-pub use self::repo_cloner::RepoCloner;
+pub use self::releases::{ReleaseOptions, Releases};
+pub use self::repo_cloner::{RepoCloner, SyncOptions, SyncReport};
 pub use self::repository_path::RepositoryPath;
 pub use self::service::{GitExecutable, GitService};
+pub use self::sync_strategy::SyncStrategy;
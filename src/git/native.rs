@@ -0,0 +1,357 @@
+use std::{any::Any, path::Path};
+
+use async_trait::async_trait;
+use git2::{
+    build::{CheckoutBuilder, RepoBuilder},
+    AutotagOption, Branch, BranchType, Cred, FetchOptions, RemoteCallbacks, Repository, ResetType,
+    SubmoduleUpdateOptions,
+};
+
+use super::{CloneOptions, GitCredentials, GitError, GitService};
+
+const REMOTE_NAME: &str = "origin";
+const FETCH_REFSPECS: [&str; 2] = [
+    "+refs/heads/*:refs/remotes/origin/*",
+    "+refs/tags/*:refs/tags/*",
+];
+
+/// Git implementation based on libgit2, not needing the `git` binary.
+///
+/// Clone options (shallow, partial, single-branch or sparse clones) and Git LFS
+/// are not supported.
+#[derive(Debug, Default)]
+pub struct GitNative;
+
+impl GitNative {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Run a blocking libgit2 operation outside of the async runtime.
+    async fn run<T, F>(f: F) -> Result<T, GitError>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T, git2::Error> + Send + 'static,
+    {
+        let result = tokio::task::spawn_blocking(f)
+            .await
+            .map_err(|e| GitError::GitExecutionError(e.to_string()))?;
+
+        result.map_err(|e| {
+            let message = e.message().to_string();
+            tracing::error!(error = %message);
+            GitError::GitExecutionError(message)
+        })
+    }
+
+    fn fetch_options(credentials: &GitCredentials) -> FetchOptions<'static> {
+        let mut callbacks = RemoteCallbacks::new();
+        let credentials = credentials.clone();
+        let mut attempts = 0;
+
+        callbacks.credentials(move |url, username_from_url, _allowed| {
+            // libgit2 calls back again on rejected credentials
+            attempts += 1;
+            if attempts > 1 {
+                return Err(git2::Error::from_str("authentication failed"));
+            }
+
+            match &credentials {
+                GitCredentials::None => Err(git2::Error::from_str(
+                    "authentication required, but no credentials are configured",
+                )),
+                GitCredentials::Token { .. } if !credentials.allows_url(url) => Err(
+                    git2::Error::from_str("the token is not allowed for this remote URL"),
+                ),
+                GitCredentials::Token {
+                    username, token, ..
+                } => Cred::userpass_plaintext(username.as_deref().unwrap_or("git"), token),
+                GitCredentials::SshKey(key) => {
+                    Cred::ssh_key(username_from_url.unwrap_or("git"), None, key, None)
+                }
+            }
+        });
+
+        let mut options = FetchOptions::new();
+        options.remote_callbacks(callbacks);
+        options
+    }
+
+    fn fetch_remote(repo: &Repository, credentials: &GitCredentials) -> Result<(), git2::Error> {
+        let mut options = Self::fetch_options(credentials);
+        options.download_tags(AutotagOption::All);
+
+        repo.find_remote(REMOTE_NAME)?
+            .fetch(&FETCH_REFSPECS, Some(&mut options), None)
+    }
+
+    /// Check out a local branch, a remote branch (creating its local tracking branch),
+    /// a tag or a commit, like `git checkout <reference>`.
+    fn checkout_reference(repo: &Repository, reference: &str) -> Result<(), git2::Error> {
+        let mut checkout = CheckoutBuilder::new();
+        checkout.safe();
+
+        if let Ok(branch) = repo.find_branch(reference, BranchType::Local) {
+            let commit = branch.get().peel_to_commit()?;
+            repo.checkout_tree(commit.as_object(), Some(&mut checkout))?;
+            return repo.set_head(&format!("refs/heads/{}", reference));
+        }
+
+        let remote_name = format!("{}/{}", REMOTE_NAME, reference);
+        if let Ok(remote) = repo.find_branch(&remote_name, BranchType::Remote) {
+            let commit = remote.get().peel_to_commit()?;
+            repo.checkout_tree(commit.as_object(), Some(&mut checkout))?;
+            let mut branch = repo.branch(reference, &commit, false)?;
+            branch.set_upstream(Some(&remote_name))?;
+            return repo.set_head(&format!("refs/heads/{}", reference));
+        }
+
+        let commit = repo.revparse_single(reference)?.peel_to_commit()?;
+        repo.checkout_tree(commit.as_object(), Some(&mut checkout))?;
+        repo.set_head_detached(commit.id())
+    }
+
+    /// Fast-forward the current branch to its upstream branch.
+    fn fast_forward(repo: &Repository) -> Result<String, git2::Error> {
+        let head = repo.head()?;
+        if !head.is_branch() {
+            return Err(git2::Error::from_str("not on a branch"));
+        }
+
+        let upstream = Branch::wrap(repo.head()?).upstream()?;
+        let target = upstream.get().peel_to_commit()?;
+        let annotated = repo.reference_to_annotated_commit(upstream.get())?;
+        let (analysis, _) = repo.merge_analysis(&[&annotated])?;
+
+        if analysis.is_up_to_date() {
+            Ok("Already up to date.".into())
+        } else if analysis.is_fast_forward() {
+            let mut checkout = CheckoutBuilder::new();
+            checkout.safe();
+            repo.checkout_tree(target.as_object(), Some(&mut checkout))?;
+            repo.head()?
+                .set_target(target.id(), &format!("fast-forward: {}", target.id()))?;
+
+            Ok(format!("Fast-forward to {}", target.id()))
+        } else {
+            Err(git2::Error::from_str(
+                "not possible to fast-forward, the branch has diverged",
+            ))
+        }
+    }
+
+    fn update_submodules_recursive(
+        repo: &Repository,
+        credentials: &GitCredentials,
+    ) -> Result<(), git2::Error> {
+        for mut submodule in repo.submodules()? {
+            let mut options = SubmoduleUpdateOptions::new();
+            options.fetch(Self::fetch_options(credentials));
+            submodule.update(true, Some(&mut options))?;
+            Self::update_submodules_recursive(&submodule.open()?, credentials)?;
+        }
+
+        Ok(())
+    }
+
+    fn unsupported(operation: &str) -> GitError {
+        GitError::UnsupportedOperation(operation.into())
+    }
+}
+
+#[async_trait]
+impl GitService for GitNative {
+    #[tracing::instrument(skip(self, credentials))]
+    async fn clone_repository(
+        &self,
+        working_dir: &Path,
+        reference: Option<&str>,
+        url: &str,
+        folder_name: &str,
+        options: &CloneOptions,
+        credentials: &GitCredentials,
+    ) -> Result<String, GitError> {
+        if options != &CloneOptions::default() {
+            return Err(Self::unsupported("clone options"));
+        }
+
+        let destination = working_dir.join(folder_name);
+        let reference = reference.map(ToOwned::to_owned);
+        let url = url.to_owned();
+        let credentials = credentials.clone();
+
+        Self::run(move || {
+            let repo = RepoBuilder::new()
+                .fetch_options(Self::fetch_options(&credentials))
+                .clone(&url, &destination)?;
+            if let Some(reference) = &reference {
+                Self::checkout_reference(&repo, reference)?;
+            }
+
+            Ok(format!("Cloned '{}' into '{}'", url, destination.display()))
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self, credentials))]
+    async fn fetch(
+        &self,
+        working_dir: &Path,
+        options: &CloneOptions,
+        credentials: &GitCredentials,
+    ) -> Result<String, GitError> {
+        if options != &CloneOptions::default() {
+            return Err(Self::unsupported("clone options"));
+        }
+
+        let working_dir = working_dir.to_owned();
+        let credentials = credentials.clone();
+
+        Self::run(move || {
+            Self::fetch_remote(&Repository::open(&working_dir)?, &credentials)?;
+            Ok(format!("Fetched '{}'", REMOTE_NAME))
+        })
+        .await
+    }
+
+    async fn sparse_checkout(
+        &self,
+        _working_dir: &Path,
+        _paths: &[String],
+        _credentials: &GitCredentials,
+    ) -> Result<String, GitError> {
+        Err(Self::unsupported("sparse checkout"))
+    }
+
+    // Partial clones are not supported, so objects are always available locally
+    #[tracing::instrument(skip(self, _credentials))]
+    async fn checkout(
+        &self,
+        working_dir: &Path,
+        reference: &str,
+        _credentials: &GitCredentials,
+    ) -> Result<String, GitError> {
+        let working_dir = working_dir.to_owned();
+        let reference = reference.to_owned();
+
+        Self::run(move || {
+            Self::checkout_reference(&Repository::open(&working_dir)?, &reference)?;
+            Ok(format!("Checked out '{}'", reference))
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self, credentials))]
+    async fn pull(
+        &self,
+        working_dir: &Path,
+        credentials: &GitCredentials,
+    ) -> Result<String, GitError> {
+        let working_dir = working_dir.to_owned();
+        let credentials = credentials.clone();
+
+        Self::run(move || {
+            let repo = Repository::open(&working_dir)?;
+            Self::fetch_remote(&repo, &credentials)?;
+            Self::fast_forward(&repo)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self, _credentials))]
+    async fn reset_hard(
+        &self,
+        working_dir: &Path,
+        revision: &str,
+        _credentials: &GitCredentials,
+    ) -> Result<String, GitError> {
+        let working_dir = working_dir.to_owned();
+        let revision = revision.to_owned();
+
+        Self::run(move || {
+            let repo = Repository::open(&working_dir)?;
+            let target = repo.revparse_single(&revision)?.peel_to_commit()?;
+            repo.reset(target.as_object(), ResetType::Hard, None)?;
+            Ok(format!("HEAD is now at {}", target.id()))
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn clean(&self, working_dir: &Path) -> Result<String, GitError> {
+        let working_dir = working_dir.to_owned();
+
+        Self::run(move || {
+            let mut checkout = CheckoutBuilder::new();
+            checkout.force().remove_untracked(true).remove_ignored(true);
+            Repository::open(&working_dir)?.checkout_head(Some(&mut checkout))?;
+            Ok("Removed untracked and ignored files".into())
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self, credentials))]
+    async fn update_submodules(
+        &self,
+        working_dir: &Path,
+        credentials: &GitCredentials,
+    ) -> Result<String, GitError> {
+        let working_dir = working_dir.to_owned();
+        let credentials = credentials.clone();
+
+        Self::run(move || {
+            Self::update_submodules_recursive(&Repository::open(&working_dir)?, &credentials)?;
+            Ok("Updated submodules".into())
+        })
+        .await
+        .map_err(|e| match e {
+            GitError::GitExecutionError(e) => GitError::SubmoduleUpdateError(e),
+            e => e,
+        })
+    }
+
+    async fn lfs_pull(
+        &self,
+        _working_dir: &Path,
+        _credentials: &GitCredentials,
+    ) -> Result<String, GitError> {
+        Err(GitError::LfsPullError(
+            "Git LFS is not supported by the native Git implementation".into(),
+        ))
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn head_commit(&self, working_dir: &Path) -> Result<String, GitError> {
+        let working_dir = working_dir.to_owned();
+
+        Self::run(move || {
+            let repo = Repository::open(&working_dir)?;
+            let commit = repo.head()?.peel_to_commit()?;
+            Ok(commit.id().to_string())
+        })
+        .await
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::testing::{check_git_service, check_webhook_synchronization};
+
+    use super::GitNative;
+
+    #[tokio::test]
+    async fn test_git_native() {
+        check_git_service(Arc::new(GitNative::new())).await;
+    }
+
+    #[tokio::test]
+    async fn test_git_native_webhooks() {
+        check_webhook_synchronization(Arc::new(GitNative::new())).await;
+    }
+}
@@ -2,10 +2,45 @@ use std::str::FromStr;
 
 use super::GitError;
 
-#[derive(Debug)]
+const HEADS_PREFIX: &str = "refs/heads/";
+const BRANCHES_PREFIX: &str = "refs/branches/";
+const TAGS_PREFIX: &str = "refs/tags/";
+const REMOTES_PREFIX: &str = "refs/remotes/";
+
+/// Git reference to synchronize.
+///
+/// Supported forms are:
+/// - branches: `refs/heads/<branch>` (or `refs/branches/<branch>`),
+/// - remote-tracking branches: `refs/remotes/<remote>/<branch>`,
+/// - tags: `refs/tags/<tag>`,
+/// - full commit SHAs (SHA-1 or SHA-256).
+#[derive(Debug, Clone, PartialEq)]
 pub enum RefType {
     Branch(String),
     Tag(String),
+    Commit(String),
+}
+
+impl RefType {
+    /// Reference to use when cloning, if any (commits can not be cloned directly).
+    pub fn clone_reference(&self) -> Option<&str> {
+        match self {
+            Self::Branch(name) | Self::Tag(name) => Some(name),
+            Self::Commit(_) => None,
+        }
+    }
+
+    pub(crate) fn is_full_sha(value: &str) -> bool {
+        (value.len() == 40 || value.len() == 64) && value.chars().all(|c| c.is_ascii_hexdigit())
+    }
+
+    fn non_empty(name: &str, value: &str) -> Result<String, GitError> {
+        if name.is_empty() {
+            Err(GitError::UnsupportedRefType(value.into()))
+        } else {
+            Ok(name.into())
+        }
+    }
 }
 
 impl ToString for RefType {
@@ -13,6 +48,7 @@ impl ToString for RefType {
         match &self {
             Self::Branch(b) => b.into(),
             Self::Tag(t) => t.into(),
+            Self::Commit(c) => c.into(),
         }
     }
 }
@@ -21,12 +57,23 @@ impl TryFrom<&str> for RefType {
     type Error = GitError;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        if value.starts_with("refs/tags/") {
-            return Ok(Self::Tag(value.strip_prefix("refs/tags/").unwrap().into()));
-        } else if value.starts_with("refs/branches/") {
-            return Ok(Self::Branch(
-                value.strip_prefix("refs/branches/").unwrap().into(),
-            ));
+        if let Some(tag) = value.strip_prefix(TAGS_PREFIX) {
+            Ok(Self::Tag(Self::non_empty(tag, value)?))
+        } else if let Some(branch) = value
+            .strip_prefix(HEADS_PREFIX)
+            .or_else(|| value.strip_prefix(BRANCHES_PREFIX))
+        {
+            Ok(Self::Branch(Self::non_empty(branch, value)?))
+        } else if let Some(remote_branch) = value.strip_prefix(REMOTES_PREFIX) {
+            // Remote name is dropped, as the synchronization always uses the origin remote
+            match remote_branch.split_once('/') {
+                Some((remote, branch)) if !remote.is_empty() => {
+                    Ok(Self::Branch(Self::non_empty(branch, value)?))
+                }
+                _ => Err(GitError::UnsupportedRefType(value.into())),
+            }
+        } else if Self::is_full_sha(value) {
+            Ok(Self::Commit(value.to_lowercase()))
         } else {
             Err(GitError::UnsupportedRefType(value.into()))
         }
@@ -37,14 +84,86 @@ impl FromStr for RefType {
     type Err = GitError;
 
     fn from_str(value: &str) -> Result<Self, Self::Err> {
-        if value.starts_with("refs/tags/") {
-            return Ok(Self::Tag(value.strip_prefix("refs/tags/").unwrap().into()));
-        } else if value.starts_with("refs/branches/") {
-            return Ok(Self::Branch(
-                value.strip_prefix("refs/branches/").unwrap().into(),
-            ));
-        } else {
-            Err(GitError::UnsupportedRefType(value.into()))
+        Self::try_from(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use pretty_assertions::assert_eq;
+
+    use crate::git::GitError;
+
+    use super::RefType;
+
+    #[test]
+    fn test_parse_branches() {
+        assert_eq!(
+            RefType::try_from("refs/heads/main").unwrap(),
+            RefType::Branch("main".into())
+        );
+        assert_eq!(
+            RefType::try_from("refs/heads/feature/sample").unwrap(),
+            RefType::Branch("feature/sample".into())
+        );
+        assert_eq!(
+            RefType::try_from("refs/branches/main").unwrap(),
+            RefType::Branch("main".into())
+        );
+        assert_eq!(
+            RefType::try_from("refs/remotes/origin/feature/sample").unwrap(),
+            RefType::Branch("feature/sample".into())
+        );
+    }
+
+    #[test]
+    fn test_parse_tags() {
+        assert_eq!(
+            RefType::try_from("refs/tags/v1.0.0").unwrap(),
+            RefType::Tag("v1.0.0".into())
+        );
+    }
+
+    #[test]
+    fn test_parse_commits() {
+        assert_eq!(
+            RefType::try_from("6113728F27AE82C7B1A177C8D03F9E96E0ADF246").unwrap(),
+            RefType::Commit("6113728f27ae82c7b1a177c8d03f9e96e0adf246".into())
+        );
+        assert_eq!(
+            RefType::try_from("1f6b2a5a4a9f1d4c9a0e0e0c6c7b1f7b9d3e5a0c1f6b2a5a4a9f1d4c9a0e0e0c")
+                .unwrap(),
+            RefType::Commit(
+                "1f6b2a5a4a9f1d4c9a0e0e0c6c7b1f7b9d3e5a0c1f6b2a5a4a9f1d4c9a0e0e0c".into()
+            )
+        );
+        assert_eq!(
+            RefType::try_from("6113728f27ae82c7b1a177c8d03f9e96e0adf246")
+                .unwrap()
+                .clone_reference(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_unsupported() {
+        for value in [
+            "main",
+            "refs/heads/",
+            "refs/tags/",
+            "refs/remotes/origin",
+            "refs/remotes//main",
+            "refs/pull/1/head",
+            "6113728f",
+            "6113728f27ae82c7b1a177c8d03f9e96e0adf24z",
+        ] {
+            assert_matches!(
+                RefType::try_from(value),
+                Err(GitError::UnsupportedRefType(_)),
+                "{}",
+                value
+            );
         }
     }
 }
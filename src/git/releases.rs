@@ -0,0 +1,276 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::jobs::now_millis;
+
+use super::GitError;
+
+const DEFAULT_KEPT_RELEASES: usize = 5;
+
+/// Release deployment options of a repository.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReleaseOptions {
+    /// Number of releases kept on disk, including the current one.
+    #[serde(default = "default_kept_releases")]
+    pub keep: usize,
+}
+
+impl Default for ReleaseOptions {
+    fn default() -> Self {
+        Self {
+            keep: DEFAULT_KEPT_RELEASES,
+        }
+    }
+}
+
+fn default_kept_releases() -> usize {
+    DEFAULT_KEPT_RELEASES
+}
+
+/// Release deployments of a repository target directory:
+/// - `repository/`: the Git checkout, synchronized as usual,
+/// - `releases/<timestamp>-<sha>/`: copies of the checkout, where actions run,
+/// - `current`: a symlink to the active release, switched atomically.
+#[derive(Debug)]
+pub struct Releases {
+    root: PathBuf,
+}
+
+impl Releases {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_owned(),
+        }
+    }
+
+    pub fn checkout_dir(&self) -> PathBuf {
+        self.root.join("repository")
+    }
+
+    pub fn current_link(&self) -> PathBuf {
+        self.root.join("current")
+    }
+
+    pub fn release_dir(&self, name: &str) -> PathBuf {
+        self.root.join("releases").join(name)
+    }
+
+    /// Get the commit of a release, from its name.
+    pub fn release_commit(name: &str) -> Option<&str> {
+        name.split_once('-').map(|(_, sha)| sha)
+    }
+
+    /// List release names, oldest first.
+    pub fn list(&self) -> Result<Vec<String>, GitError> {
+        let releases_dir = self.root.join("releases");
+        if !releases_dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut names = vec![];
+        for entry in fs::read_dir(releases_dir).map_err(to_git_error)? {
+            let entry = entry.map_err(to_git_error)?;
+            if entry.file_type().map_err(to_git_error)?.is_dir() {
+                names.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+
+        // Timestamps have the same number of digits until 2286
+        names.sort();
+        Ok(names)
+    }
+
+    /// Get the name of the active release.
+    pub fn current(&self) -> Option<String> {
+        fs::read_link(self.current_link())
+            .ok()
+            .and_then(|target| target.file_name().map(|n| n.to_string_lossy().into_owned()))
+    }
+
+    /// Get the name of the release preceding the active one.
+    pub fn previous(&self) -> Result<Option<String>, GitError> {
+        let names = self.list()?;
+        let current = self.current();
+
+        Ok(names
+            .iter()
+            .position(|n| Some(n) == current.as_ref())
+            .and_then(|i| i.checked_sub(1))
+            .map(|i| names[i].clone()))
+    }
+
+    /// Create a release from the current checkout, without its `.git` directory.
+    pub fn create(&self, commit: &str) -> Result<String, GitError> {
+        let name = format!("{}-{}", now_millis(), commit);
+        copy_tree(&self.checkout_dir(), &self.release_dir(&name)).map_err(to_git_error)?;
+
+        Ok(name)
+    }
+
+    /// Remove a release, e.g. after a failed build.
+    pub fn remove(&self, name: &str) -> Result<(), GitError> {
+        fs::remove_dir_all(self.release_dir(name)).map_err(to_git_error)
+    }
+
+    /// Point the `current` symlink to a release.
+    ///
+    /// The new symlink is created next to the current one, then renamed over it,
+    /// so the web server never sees a missing or half-updated directory.
+    /// Only listed releases can be activated, so names like `..` never escape `releases/`.
+    pub fn activate(&self, name: &str) -> Result<(), GitError> {
+        if !self.list()?.iter().any(|n| n == name) {
+            return Err(GitError::UnknownRelease(name.into()));
+        }
+
+        let link = self.current_link();
+        let next_link = self.root.join("current.next");
+        if fs::symlink_metadata(&next_link).is_ok() {
+            fs::remove_file(&next_link).map_err(to_git_error)?;
+        }
+
+        symlink_dir(&Path::new("releases").join(name), &next_link).map_err(to_git_error)?;
+        fs::rename(&next_link, &link).map_err(to_git_error)
+    }
+
+    /// Remove the oldest releases, keeping `keep` releases and the active one.
+    pub fn prune(&self, keep: usize) -> Result<Vec<String>, GitError> {
+        let names = self.list()?;
+        let current = self.current();
+        let mut removed = vec![];
+
+        for name in names.iter().take(names.len().saturating_sub(keep.max(1))) {
+            if Some(name) != current.as_ref() {
+                self.remove(name)?;
+                removed.push(name.clone());
+            }
+        }
+
+        Ok(removed)
+    }
+}
+
+fn to_git_error(e: io::Error) -> GitError {
+    GitError::IoError(e.to_string())
+}
+
+fn copy_tree(source: &Path, destination: &Path) -> io::Result<()> {
+    fs::create_dir_all(destination)?;
+
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        if entry.file_name() == ".git" {
+            continue;
+        }
+
+        let file_type = entry.file_type()?;
+        let target = destination.join(entry.file_name());
+        if file_type.is_dir() {
+            copy_tree(&entry.path(), &target)?;
+        } else if file_type.is_symlink() {
+            copy_symlink(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink_dir(original: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(original, link)
+}
+
+#[cfg(windows)]
+fn symlink_dir(original: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_dir(original, link)
+}
+
+#[cfg(unix)]
+fn copy_symlink(source: &Path, destination: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(source)?, destination)
+}
+
+#[cfg(windows)]
+fn copy_symlink(source: &Path, destination: &Path) -> io::Result<()> {
+    fs::copy(source, destination).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::Releases;
+
+    fn create_releases(count: usize) -> (tempfile::TempDir, Releases, Vec<String>) {
+        let tempdir = tempfile::tempdir().unwrap();
+        let releases = Releases::new(tempdir.path());
+        std::fs::create_dir_all(releases.checkout_dir().join(".git")).unwrap();
+        std::fs::create_dir_all(releases.checkout_dir().join("site")).unwrap();
+        std::fs::write(releases.checkout_dir().join("site/index.html"), "hello").unwrap();
+
+        let names = (0..count)
+            .map(|i| {
+                // Make sure timestamps are different
+                std::thread::sleep(std::time::Duration::from_millis(2));
+                releases.create(&format!("{:040}", i)).unwrap()
+            })
+            .collect();
+        (tempdir, releases, names)
+    }
+
+    #[test]
+    fn test_create_and_activate() {
+        let (_tempdir, releases, names) = create_releases(1);
+        assert_eq!(releases.current(), None);
+
+        releases.activate(&names[0]).unwrap();
+        assert_eq!(releases.current(), Some(names[0].clone()));
+        assert_eq!(
+            Releases::release_commit(&names[0]),
+            Some("0000000000000000000000000000000000000000")
+        );
+
+        let current = releases.current_link();
+        assert_eq!(
+            std::fs::read_to_string(current.join("site/index.html")).unwrap(),
+            "hello"
+        );
+        assert!(!current.join(".git").exists());
+        assert!(releases.activate("unknown").is_err());
+    }
+
+    #[test]
+    fn test_activate_outside_releases() {
+        let (_tempdir, releases, names) = create_releases(1);
+        releases.activate(&names[0]).unwrap();
+
+        for name in ["../repository", "..", ".", "", "/tmp"] {
+            assert!(releases.activate(name).is_err(), "{}", name);
+        }
+        assert_eq!(releases.current(), Some(names[0].clone()));
+    }
+
+    #[test]
+    fn test_previous_and_prune() {
+        let (_tempdir, releases, names) = create_releases(4);
+        assert_eq!(releases.list().unwrap(), names);
+
+        releases.activate(&names[3]).unwrap();
+        assert_eq!(releases.previous().unwrap(), Some(names[2].clone()));
+
+        // The active release is never pruned
+        releases.activate(&names[0]).unwrap();
+        assert_eq!(releases.previous().unwrap(), None);
+        assert_eq!(releases.prune(2).unwrap(), [names[1].clone()]);
+        assert_eq!(
+            releases.list().unwrap(),
+            [names[0].clone(), names[2].clone(), names[3].clone()]
+        );
+    }
+}
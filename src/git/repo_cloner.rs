@@ -1,12 +1,68 @@
 use std::path::{Path, PathBuf};
 
-use crate::{config::Config, service::ServiceHandler};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
-use super::{GitBackend, GitError, RefType, RepositoryPath};
+use crate::{
+    actions::{Action, ActionError, ActionOutput},
+    config::Config,
+    jobs::now_millis,
+    service::ServiceHandler,
+};
+
+use super::{
+    CloneOptions, GitBackend, GitCredentials, GitError, GitProtocol, RefType, ReleaseOptions,
+    Releases, RepositoryPath, SyncError, SyncFailure, SyncStrategy,
+};
+
+/// Outputs collected during a repository synchronization.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct SyncReport {
+    pub git_output: Vec<String>,
+    pub action_outputs: Vec<ActionOutput>,
+    /// Commit checked out by the synchronization.
+    #[serde(default)]
+    pub head_commit: Option<String>,
+    /// Release activated by the job, in release deployment mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release: Option<String>,
+}
+
+impl SyncReport {
+    /// Attach the report to the result of a synchronization, successful or not.
+    pub fn into_result(self, result: Result<(), SyncError>) -> Result<Self, SyncFailure> {
+        match result {
+            Ok(()) => Ok(self),
+            Err(e) => Err(SyncFailure::new(e, self)),
+        }
+    }
+}
+
+/// How a checkout is created and updated.
+#[derive(Debug, Clone, Default)]
+pub struct SyncOptions {
+    pub strategy: SyncStrategy,
+    pub clone: CloneOptions,
+    /// Exact commit to check out, instead of the reference tip.
+    pub commit: Option<String>,
+    /// Initialize and update submodules, recursively.
+    pub submodules: bool,
+    /// Download Git LFS files.
+    pub lfs: bool,
+    /// Credentials used to reach the remote.
+    pub credentials: GitCredentials,
+    /// Protocol of the clone URL.
+    pub protocol: GitProtocol,
+}
 
 pub struct RepoCloner;
 
 impl RepoCloner {
+    /// Clone or update a repository reference, then run the repository actions.
+    /// `forced` marks a force-pushed reference, which is never pulled.
+    /// When `commit` is set, this exact commit is checked out instead of the reference tip.
+    /// With releases enabled, actions run in a new release, activated only when they succeed.
+    /// On failure, the outputs collected until then are kept in the returned error.
     #[tracing::instrument]
     pub async fn create_or_update_using_config(
         config: &Config,
@@ -14,57 +70,379 @@ impl RepoCloner {
         backend: GitBackend,
         repo_path: &RepositoryPath,
         reference: RefType,
-    ) -> Result<(), GitError> {
+        forced: bool,
+        commit: Option<&str>,
+    ) -> Result<SyncReport, SyncFailure> {
         let repository_target_dir =
-            Self::get_repository_target_dir(config, &repo_path.full_name(), repo_path.name());
+            Self::get_repository_target_dir(config, repo_path, &reference.to_string());
 
-        Self::create_or_update_in_directory(
+        let repository = config.repository(&repo_path.full_name());
+        let release_options = repository.and_then(|r| r.releases()).cloned();
+        let checkout_dir = match &release_options {
+            Some(_) => Releases::new(&repository_target_dir).checkout_dir(),
+            None => repository_target_dir.clone(),
+        };
+        let mut options = SyncOptions {
+            strategy: config.repo_sync_strategy(&repo_path.full_name()),
+            clone: config.repo_clone_options(&repo_path.full_name()),
+            commit: commit.map(ToOwned::to_owned),
+            submodules: repository.map(|r| r.submodules()).unwrap_or(false),
+            lfs: repository.map(|r| r.lfs()).unwrap_or(false),
+            credentials: config
+                .repo_credentials(&repo_path.full_name())
+                .for_backend(&backend),
+            protocol: config.repo_protocol(&repo_path.full_name()),
+        };
+        if forced && options.strategy == SyncStrategy::Pull {
+            tracing::info!(message = "Force-push detected, resetting instead of pulling");
+            options.strategy = SyncStrategy::Reset;
+        }
+
+        let mut report = SyncReport::default();
+        if let Err(e) = Self::create_or_update_in_directory(
             services,
             backend,
             repo_path,
             reference,
-            &repository_target_dir,
+            &options,
+            &checkout_dir,
+            &mut report.git_output,
+        )
+        .await
+        {
+            return Err(SyncFailure::new(e, report));
+        }
+
+        report.head_commit = services.git().head_commit(&checkout_dir).await.ok();
+        let actions = config.repo_actions(&repo_path.full_name());
+
+        let result = match release_options {
+            Some(release_options) => {
+                Self::deploy_release(
+                    services,
+                    &Releases::new(&repository_target_dir),
+                    &release_options,
+                    actions,
+                    &mut report,
+                )
+                .await
+            }
+            None => Self::run_actions(services, &checkout_dir, actions, &mut report.action_outputs)
+                .await
+                .map_err(SyncError::from),
+        };
+
+        report.into_result(result)
+    }
+
+    /// Create a release from the checkout and run the actions in it,
+    /// then activate it and prune old releases.
+    /// Failed releases are removed, leaving the active release untouched.
+    async fn deploy_release(
+        services: &ServiceHandler,
+        releases: &Releases,
+        release_options: &ReleaseOptions,
+        actions: &[Action],
+        report: &mut SyncReport,
+    ) -> Result<(), SyncError> {
+        let commit = report.head_commit.as_deref().unwrap_or("unknown");
+        let name = releases.create(commit)?;
+
+        if let Err(e) = Self::run_actions(
+            services,
+            &releases.release_dir(&name),
+            actions,
+            &mut report.action_outputs,
         )
         .await
+        {
+            if let Err(remove_error) = releases.remove(&name) {
+                tracing::warn!(message = "Could not remove failed release", release = %name, error = %remove_error);
+            }
+            return Err(e.into());
+        }
+
+        releases.activate(&name)?;
+        report
+            .git_output
+            .push(format!("Activated release '{}'", name));
+        for pruned in releases.prune(release_options.keep)? {
+            report
+                .git_output
+                .push(format!("Removed release '{}'", pruned));
+        }
+
+        report.release = Some(name);
+        Ok(())
+    }
+
+    /// Run actions one after the other, stopping at the first failure.
+    /// Outputs are appended as they complete, including the one of a failed command.
+    async fn run_actions(
+        services: &ServiceHandler,
+        directory: &Path,
+        actions: &[Action],
+        action_outputs: &mut Vec<ActionOutput>,
+    ) -> Result<(), ActionError> {
+        for action in actions {
+            match services.actions().run(directory, action).await {
+                Ok(output) => action_outputs.push(output),
+                Err(e) => {
+                    action_outputs.extend(e.output().cloned());
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Activate a previous release of a repository reference: the one before the active release,
+    /// or `release` when set.
+    #[tracing::instrument]
+    pub fn rollback_using_config(
+        config: &Config,
+        repo_path: &RepositoryPath,
+        reference: &RefType,
+        release: Option<&str>,
+    ) -> Result<SyncReport, SyncError> {
+        if config
+            .repository(&repo_path.full_name())
+            .and_then(|r| r.releases())
+            .is_none()
+        {
+            return Err(GitError::ReleasesDisabled(repo_path.full_name()).into());
+        }
+
+        let releases = Releases::new(&Self::get_repository_target_dir(
+            config,
+            repo_path,
+            &reference.to_string(),
+        ));
+        let name = match release {
+            Some(r) => r.to_owned(),
+            None => releases.previous()?.ok_or(GitError::NoPreviousRelease)?,
+        };
+        releases.activate(&name)?;
+
+        Ok(SyncReport {
+            git_output: vec![format!("Rolled back to release '{}'", name)],
+            head_commit: Releases::release_commit(&name).map(ToOwned::to_owned),
+            release: Some(name),
+            ..SyncReport::default()
+        })
     }
 
+    /// Remove the checkout of a deleted reference, after running the repository teardown actions.
+    /// The checkout is moved to the trash directory when one is configured.
     #[tracing::instrument]
+    pub async fn remove_using_config(
+        config: &Config,
+        services: &ServiceHandler,
+        repo_path: &RepositoryPath,
+        reference: &RefType,
+    ) -> Result<SyncReport, SyncFailure> {
+        let repository_target_dir =
+            Self::get_repository_target_dir(config, repo_path, &reference.to_string());
+        if !repository_target_dir.exists() {
+            return Ok(SyncReport::default());
+        }
+
+        // With releases, teardown actions run in the active release
+        let actions_dir = match config
+            .repository(&repo_path.full_name())
+            .and_then(|r| r.releases())
+        {
+            Some(_) => Releases::new(&repository_target_dir).current_link(),
+            None => repository_target_dir.clone(),
+        };
+        let mut report = SyncReport::default();
+        if let Err(e) = Self::run_actions(
+            services,
+            &actions_dir,
+            config.repo_teardown_actions(&repo_path.full_name()),
+            &mut report.action_outputs,
+        )
+        .await
+        {
+            return Err(SyncFailure::new(e, report));
+        }
+
+        let result = Self::remove_directory(config, &repository_target_dir)
+            .map(|output| report.git_output.push(output))
+            .map_err(SyncError::from);
+        report.into_result(result)
+    }
+
+    fn remove_directory(config: &Config, directory: &Path) -> Result<String, GitError> {
+        let to_io_error = |e: std::io::Error| GitError::IoError(e.to_string());
+        let (_, folder_name) = split_target_dir(directory)?;
+
+        if let Some(trash_dir) = config.trash_dir() {
+            std::fs::create_dir_all(trash_dir).map_err(to_io_error)?;
+            let destination = trash_dir.join(format!("{}-{}", folder_name, now_millis()));
+            std::fs::rename(directory, &destination).map_err(to_io_error)?;
+
+            Ok(format!(
+                "Moved '{}' to '{}'",
+                directory.display(),
+                destination.display()
+            ))
+        } else {
+            std::fs::remove_dir_all(directory).map_err(to_io_error)?;
+
+            Ok(format!("Removed '{}'", directory.display()))
+        }
+    }
+
+    /// Clone or update a checkout, appending the output of each Git command to `output`.
+    #[tracing::instrument(skip(output))]
     pub async fn create_or_update_in_directory(
         services: &ServiceHandler,
         backend: GitBackend,
         repo_full_name: &RepositoryPath,
         reference: RefType,
+        options: &SyncOptions,
         working_dir: &Path,
+        output: &mut Vec<String>,
     ) -> Result<(), GitError> {
-        let root_url = backend.root_url();
-        let repo_full_name = repo_full_name.full_name();
+        let commit = options.commit.as_deref();
+        let sparse_paths = &options.clone.sparse_paths;
+        let credentials = &options.credentials;
         let ref_name = reference.to_string();
-        let repo_url: String = format!("{root_url}/{repo_full_name}");
+        let repo_url = backend.clone_url(&repo_full_name.full_name(), options.protocol);
 
         if !working_dir.exists() {
-            // Get folder name
-            let folder_name = working_dir.file_stem().unwrap().to_string_lossy();
+            let (parent_dir, folder_name) = split_target_dir(working_dir)?;
+
+            // Templated target directories may need their parents to be created
+            std::fs::create_dir_all(parent_dir).map_err(|e| GitError::IoError(e.to_string()))?;
 
             // Clone the repository
-            services
-                .git()
-                .clone_repository(
-                    working_dir.parent().unwrap(),
-                    &ref_name,
-                    &repo_url,
-                    &folder_name,
-                )
-                .await?;
+            output.push(
+                services
+                    .git()
+                    .clone_repository(
+                        parent_dir,
+                        reference.clone_reference(),
+                        &repo_url,
+                        &folder_name,
+                        &options.clone,
+                        credentials,
+                    )
+                    .await?,
+            );
+
+            if options.clone.is_sparse() {
+                output.push(
+                    services
+                        .git()
+                        .sparse_checkout(working_dir, sparse_paths, credentials)
+                        .await?,
+                );
+            }
+
+            // Commits can not be cloned directly
+            if let RefType::Commit(sha) = &reference {
+                output.push(
+                    services
+                        .git()
+                        .checkout(working_dir, sha, credentials)
+                        .await?,
+                );
+            } else if let Some(commit) = commit {
+                // The reference may have moved since the push
+                output.push(
+                    services
+                        .git()
+                        .reset_hard(working_dir, commit, credentials)
+                        .await?,
+                );
+            }
         } else {
-            services.git().fetch(working_dir).await?;
-            services.git().checkout(working_dir, &ref_name).await?;
-            services.git().pull(working_dir).await?;
+            output.push(
+                services
+                    .git()
+                    .fetch(working_dir, &options.clone, credentials)
+                    .await?,
+            );
+
+            // Sparse paths may have changed since the clone
+            if options.clone.is_sparse() {
+                output.push(
+                    services
+                        .git()
+                        .sparse_checkout(working_dir, sparse_paths, credentials)
+                        .await?,
+                );
+            }
+
+            output.push(
+                services
+                    .git()
+                    .checkout(working_dir, &ref_name, credentials)
+                    .await?,
+            );
+
+            match (options.strategy, &reference) {
+                (SyncStrategy::Pull, RefType::Branch(_)) => {
+                    output.push(services.git().pull(working_dir, credentials).await?);
+                    if let Some(commit) = commit {
+                        output.push(
+                            services
+                                .git()
+                                .reset_hard(working_dir, commit, credentials)
+                                .await?,
+                        );
+                    }
+                }
+                // Nothing to pull on a detached HEAD
+                (SyncStrategy::Pull, _) => (),
+                (SyncStrategy::Reset | SyncStrategy::ResetClean, _) => {
+                    let revision = match commit {
+                        Some(c) => c.to_owned(),
+                        None => Self::get_reset_revision(&reference),
+                    };
+                    output.push(
+                        services
+                            .git()
+                            .reset_hard(working_dir, &revision, credentials)
+                            .await?,
+                    );
+
+                    if options.strategy == SyncStrategy::ResetClean {
+                        output.push(services.git().clean(working_dir).await?);
+                    }
+                }
+            }
+        }
+
+        if options.submodules {
+            output.push(
+                services
+                    .git()
+                    .update_submodules(working_dir, credentials)
+                    .await?,
+            );
+        }
+
+        if options.lfs {
+            output.push(services.git().lfs_pull(working_dir, credentials).await?);
         }
 
         Ok(())
     }
 
-    fn get_working_dir(config: &Config) -> PathBuf {
+    /// Get the fetched revision matching a reference, to reset a checkout to.
+    fn get_reset_revision(reference: &RefType) -> String {
+        match reference {
+            RefType::Branch(b) => format!("origin/{}", b),
+            RefType::Tag(t) => format!("refs/tags/{}", t),
+            RefType::Commit(c) => c.clone(),
+        }
+    }
+
+    pub(crate) fn get_working_dir(config: &Config) -> PathBuf {
         if let Some(d) = config.working_dir() {
             PathBuf::from(d)
         } else {
@@ -72,15 +450,707 @@ impl RepoCloner {
         }
     }
 
-    fn get_repository_target_dir(
+    /// Get the target directory of a repository reference.
+    /// Configured target directories can use the `{owner}`, `{repo}` and `{branch}` placeholders,
+    /// `{branch}` being the sanitized branch, tag or commit name.
+    pub(crate) fn get_repository_target_dir(
         config: &Config,
-        repo_full_name: &str,
-        folder_name: &str,
+        repo_path: &RepositoryPath,
+        reference_name: &str,
     ) -> PathBuf {
-        if let Some(value) = config.repo_mapping().get(repo_full_name) {
-            PathBuf::from(value)
+        if let Some(value) = config
+            .repository(&repo_path.full_name())
+            .and_then(|r| r.target_dir())
+        {
+            PathBuf::from(
+                value
+                    .to_string_lossy()
+                    .replace("{owner}", &sanitize_path_component(repo_path.owner()))
+                    .replace("{repo}", &sanitize_path_component(repo_path.name()))
+                    .replace("{branch}", &sanitize_path_component(reference_name)),
+            )
         } else {
-            Self::get_working_dir(config).join(folder_name)
+            Self::get_working_dir(config).join(repo_path.name())
+        }
+    }
+}
+
+/// Split a target directory into its parent directory and its folder name.
+/// Directories without both, like `/` or paths ending with `..`, can not be cloned into or removed.
+fn split_target_dir(directory: &Path) -> Result<(&Path, String), GitError> {
+    match (directory.parent(), directory.file_name()) {
+        (Some(parent), Some(name)) => Ok((parent, name.to_string_lossy().into_owned())),
+        _ => Err(GitError::InvalidTargetDir(directory.display().to_string())),
+    }
+}
+
+/// Make a name usable as a single path component: every character other than ASCII
+/// alphanumerics, `-`, `_` and `.` is replaced by `-`, and leading dots are replaced to avoid
+/// hidden or parent directories.
+/// When the name is changed, a short hash of the original name is appended so that distinct names
+/// never share a directory (e.g. `feature/x` becomes `feature-x-217d2bf5`, unlike `feature-x`).
+fn sanitize_path_component(value: &str) -> String {
+    let sanitized: String = value
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let trimmed = sanitized.trim_start_matches('.');
+    let prefix = "-".repeat(sanitized.len() - trimmed.len());
+    let sanitized = format!("{prefix}{trimmed}");
+    if sanitized == value {
+        return sanitized;
+    }
+
+    let hash = hex::encode(Sha256::digest(value.as_bytes()));
+    format!("{}-{}", sanitized, &hash[..8])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        num::NonZeroU32,
+        path::{Path, PathBuf},
+        sync::Arc,
+    };
+
+    use assert_matches::assert_matches;
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        actions::{Action, ShellExecutor},
+        config::Config,
+        git::{
+            CloneOptions, GitBackend, GitCredentials, GitError, RefType, ReleaseOptions, Releases,
+            RepositoryPath, SyncError, SyncFailure, SyncReport, SyncStrategy,
+        },
+        service::ServiceHandler,
+        testing::{
+            create_failed_action_error, create_test_config, create_test_services,
+            extract_fake_action_service, extract_fake_git_service, FakeGitService,
+        },
+    };
+
+    use super::{sanitize_path_component, RepoCloner};
+
+    #[test]
+    fn test_sanitize_path_component() {
+        assert_eq!(sanitize_path_component("main"), "main");
+        assert_eq!(sanitize_path_component("feature/x"), "feature-x-217d2bf5");
+        assert_eq!(sanitize_path_component("v1.0.0"), "v1.0.0");
+        assert_eq!(sanitize_path_component("../../etc"), "---..-etc-74ccf3c5");
+        assert_eq!(sanitize_path_component(".hidden"), "-hidden-16924190");
+        assert_eq!(
+            sanitize_path_component("fix #12 ~ é"),
+            "fix--12-----f4b1b164"
+        );
+    }
+
+    #[test]
+    fn test_sanitize_path_component_collisions() {
+        for (a, b) in [("feature/x", "feature-x"), ("a/b", "a.b"), ("a/b", "a b")] {
+            assert_ne!(sanitize_path_component(a), sanitize_path_component(b));
         }
     }
+
+    #[test]
+    fn test_get_repository_target_dir_template() {
+        let mut config = create_test_config();
+        config
+            .repository_mut("Srynetix/things")
+            .unwrap()
+            .set_target_dir("/srv/previews/{owner}/{repo}/{branch}");
+
+        let repo_path = RepositoryPath::new("Srynetix/things").unwrap();
+        assert_eq!(
+            RepoCloner::get_repository_target_dir(&config, &repo_path, "feature/x"),
+            Path::new("/srv/previews/Srynetix/things/feature-x-217d2bf5")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_clone_in_per_branch_directory() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut config = create_test_config();
+        config
+            .repository_mut("Srynetix/things")
+            .unwrap()
+            .set_target_dir(tempdir.path().join("previews/{repo}/{branch}"));
+
+        let services = create_test_services();
+        RepoCloner::create_or_update_using_config(
+            &config,
+            &services,
+            GitBackend::GitHub,
+            &RepositoryPath::new("Srynetix/things").unwrap(),
+            RefType::Branch("feature/x".into()),
+            false,
+            None,
+        )
+        .await
+        .unwrap();
+
+        let calls = extract_fake_git_service(&services).clone_repository.calls();
+        assert_eq!(calls[0].0, tempdir.path().join("previews/things"));
+        assert_eq!(calls[0].1.as_deref(), Some("feature/x"));
+        assert_eq!(calls[0].3, "feature-x-217d2bf5");
+        assert!(tempdir.path().join("previews/things").is_dir());
+    }
+
+    #[tokio::test]
+    async fn test_remove_per_branch_directory() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(tempdir.path().join("previews/feature-x-217d2bf5")).unwrap();
+
+        let mut config = create_test_config();
+        config
+            .repository_mut("Srynetix/things")
+            .unwrap()
+            .set_target_dir(tempdir.path().join("previews/{branch}"));
+        config
+            .repository_mut("Srynetix/things")
+            .unwrap()
+            .set_teardown_actions(vec![Action::new("teardown")]);
+
+        let services = create_test_services();
+        let report = RepoCloner::remove_using_config(
+            &config,
+            &services,
+            &RepositoryPath::new("Srynetix/things").unwrap(),
+            &RefType::Branch("feature/x".into()),
+        )
+        .await
+        .unwrap();
+
+        assert!(!tempdir.path().join("previews/feature-x-217d2bf5").exists());
+        assert_eq!(report.action_outputs.len(), 1);
+        let calls = extract_fake_action_service(&services).run.calls();
+        assert_eq!(
+            calls[0].0,
+            tempdir.path().join("previews/feature-x-217d2bf5")
+        );
+        assert_eq!(calls[0].1.command, "teardown");
+    }
+
+    #[tokio::test]
+    async fn test_remove_per_branch_directory_to_trash() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(tempdir.path().join("previews/feature-x-217d2bf5")).unwrap();
+
+        let mut config = create_test_config();
+        config.set_trash_dir(tempdir.path().join("trash"));
+        config
+            .repository_mut("Srynetix/things")
+            .unwrap()
+            .set_target_dir(tempdir.path().join("previews/{branch}"));
+
+        let services = create_test_services();
+        RepoCloner::remove_using_config(
+            &config,
+            &services,
+            &RepositoryPath::new("Srynetix/things").unwrap(),
+            &RefType::Branch("feature/x".into()),
+        )
+        .await
+        .unwrap();
+
+        assert!(!tempdir.path().join("previews/feature-x-217d2bf5").exists());
+        let trashed: Vec<_> = std::fs::read_dir(tempdir.path().join("trash"))
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(trashed.len(), 1);
+        assert!(trashed[0].starts_with("feature-x-217d2bf5-"));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_target_dir() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tempdir.path().join("existing")).unwrap();
+
+        let mut config = create_test_config();
+        let services = create_test_services();
+        let repo_path = RepositoryPath::new("Srynetix/things").unwrap();
+        let reference = RefType::Branch("main".into());
+
+        config
+            .repository_mut("Srynetix/things")
+            .unwrap()
+            .set_target_dir(tempdir.path().join("missing/.."));
+        assert_matches!(
+            RepoCloner::create_or_update_using_config(
+                &config,
+                &services,
+                GitBackend::GitHub,
+                &repo_path,
+                reference.clone(),
+                false,
+                None,
+            )
+            .await,
+            Err(SyncFailure {
+                error: SyncError::Git(GitError::InvalidTargetDir(_)),
+                ..
+            })
+        );
+
+        config
+            .repository_mut("Srynetix/things")
+            .unwrap()
+            .set_target_dir(tempdir.path().join("existing/.."));
+        assert_matches!(
+            RepoCloner::remove_using_config(&config, &services, &repo_path, &reference).await,
+            Err(SyncFailure {
+                error: SyncError::Git(GitError::InvalidTargetDir(_)),
+                ..
+            })
+        );
+        assert!(tempdir.path().join("existing").exists());
+    }
+
+    async fn update_existing_checkout(
+        strategy: Option<SyncStrategy>,
+        reference: RefType,
+        forced: bool,
+        commit: Option<&str>,
+    ) -> ServiceHandler {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tempdir.path().join("things")).unwrap();
+
+        let mut config = create_test_config();
+        config.set_working_dir(tempdir.path());
+        if let Some(strategy) = strategy {
+            config
+                .repository_mut("Srynetix/things")
+                .unwrap()
+                .set_sync_strategy(strategy);
+        }
+
+        let services = create_test_services();
+        RepoCloner::create_or_update_using_config(
+            &config,
+            &services,
+            GitBackend::GitHub,
+            &RepositoryPath::new("Srynetix/things").unwrap(),
+            reference,
+            forced,
+            commit,
+        )
+        .await
+        .unwrap();
+
+        services
+    }
+
+    #[tokio::test]
+    async fn test_clone_and_update_with_clone_options() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut config = create_test_config();
+        config.set_working_dir(tempdir.path());
+        let options = CloneOptions {
+            depth: NonZeroU32::new(1),
+            filter: Some("blob:none".into()),
+            single_branch: true,
+            sparse_paths: vec!["site".into()],
+        };
+        config
+            .repository_mut("Srynetix/things")
+            .unwrap()
+            .set_clone_options(options.clone());
+        let credentials = GitCredentials::Token {
+            username: Some("user".into()),
+            token: "secret-token".into(),
+            url: None,
+        };
+        config
+            .repository_mut("Srynetix/things")
+            .unwrap()
+            .set_credentials(credentials.clone());
+        let credentials = credentials.for_backend(&GitBackend::GitHub);
+
+        let services = create_test_services();
+        let repo_path = RepositoryPath::new("Srynetix/things").unwrap();
+        for _ in 0..2 {
+            RepoCloner::create_or_update_using_config(
+                &config,
+                &services,
+                GitBackend::GitHub,
+                &repo_path,
+                RefType::Branch("main".into()),
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+
+            // Simulate the clone
+            std::fs::create_dir_all(tempdir.path().join("things")).unwrap();
+        }
+
+        let git_service = extract_fake_git_service(&services);
+        assert_eq!(git_service.clone_repository.calls()[0].4, options);
+        assert_eq!(git_service.fetch.calls()[0].1, options);
+        let sparse_calls = git_service.sparse_checkout.calls();
+        assert_eq!(sparse_calls.len(), 2);
+        assert_eq!(sparse_calls[1].1, ["site"]);
+
+        // Missing blobs of partial clones are fetched lazily, so every step needs credentials
+        assert!(sparse_calls.iter().all(|c| c.2 == credentials));
+        assert_eq!(git_service.checkout.calls()[0].2, credentials);
+        assert_eq!(git_service.reset_hard.calls()[0].2, credentials);
+    }
+
+    #[tokio::test]
+    async fn test_clone_private_repository() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut config = create_test_config();
+        config.set_working_dir(tempdir.path());
+        config
+            .repository_mut("Srynetix/token")
+            .unwrap()
+            .set_credentials(GitCredentials::Token {
+                username: None,
+                token: "secret-token".into(),
+                url: None,
+            });
+        config
+            .repository_mut("Srynetix/deploy")
+            .unwrap()
+            .set_credentials(GitCredentials::SshKey("/keys/deploy".into()));
+
+        let services = create_test_services();
+        for name in ["Srynetix/token", "Srynetix/deploy"] {
+            RepoCloner::create_or_update_using_config(
+                &config,
+                &services,
+                GitBackend::GitLab,
+                &RepositoryPath::new(name).unwrap(),
+                RefType::Branch("main".into()),
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+        }
+
+        let calls = extract_fake_git_service(&services).clone_repository.calls();
+        assert_eq!(calls[0].2, "https://gitlab.com/Srynetix/token");
+        assert_eq!(
+            calls[0].5,
+            GitCredentials::Token {
+                username: Some("oauth2".into()),
+                token: "secret-token".into(),
+                url: Some("https://gitlab.com".into()),
+            }
+        );
+        assert_eq!(calls[1].2, "git@gitlab.com:Srynetix/deploy.git");
+        assert_eq!(
+            calls[1].5,
+            GitCredentials::SshKey(PathBuf::from("/keys/deploy"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_submodules_and_lfs() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut config = create_test_config();
+        config.set_working_dir(tempdir.path());
+        let repository = config.repository_mut("Srynetix/things").unwrap();
+        repository.set_submodules(true);
+        repository.set_lfs(true);
+
+        let services = create_test_services();
+        extract_fake_git_service(&services)
+            .lfs_pull
+            .return_value(Err(GitError::LfsPullError("missing git-lfs".into())));
+
+        let result = RepoCloner::create_or_update_using_config(
+            &config,
+            &services,
+            GitBackend::GitHub,
+            &RepositoryPath::new("Srynetix/things").unwrap(),
+            RefType::Branch("main".into()),
+            false,
+            None,
+        )
+        .await;
+
+        assert_matches!(
+            result,
+            Err(SyncFailure {
+                error: SyncError::Git(GitError::LfsPullError(e)),
+                report,
+            }) if e == "missing git-lfs" && report.git_output == ["OK", "OK"]
+        );
+        let git_service = extract_fake_git_service(&services);
+        assert!(git_service.update_submodules.called());
+        assert!(git_service.lfs_pull.called());
+    }
+
+    #[tokio::test]
+    async fn test_failed_action_keeps_outputs() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tempdir.path().join("things")).unwrap();
+
+        let mut config = create_test_config();
+        config.set_working_dir(tempdir.path());
+        config
+            .repository_mut("Srynetix/things")
+            .unwrap()
+            .set_actions(vec![
+                Action::new("echo installed"),
+                Action::new("echo building && echo broken >&2 && exit 2"),
+                Action::new("echo never"),
+            ]);
+
+        let services = ServiceHandler::new(
+            Arc::new(FakeGitService::new()),
+            Arc::new(ShellExecutor::new()),
+        );
+        let failure = RepoCloner::create_or_update_using_config(
+            &config,
+            &services,
+            GitBackend::GitHub,
+            &RepositoryPath::new("Srynetix/things").unwrap(),
+            RefType::Branch("main".into()),
+            false,
+            None,
+        )
+        .await
+        .unwrap_err();
+
+        assert_matches!(failure.error, SyncError::Action(_));
+        assert!(!failure.report.git_output.is_empty());
+        let outputs: Vec<_> = failure
+            .report
+            .action_outputs
+            .iter()
+            .map(|o| (o.stdout.as_str(), o.stderr.as_str(), o.status_code))
+            .collect();
+        assert_eq!(
+            outputs,
+            [("installed", "", Some(0)), ("building", "broken", Some(2))]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_with_reset_strategy() {
+        let services =
+            update_existing_checkout(None, RefType::Tag("v1.0".into()), false, None).await;
+
+        let git_service = extract_fake_git_service(&services);
+        assert!(git_service.fetch.called());
+        assert_eq!(git_service.checkout.calls()[0].1, "v1.0");
+        assert_eq!(git_service.reset_hard.calls()[0].1, "refs/tags/v1.0");
+        assert!(!git_service.clean.called());
+        assert!(!git_service.pull.called());
+    }
+
+    #[tokio::test]
+    async fn test_update_with_reset_clean_strategy() {
+        let services = update_existing_checkout(
+            Some(SyncStrategy::ResetClean),
+            RefType::Branch("main".into()),
+            false,
+            None,
+        )
+        .await;
+
+        let git_service = extract_fake_git_service(&services);
+        assert_eq!(git_service.reset_hard.calls()[0].1, "origin/main");
+        assert!(git_service.clean.called());
+        assert!(!git_service.pull.called());
+    }
+
+    #[tokio::test]
+    async fn test_update_to_pushed_commit() {
+        let services = update_existing_checkout(
+            None,
+            RefType::Branch("main".into()),
+            false,
+            Some("a10867b14bb761a232cd80139fbd4c0d33264240"),
+        )
+        .await;
+
+        let git_service = extract_fake_git_service(&services);
+        assert_eq!(git_service.checkout.calls()[0].1, "main");
+        assert_eq!(
+            git_service.reset_hard.calls()[0].1,
+            "a10867b14bb761a232cd80139fbd4c0d33264240"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_with_pull_strategy() {
+        let services = update_existing_checkout(
+            Some(SyncStrategy::Pull),
+            RefType::Branch("main".into()),
+            false,
+            None,
+        )
+        .await;
+
+        let git_service = extract_fake_git_service(&services);
+        assert!(git_service.pull.called());
+        assert!(!git_service.reset_hard.called());
+    }
+
+    #[tokio::test]
+    async fn test_update_forced_with_pull_strategy() {
+        let services = update_existing_checkout(
+            Some(SyncStrategy::Pull),
+            RefType::Branch("main".into()),
+            true,
+            None,
+        )
+        .await;
+
+        let git_service = extract_fake_git_service(&services);
+        assert!(!git_service.pull.called());
+        assert_eq!(git_service.reset_hard.calls()[0].1, "origin/main");
+    }
+
+    fn create_releases_config(working_dir: &Path) -> Config {
+        let mut config = create_test_config();
+        config.set_working_dir(working_dir);
+        config
+            .repository_mut("Srynetix/things")
+            .unwrap()
+            .set_releases(ReleaseOptions { keep: 2 });
+
+        // Simulate the clone
+        let checkout_dir = working_dir.join("things/repository");
+        std::fs::create_dir_all(&checkout_dir).unwrap();
+        std::fs::write(checkout_dir.join("index.html"), "hello").unwrap();
+        config
+    }
+
+    async fn sync_release(
+        config: &Config,
+        services: &ServiceHandler,
+    ) -> Result<SyncReport, SyncFailure> {
+        // Make sure release timestamps are different
+        tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        RepoCloner::create_or_update_using_config(
+            config,
+            services,
+            GitBackend::GitHub,
+            &RepositoryPath::new("Srynetix/things").unwrap(),
+            RefType::Branch("main".into()),
+            false,
+            None,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_deploy_releases() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let config = create_releases_config(tempdir.path());
+        let services = create_test_services();
+        let releases = Releases::new(&tempdir.path().join("things"));
+
+        let mut names = vec![];
+        for _ in 0..3 {
+            names.push(
+                sync_release(&config, &services)
+                    .await
+                    .unwrap()
+                    .release
+                    .unwrap(),
+            );
+        }
+
+        assert!(names[2].ends_with("-0d1a26e67d8f5eaf1f6ba5c57fc3c7d91ac0fd1c"));
+        assert_eq!(releases.list().unwrap(), &names[1..]);
+        assert_eq!(releases.current(), Some(names[2].clone()));
+        assert_eq!(
+            std::fs::read_to_string(releases.current_link().join("index.html")).unwrap(),
+            "hello"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_deploy_release_with_failed_action() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut config = create_releases_config(tempdir.path());
+        config
+            .repository_mut("Srynetix/things")
+            .unwrap()
+            .set_actions(vec![Action::new("npm run build")]);
+        let services = create_test_services();
+        let releases = Releases::new(&tempdir.path().join("things"));
+
+        // Actions run in the release directory
+        let first = sync_release(&config, &services).await.unwrap().release;
+        assert_eq!(
+            extract_fake_action_service(&services).run.calls()[0].0,
+            releases.release_dir(first.as_deref().unwrap())
+        );
+
+        extract_fake_action_service(&services)
+            .run
+            .return_value(Err(create_failed_action_error("npm run build")));
+        assert_matches!(
+            sync_release(&config, &services).await,
+            Err(SyncFailure {
+                error: SyncError::Action(_),
+                ..
+            })
+        );
+
+        // The failed release is removed, and the current one is kept
+        assert_eq!(releases.current(), first);
+        assert_eq!(releases.list().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rollback_release() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let config = create_releases_config(tempdir.path());
+        let services = create_test_services();
+        let repo_path = RepositoryPath::new("Srynetix/things").unwrap();
+        let reference = RefType::Branch("main".into());
+
+        let first = sync_release(&config, &services).await.unwrap().release;
+        let second = sync_release(&config, &services).await.unwrap().release;
+
+        let report =
+            RepoCloner::rollback_using_config(&config, &repo_path, &reference, None).unwrap();
+        assert_eq!(report.release, first);
+        assert_matches!(
+            RepoCloner::rollback_using_config(&config, &repo_path, &reference, None),
+            Err(SyncError::Git(GitError::NoPreviousRelease))
+        );
+
+        let report =
+            RepoCloner::rollback_using_config(&config, &repo_path, &reference, second.as_deref())
+                .unwrap();
+        assert_eq!(report.release, second);
+        assert_matches!(
+            RepoCloner::rollback_using_config(&config, &repo_path, &reference, Some("unknown")),
+            Err(SyncError::Git(GitError::UnknownRelease(_)))
+        );
+        assert_matches!(
+            RepoCloner::rollback_using_config(&create_test_config(), &repo_path, &reference, None),
+            Err(SyncError::Git(GitError::ReleasesDisabled(_)))
+        );
+    }
+
+    #[test]
+    fn test_get_repository_target_dir_default() {
+        let mut config = create_test_config();
+        config.set_working_dir("/srv/work");
+
+        let repo_path = RepositoryPath::new("Srynetix/things").unwrap();
+        assert_eq!(
+            RepoCloner::get_repository_target_dir(&config, &repo_path, "feature/x"),
+            Path::new("/srv/work/things")
+        );
+    }
 }
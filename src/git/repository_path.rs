@@ -2,7 +2,7 @@ use std::str::FromStr;
 
 use super::GitError;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RepositoryPath {
     owner: String,
     name: String,
@@ -30,13 +30,18 @@ impl RepositoryPath {
         format!("{}/{}", self.owner, self.name)
     }
 
+    /// Split a repository path in owner and name.
+    /// Owner can contain slashes, to support GitLab subgroups (e.g. `group/subgroup/repo`).
     fn split_repo_path(repo_path: &str) -> Result<(&str, &str), GitError> {
-        let split: Vec<_> = repo_path.split('/').collect();
-
-        if split.len() == 2 {
-            Ok((split[0], split[1]))
-        } else {
-            Err(GitError::MalformedRepositoryPath(repo_path.into()))
+        match repo_path.rsplit_once('/') {
+            Some((owner, name))
+                if !name.is_empty()
+                    && !owner.is_empty()
+                    && owner.split('/').all(|s| !s.is_empty()) =>
+            {
+                Ok((owner, name))
+            }
+            _ => Err(GitError::MalformedRepositoryPath(repo_path.into())),
         }
     }
 }
@@ -3,27 +3,79 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use super::GitError;
+use super::{CloneOptions, GitCredentials, GitError};
 use async_trait::async_trait;
 use tokio::process::Command;
 use which::which;
 
 #[async_trait]
 pub trait GitService: std::fmt::Debug + Send + Sync {
+    /// Clone a repository, on a specific branch or tag if `reference` is set.
     async fn clone_repository(
         &self,
         working_dir: &Path,
-        reference: &str,
+        reference: Option<&str>,
         url: &str,
         folder_name: &str,
+        options: &CloneOptions,
+        credentials: &GitCredentials,
+    ) -> Result<String, GitError>;
+    async fn fetch(
+        &self,
+        working_dir: &Path,
+        options: &CloneOptions,
+        credentials: &GitCredentials,
+    ) -> Result<String, GitError>;
+    /// Restrict the checkout to some directories.
+    /// Like checkouts and resets, this may fetch missing objects from a partial clone remote.
+    async fn sparse_checkout(
+        &self,
+        working_dir: &Path,
+        paths: &[String],
+        credentials: &GitCredentials,
+    ) -> Result<String, GitError>;
+    async fn checkout(
+        &self,
+        working_dir: &Path,
+        reference: &str,
+        credentials: &GitCredentials,
+    ) -> Result<String, GitError>;
+    async fn pull(
+        &self,
+        working_dir: &Path,
+        credentials: &GitCredentials,
+    ) -> Result<String, GitError>;
+    /// Reset the checkout, index and working tree to a specific revision.
+    async fn reset_hard(
+        &self,
+        working_dir: &Path,
+        revision: &str,
+        credentials: &GitCredentials,
+    ) -> Result<String, GitError>;
+    /// Remove untracked and ignored files.
+    async fn clean(&self, working_dir: &Path) -> Result<String, GitError>;
+    /// Initialize and update submodules, recursively.
+    async fn update_submodules(
+        &self,
+        working_dir: &Path,
+        credentials: &GitCredentials,
+    ) -> Result<String, GitError>;
+    /// Download Git LFS files of the current checkout.
+    async fn lfs_pull(
+        &self,
+        working_dir: &Path,
+        credentials: &GitCredentials,
     ) -> Result<String, GitError>;
-    async fn fetch(&self, working_dir: &Path) -> Result<String, GitError>;
-    async fn checkout(&self, working_dir: &Path, reference: &str) -> Result<String, GitError>;
-    async fn pull(&self, working_dir: &Path) -> Result<String, GitError>;
+    /// Get the commit hash currently checked out.
+    async fn head_commit(&self, working_dir: &Path) -> Result<String, GitError>;
 
     fn as_any(&self) -> &dyn Any;
 }
 
+/// Inline credential helper answering with the token from the environment.
+const CREDENTIAL_HELPER: &str =
+    "!f() { echo \"username=$HR_GIT_USERNAME\"; echo \"password=$HR_GIT_TOKEN\"; }; f";
+
 #[derive(Debug)]
 pub struct GitExecutable {
     binary_path: PathBuf,
@@ -36,14 +88,62 @@ impl GitExecutable {
         Ok(Self { binary_path })
     }
 
-    #[tracing::instrument(skip(self))]
     async fn execute(
         &self,
         working_directory: &Path,
         command: &str,
         args: &[&str],
     ) -> Result<String, GitError> {
-        let output = Command::new(&self.binary_path)
+        self.execute_with_credentials(working_directory, command, args, &GitCredentials::None)
+            .await
+    }
+
+    /// Run a Git command which may reach a remote, including lazy fetches of partial clones.
+    ///
+    /// Secrets are only passed through the environment of the child process,
+    /// never as arguments, so they cannot leak in logs or process listings.
+    #[tracing::instrument(skip(self, credentials))]
+    async fn execute_with_credentials(
+        &self,
+        working_directory: &Path,
+        command: &str,
+        args: &[&str],
+        credentials: &GitCredentials,
+    ) -> Result<String, GitError> {
+        let mut git = Command::new(&self.binary_path);
+        // Never wait for a password on a terminal
+        git.env("GIT_TERMINAL_PROMPT", "0");
+
+        match credentials {
+            GitCredentials::None => {}
+            GitCredentials::Token {
+                username,
+                token,
+                url,
+            } => {
+                // Scope the helper to the backend origin, so the token is never sent to other hosts
+                let helper_key = match url {
+                    Some(url) => format!("credential.{}.helper", url),
+                    None => "credential.helper".into(),
+                };
+                git.args(["-c", "credential.helper="])
+                    .arg("-c")
+                    .arg(format!("{}={}", helper_key, CREDENTIAL_HELPER))
+                    .env("HR_GIT_USERNAME", username.as_deref().unwrap_or("git"))
+                    .env("HR_GIT_TOKEN", token);
+            }
+            GitCredentials::SshKey(key) => {
+                git.env(
+                    "GIT_SSH_COMMAND",
+                    format!(
+                        "ssh -i '{}' -o IdentitiesOnly=yes -o StrictHostKeyChecking=accept-new",
+                        key.display()
+                    ),
+                );
+            }
+        }
+
+        let output = git
             .arg(command)
             .args(args)
             .current_dir(working_directory)
@@ -74,27 +174,145 @@ impl GitService for GitExecutable {
     async fn clone_repository(
         &self,
         working_dir: &Path,
-        reference: &str,
+        reference: Option<&str>,
         url: &str,
         folder_name: &str,
+        options: &CloneOptions,
+        credentials: &GitCredentials,
     ) -> Result<String, GitError> {
-        self.execute(working_dir, "clone", &["-b", reference, url, folder_name])
+        let mut args = options.clone_args();
+        if let Some(reference) = reference {
+            args.extend(["-b".into(), reference.into()]);
+        }
+        args.extend([url.into(), folder_name.into()]);
+
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        self.execute_with_credentials(working_dir, "clone", &args, credentials)
             .await
     }
 
-    async fn fetch(&self, working_dir: &Path) -> Result<String, GitError> {
-        self.execute(working_dir, "fetch", &[]).await
+    async fn fetch(
+        &self,
+        working_dir: &Path,
+        options: &CloneOptions,
+        credentials: &GitCredentials,
+    ) -> Result<String, GitError> {
+        // Force tag updates, so moved tags are mirrored too
+        let mut args = vec!["--tags".to_string(), "--force".to_string()];
+        args.extend(options.fetch_args());
+
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        self.execute_with_credentials(working_dir, "fetch", &args, credentials)
+            .await
     }
 
-    async fn checkout(&self, working_dir: &Path, reference: &str) -> Result<String, GitError> {
-        self.execute(working_dir, "checkout", &[reference]).await
+    async fn sparse_checkout(
+        &self,
+        working_dir: &Path,
+        paths: &[String],
+        credentials: &GitCredentials,
+    ) -> Result<String, GitError> {
+        let mut args = vec!["set"];
+        args.extend(paths.iter().map(String::as_str));
+
+        self.execute_with_credentials(working_dir, "sparse-checkout", &args, credentials)
+            .await
+    }
+
+    async fn checkout(
+        &self,
+        working_dir: &Path,
+        reference: &str,
+        credentials: &GitCredentials,
+    ) -> Result<String, GitError> {
+        // References are never interpreted as paths
+        self.execute_with_credentials(working_dir, "checkout", &[reference, "--"], credentials)
+            .await
+    }
+
+    async fn pull(
+        &self,
+        working_dir: &Path,
+        credentials: &GitCredentials,
+    ) -> Result<String, GitError> {
+        self.execute_with_credentials(working_dir, "pull", &[], credentials)
+            .await
+    }
+
+    async fn reset_hard(
+        &self,
+        working_dir: &Path,
+        revision: &str,
+        credentials: &GitCredentials,
+    ) -> Result<String, GitError> {
+        self.execute_with_credentials(
+            working_dir,
+            "reset",
+            &["--hard", revision, "--"],
+            credentials,
+        )
+        .await
     }
 
-    async fn pull(&self, working_dir: &Path) -> Result<String, GitError> {
-        self.execute(working_dir, "pull", &[]).await
+    async fn clean(&self, working_dir: &Path) -> Result<String, GitError> {
+        self.execute(working_dir, "clean", &["-fdx"]).await
+    }
+
+    async fn update_submodules(
+        &self,
+        working_dir: &Path,
+        credentials: &GitCredentials,
+    ) -> Result<String, GitError> {
+        self.execute_with_credentials(
+            working_dir,
+            "submodule",
+            &["update", "--init", "--recursive"],
+            credentials,
+        )
+        .await
+        .map_err(|e| match e {
+            GitError::GitExecutionError(e) => GitError::SubmoduleUpdateError(e),
+            e => e,
+        })
+    }
+
+    async fn lfs_pull(
+        &self,
+        working_dir: &Path,
+        credentials: &GitCredentials,
+    ) -> Result<String, GitError> {
+        self.execute_with_credentials(working_dir, "lfs", &["pull"], credentials)
+            .await
+            .map_err(|e| match e {
+                GitError::GitExecutionError(e) => GitError::LfsPullError(e),
+                e => e,
+            })
+    }
+
+    async fn head_commit(&self, working_dir: &Path) -> Result<String, GitError> {
+        self.execute(working_dir, "rev-parse", &["HEAD"]).await
     }
 
     fn as_any(&self) -> &dyn Any {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::testing::{check_git_service, check_webhook_synchronization};
+
+    use super::GitExecutable;
+
+    #[tokio::test]
+    async fn test_git_executable() {
+        check_git_service(Arc::new(GitExecutable::new().unwrap())).await;
+    }
+
+    #[tokio::test]
+    async fn test_git_executable_webhooks() {
+        check_webhook_synchronization(Arc::new(GitExecutable::new().unwrap())).await;
+    }
+}
@@ -0,0 +1,43 @@
+use std::{fmt, str::FromStr};
+
+use super::GitError;
+
+/// How an existing checkout is updated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncStrategy {
+    /// `git pull`, which fails or creates merge commits on force-pushes and local modifications.
+    Pull,
+    /// `git fetch` then `git reset --hard`, mirroring the pushed commit.
+    Reset,
+    /// Same as `Reset`, also removing untracked and ignored files with `git clean -fdx`.
+    ResetClean,
+}
+
+impl Default for SyncStrategy {
+    fn default() -> Self {
+        Self::Reset
+    }
+}
+
+impl fmt::Display for SyncStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pull => f.write_str("pull"),
+            Self::Reset => f.write_str("reset"),
+            Self::ResetClean => f.write_str("reset-clean"),
+        }
+    }
+}
+
+impl FromStr for SyncStrategy {
+    type Err = GitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pull" => Ok(Self::Pull),
+            "reset" => Ok(Self::Reset),
+            "reset-clean" => Ok(Self::ResetClean),
+            other => Err(GitError::UnsupportedSyncStrategy(other.into())),
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use crate::{config::Config, git::RepoCloner};
+
+use super::{
+    store::{JsonLinesFile, HISTORY_DIRECTORY},
+    HistoryError,
+};
+
+const DELIVERY_IDS_FILE: &str = "delivery_ids.jsonl";
+
+#[derive(Debug)]
+struct DeliveryCacheInner {
+    capacity: usize,
+    /// Seen identifiers, oldest first.
+    ids: VecDeque<String>,
+    known: HashSet<String>,
+    /// Identifiers being handled, not confirmed yet.
+    pending: HashSet<String>,
+    file: Option<JsonLinesFile>,
+}
+
+/// Bounded cache of seen webhook delivery identifiers, used to reject replayed deliveries.
+/// It can be persisted, to survive restarts. A disabled cache accepts every delivery.
+#[derive(Debug, Clone, Default)]
+pub struct DeliveryCache {
+    inner: Option<Arc<Mutex<DeliveryCacheInner>>>,
+}
+
+impl DeliveryCache {
+    /// Create an in-memory cache, remembering at most `capacity` identifiers.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_file(capacity, vec![], None)
+    }
+
+    /// Open a cache persisted in a directory, remembering at most `capacity` identifiers.
+    pub fn open(directory: &Path, capacity: usize) -> Result<Self, HistoryError> {
+        std::fs::create_dir_all(directory).map_err(|e| {
+            HistoryError::CouldNotCreateDirectory(directory.to_owned(), e.to_string())
+        })?;
+
+        let file = JsonLinesFile::open(directory.join(DELIVERY_IDS_FILE), capacity)?;
+        let mut ids: Vec<String> = file.read()?;
+        ids.reverse();
+
+        Ok(Self::with_file(capacity, ids, Some(file)))
+    }
+
+    /// Create the cache described by the configuration, persisted in the working directory if needed.
+    pub fn from_config(config: &Config) -> Result<Self, HistoryError> {
+        match config.replay_cache_size() {
+            0 => Ok(Self::disabled()),
+            capacity if config.persist_replay_cache() => Self::open(
+                &RepoCloner::get_working_dir(config).join(HISTORY_DIRECTORY),
+                capacity,
+            ),
+            capacity => Ok(Self::new(capacity)),
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self { inner: None }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    fn with_file(capacity: usize, ids: Vec<String>, file: Option<JsonLinesFile>) -> Self {
+        if capacity == 0 {
+            return Self::disabled();
+        }
+
+        Self {
+            inner: Some(Arc::new(Mutex::new(DeliveryCacheInner {
+                capacity,
+                known: ids.iter().cloned().collect(),
+                ids: ids.into(),
+                pending: HashSet::new(),
+                file,
+            }))),
+        }
+    }
+
+    /// Reserve a delivery identifier while the delivery is handled.
+    /// Returns `None` if this identifier has already been seen, or is being handled.
+    /// The reservation is released when dropped, unless it has been confirmed.
+    pub fn reserve(&self, id: &str) -> Option<DeliveryReservation> {
+        let reserved = match &self.inner {
+            Some(inner) => {
+                let mut inner = inner.lock().unwrap();
+                !inner.known.contains(id) && inner.pending.insert(id.to_owned())
+            }
+            None => true,
+        };
+
+        reserved.then(|| DeliveryReservation {
+            cache: self.clone(),
+            id: id.to_owned(),
+            confirmed: false,
+        })
+    }
+
+    fn confirm(&self, id: &str) {
+        if let Some(inner) = &self.inner {
+            let mut inner = inner.lock().unwrap();
+            inner.pending.remove(id);
+            if !inner.known.insert(id.to_owned()) {
+                return;
+            }
+
+            inner.ids.push_back(id.to_owned());
+            while inner.ids.len() > inner.capacity {
+                if let Some(oldest) = inner.ids.pop_front() {
+                    inner.known.remove(&oldest);
+                }
+            }
+
+            if let Some(file) = &mut inner.file {
+                if let Err(e) = file.append(&id) {
+                    tracing::error!(message = "Could not record delivery identifier", error = %e);
+                }
+            }
+        }
+    }
+
+    fn release(&self, id: &str) {
+        if let Some(inner) = &self.inner {
+            inner.lock().unwrap().pending.remove(id);
+        }
+    }
+}
+
+/// Delivery identifier reserved in a [`DeliveryCache`].
+/// It is forgotten when dropped, so a failed or cancelled delivery may be retried.
+#[derive(Debug)]
+pub struct DeliveryReservation {
+    cache: DeliveryCache,
+    id: String,
+    confirmed: bool,
+}
+
+impl DeliveryReservation {
+    /// Remember the identifier, once its delivery has been handled.
+    pub fn confirm(mut self) {
+        self.cache.confirm(&self.id);
+        self.confirmed = true;
+    }
+}
+
+impl Drop for DeliveryReservation {
+    fn drop(&mut self) {
+        if !self.confirmed {
+            self.cache.release(&self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeliveryCache;
+
+    #[test]
+    fn test_reserve_confirm_release() {
+        let cache = DeliveryCache::new(10);
+
+        let reservation = cache.reserve("first").unwrap();
+        // Concurrent deliveries with the same identifier are rejected too
+        assert!(cache.reserve("first").is_none());
+        reservation.confirm();
+        assert!(cache.reserve("first").is_none());
+
+        // Dropped reservations are released
+        let reservation = cache.reserve("second").unwrap();
+        drop(reservation);
+        assert!(cache.reserve("second").is_some());
+    }
+
+    #[test]
+    fn test_capacity() {
+        let cache = DeliveryCache::new(2);
+        for id in ["0", "1", "2"] {
+            cache.reserve(id).unwrap().confirm();
+        }
+
+        assert!(cache.reserve("0").is_some());
+        assert!(cache.reserve("1").is_none());
+        assert!(cache.reserve("2").is_none());
+    }
+
+    #[test]
+    fn test_persisted_cache() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let cache = DeliveryCache::open(tempdir.path(), 10).unwrap();
+        cache.reserve("first").unwrap().confirm();
+
+        // Identifiers should survive a restart
+        let cache = DeliveryCache::open(tempdir.path(), 10).unwrap();
+        assert!(cache.reserve("first").is_none());
+    }
+
+    #[test]
+    fn test_disabled_cache() {
+        let cache = DeliveryCache::new(0);
+        assert!(!cache.is_enabled());
+        cache.reserve("first").unwrap().confirm();
+        assert!(cache.reserve("first").is_some());
+    }
+}
@@ -0,0 +1,13 @@
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum HistoryError {
+    #[error("Could not create history directory '{0}': {1}")]
+    CouldNotCreateDirectory(PathBuf, String),
+    #[error("Could not read history file '{0}': {1}")]
+    CouldNotReadFile(PathBuf, String),
+    #[error("Could not write history file '{0}': {1}")]
+    CouldNotWriteFile(PathBuf, String),
+}
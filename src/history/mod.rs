@@ -0,0 +1,12 @@
+//! Persistent history of received deliveries and executed jobs,
+//! and cache of seen delivery identifiers.
+
+mod delivery_cache;
+mod error;
+mod models;
+mod store;
+
+pub use delivery_cache::{DeliveryCache, DeliveryReservation};
+pub use error::HistoryError;
+pub use models::Delivery;
+pub use store::HistoryStore;
@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+
+use crate::jobs::{generate_id, now_millis, JobId};
+
+/// Webhook delivery received from a Git hosting backend.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Delivery {
+    /// Delivery identifier sent by the backend, or a generated one if missing.
+    pub id: String,
+    pub backend: String,
+    pub event: String,
+    pub repository: Option<String>,
+    pub reference: Option<String>,
+    pub head_commit: Option<String>,
+    /// Timestamp, in milliseconds since the Unix epoch.
+    pub received_at: u64,
+    pub job_ids: Vec<JobId>,
+    /// Reasons of skipped synchronizations.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped: Vec<String>,
+    pub error: Option<String>,
+}
+
+impl Delivery {
+    pub fn new(backend: &str, event: &str, id: Option<&str>) -> Self {
+        Self {
+            id: id.map(ToOwned::to_owned).unwrap_or_else(generate_id),
+            backend: backend.into(),
+            event: event.into(),
+            repository: None,
+            reference: None,
+            head_commit: None,
+            received_at: now_millis(),
+            job_ids: vec![],
+            skipped: vec![],
+            error: None,
+        }
+    }
+
+    /// Set the repository, reference and commit targeted by the delivery.
+    pub fn set_target(&mut self, repository: &str, reference: &str, head_commit: Option<&str>) {
+        self.repository = Some(repository.into());
+        self.reference = Some(reference.into());
+        self.head_commit = head_commit.map(ToOwned::to_owned);
+    }
+}
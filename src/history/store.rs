@@ -0,0 +1,265 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{config::Config, git::RepoCloner, jobs::Job};
+
+use super::{Delivery, HistoryError};
+
+pub(super) const HISTORY_DIRECTORY: &str = ".hookrunner";
+const DELIVERIES_FILE: &str = "deliveries.jsonl";
+const JOBS_FILE: &str = "jobs.jsonl";
+
+/// Append-only JSON-lines file, compacted when it grows past twice the retention limit.
+#[derive(Debug)]
+pub(super) struct JsonLinesFile {
+    path: PathBuf,
+    retention: usize,
+    entries: usize,
+}
+
+impl JsonLinesFile {
+    pub(super) fn open(path: PathBuf, retention: usize) -> Result<Self, HistoryError> {
+        let entries = if path.exists() {
+            Self::read_lines(&path)?.len()
+        } else {
+            0
+        };
+
+        Ok(Self {
+            path,
+            retention,
+            entries,
+        })
+    }
+
+    pub(super) fn append<T: Serialize>(&mut self, entry: &T) -> Result<(), HistoryError> {
+        let line = serde_json::to_string(entry)
+            .map_err(|e| HistoryError::CouldNotWriteFile(self.path.clone(), e.to_string()))?;
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut f| writeln!(f, "{}", line))
+            .map_err(|e| HistoryError::CouldNotWriteFile(self.path.clone(), e.to_string()))?;
+        self.entries += 1;
+
+        if self.entries > self.retention * 2 {
+            self.compact()?;
+        }
+
+        Ok(())
+    }
+
+    /// Read the most recent entries, most recent first.
+    /// Malformed lines (e.g. from an interrupted write) are ignored.
+    pub(super) fn read<T: DeserializeOwned>(&self) -> Result<Vec<T>, HistoryError> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+
+        Ok(Self::read_lines(&self.path)?
+            .iter()
+            .rev()
+            .filter_map(|l| serde_json::from_str(l).ok())
+            .take(self.retention)
+            .collect())
+    }
+
+    /// Only keep the most recent entries, replacing the file atomically.
+    fn compact(&mut self) -> Result<(), HistoryError> {
+        let lines = Self::read_lines(&self.path)?;
+        let kept = &lines[lines.len().saturating_sub(self.retention)..];
+
+        let temp_path = self.path.with_extension("jsonl.tmp");
+        let write_error =
+            |e: std::io::Error| HistoryError::CouldNotWriteFile(self.path.clone(), e.to_string());
+        let mut file = File::create(&temp_path).map_err(write_error)?;
+        for line in kept {
+            writeln!(file, "{}", line).map_err(write_error)?;
+        }
+        std::fs::rename(&temp_path, &self.path).map_err(write_error)?;
+
+        self.entries = kept.len();
+        Ok(())
+    }
+
+    fn read_lines(path: &Path) -> Result<Vec<String>, HistoryError> {
+        let file = File::open(path)
+            .map_err(|e| HistoryError::CouldNotReadFile(path.to_owned(), e.to_string()))?;
+
+        BufReader::new(file)
+            .lines()
+            .filter(|l| !matches!(l, Ok(l) if l.trim().is_empty()))
+            .collect::<Result<_, _>>()
+            .map_err(|e| HistoryError::CouldNotReadFile(path.to_owned(), e.to_string()))
+    }
+}
+
+#[derive(Debug)]
+struct HistoryStoreInner {
+    deliveries: Mutex<JsonLinesFile>,
+    jobs: Mutex<JsonLinesFile>,
+}
+
+/// Persistent store for deliveries and jobs, as JSON-lines files.
+/// A disabled store does not record anything.
+#[derive(Debug, Clone, Default)]
+pub struct HistoryStore {
+    inner: Option<Arc<HistoryStoreInner>>,
+}
+
+impl HistoryStore {
+    /// Open a history store in a directory, keeping at most `retention` entries of each kind.
+    pub fn open(directory: &Path, retention: usize) -> Result<Self, HistoryError> {
+        std::fs::create_dir_all(directory).map_err(|e| {
+            HistoryError::CouldNotCreateDirectory(directory.to_owned(), e.to_string())
+        })?;
+
+        Ok(Self {
+            inner: Some(Arc::new(HistoryStoreInner {
+                deliveries: Mutex::new(JsonLinesFile::open(
+                    directory.join(DELIVERIES_FILE),
+                    retention,
+                )?),
+                jobs: Mutex::new(JsonLinesFile::open(directory.join(JOBS_FILE), retention)?),
+            })),
+        })
+    }
+
+    /// Open the history store located in the working directory, unless history is disabled.
+    pub fn from_config(config: &Config) -> Result<Self, HistoryError> {
+        match config.history_retention() {
+            0 => Ok(Self::disabled()),
+            retention => Self::open(
+                &RepoCloner::get_working_dir(config).join(HISTORY_DIRECTORY),
+                retention,
+            ),
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self { inner: None }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    /// Record a delivery. Errors are logged, as they should not fail the delivery itself.
+    pub fn record_delivery(&self, delivery: &Delivery) {
+        if let Some(inner) = &self.inner {
+            if let Err(e) = inner.deliveries.lock().unwrap().append(delivery) {
+                tracing::error!(message = "Could not record delivery", error = %e);
+            }
+        }
+    }
+
+    /// Record a finished job. Errors are logged, as they should not fail the job itself.
+    pub fn record_job(&self, job: &Job) {
+        if let Some(inner) = &self.inner {
+            if let Err(e) = inner.jobs.lock().unwrap().append(job) {
+                tracing::error!(message = "Could not record job", error = %e);
+            }
+        }
+    }
+
+    /// Get recorded deliveries, most recent first.
+    pub fn deliveries(&self) -> Result<Vec<Delivery>, HistoryError> {
+        match &self.inner {
+            Some(inner) => inner.deliveries.lock().unwrap().read(),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Get recorded jobs, most recent first.
+    pub fn jobs(&self) -> Result<Vec<Job>, HistoryError> {
+        match &self.inner {
+            Some(inner) => inner.jobs.lock().unwrap().read(),
+            None => Ok(vec![]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::history::Delivery;
+
+    use super::{HistoryStore, DELIVERIES_FILE};
+
+    fn create_delivery(id: &str) -> Delivery {
+        let mut delivery = Delivery::new("github", "push", Some(id));
+        delivery.set_target("Srynetix/things", "refs/heads/main", Some("abcdef"));
+        delivery
+    }
+
+    #[test]
+    fn test_record_deliveries() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let store = HistoryStore::open(tempdir.path(), 10).unwrap();
+
+        store.record_delivery(&create_delivery("first"));
+        store.record_delivery(&create_delivery("second"));
+
+        let deliveries = store.deliveries().unwrap();
+        assert_eq!(deliveries.len(), 2);
+        assert_eq!(deliveries[0].id, "second");
+        assert_eq!(deliveries[1].id, "first");
+        assert_eq!(deliveries[1].repository.as_deref(), Some("Srynetix/things"));
+        assert_eq!(deliveries[1].head_commit.as_deref(), Some("abcdef"));
+
+        // History should survive a restart
+        let store = HistoryStore::open(tempdir.path(), 10).unwrap();
+        assert_eq!(store.deliveries().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_retention() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let store = HistoryStore::open(tempdir.path(), 2).unwrap();
+
+        for i in 0..5 {
+            store.record_delivery(&create_delivery(&i.to_string()));
+        }
+
+        let deliveries = store.deliveries().unwrap();
+        assert_eq!(
+            deliveries.iter().map(|d| d.id.as_str()).collect::<Vec<_>>(),
+            vec!["4", "3"]
+        );
+
+        // File has been compacted once it reached twice the retention
+        let content = std::fs::read_to_string(tempdir.path().join(DELIVERIES_FILE)).unwrap();
+        assert_eq!(content.lines().count(), 2);
+    }
+
+    #[test]
+    fn test_malformed_lines_are_ignored() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::write(tempdir.path().join(DELIVERIES_FILE), "{\"id\": \n\n").unwrap();
+
+        let store = HistoryStore::open(tempdir.path(), 10).unwrap();
+        store.record_delivery(&create_delivery("first"));
+
+        let deliveries = store.deliveries().unwrap();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].id, "first");
+    }
+
+    #[test]
+    fn test_disabled_store() {
+        let store = HistoryStore::disabled();
+        store.record_delivery(&create_delivery("first"));
+
+        assert!(!store.is_enabled());
+        assert!(store.deliveries().unwrap().is_empty());
+    }
+}
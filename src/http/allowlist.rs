@@ -0,0 +1,365 @@
+use std::{
+    net::IpAddr,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use axum::http::HeaderMap;
+use ipnet::IpNet;
+
+use crate::{backends::github, config::Config};
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+const GITHUB_RANGES_REFRESH_INTERVAL: Duration = Duration::from_secs(3600);
+const GITHUB_RANGES_FIRST_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Default)]
+struct IpAllowlistInner {
+    allowed_ips: Vec<IpNet>,
+    github_hook_ips: Option<RwLock<Vec<IpNet>>>,
+    trusted_proxies: Vec<IpNet>,
+}
+
+/// Source IP ranges allowed to send webhook deliveries: configured ranges,
+/// and optionally the hook ranges published by GitHub, cached and refreshed periodically.
+/// A disabled allowlist accepts every source.
+#[derive(Debug, Clone, Default)]
+pub struct IpAllowlist {
+    inner: Option<Arc<IpAllowlistInner>>,
+}
+
+impl IpAllowlist {
+    pub fn new(
+        allowed_ips: Vec<IpNet>,
+        allow_github_hook_ips: bool,
+        trusted_proxies: Vec<IpNet>,
+    ) -> Self {
+        if allowed_ips.is_empty() && !allow_github_hook_ips {
+            return Self::disabled();
+        }
+
+        Self {
+            inner: Some(Arc::new(IpAllowlistInner {
+                allowed_ips,
+                github_hook_ips: allow_github_hook_ips.then(|| RwLock::new(vec![])),
+                trusted_proxies,
+            })),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(
+            config.allowed_ips().to_vec(),
+            config.allow_github_hook_ips(),
+            config.trusted_proxies().to_vec(),
+        )
+    }
+
+    pub fn disabled() -> Self {
+        Self { inner: None }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    /// Get the allowlist for other backends than GitHub, only allowing configured ranges.
+    pub fn without_github_hook_ips(&self) -> Self {
+        match &self.inner {
+            Some(inner) => Self::new(
+                inner.allowed_ips.clone(),
+                false,
+                inner.trusted_proxies.clone(),
+            ),
+            None => Self::disabled(),
+        }
+    }
+
+    /// Check if a delivery is allowed, from the peer address and the request headers.
+    /// Returns the resolved client address when it is not allowed.
+    pub fn check(&self, peer: Option<IpAddr>, headers: &HeaderMap) -> Result<(), Option<IpAddr>> {
+        let inner = match &self.inner {
+            Some(inner) => inner,
+            None => return Ok(()),
+        };
+
+        let client = peer.and_then(|peer| inner.client_ip(peer, headers));
+        match client {
+            Some(ip) if inner.is_allowed(ip) => Ok(()),
+            other => Err(other),
+        }
+    }
+
+    /// Replace the cached GitHub hook ranges by the ones currently published by GitHub.
+    /// Cached ranges are kept on error.
+    /// Returns `false` when the ranges could not be fetched.
+    pub async fn refresh_github_hook_ips(&self, config: &Config) -> bool {
+        let ranges = match self.inner.as_ref().and_then(|i| i.github_hook_ips.as_ref()) {
+            Some(ranges) => ranges,
+            None => return true,
+        };
+
+        match github::fetch_hook_ip_ranges(config).await {
+            Ok(r) => {
+                tracing::info!(message = "GitHub hook IP ranges refreshed", count = r.len());
+                *ranges.write().unwrap() = r;
+                true
+            }
+            Err(e) => {
+                tracing::error!(message = "Could not fetch GitHub hook IP ranges", error = %e);
+                false
+            }
+        }
+    }
+
+    /// Retry to refresh GitHub hook ranges until it succeeds, doubling the delay between
+    /// attempts up to the refresh interval.
+    async fn retry_github_hook_ips_refresh(&self, config: &Config, mut delay: Duration) {
+        loop {
+            tracing::info!(message = "Retrying GitHub hook IP ranges fetch", delay = ?delay);
+            tokio::time::sleep(delay).await;
+            if self.refresh_github_hook_ips(config).await {
+                return;
+            }
+            delay = (delay * 2).min(GITHUB_RANGES_REFRESH_INTERVAL);
+        }
+    }
+
+    /// Fetch GitHub hook ranges now, then refresh them periodically in the background.
+    /// A failed first fetch is retried in the background with backoff, as every GitHub delivery
+    /// is rejected until the ranges are known.
+    pub async fn start_github_hook_ips_refresh(&self, config: Config) {
+        if !self
+            .inner
+            .as_ref()
+            .map_or(false, |i| i.github_hook_ips.is_some())
+        {
+            return;
+        }
+
+        let refreshed = self.refresh_github_hook_ips(&config).await;
+
+        let allowlist = self.clone();
+        tokio::spawn(async move {
+            if !refreshed {
+                allowlist
+                    .retry_github_hook_ips_refresh(&config, GITHUB_RANGES_FIRST_RETRY_DELAY)
+                    .await;
+            }
+
+            let mut interval = tokio::time::interval(GITHUB_RANGES_REFRESH_INTERVAL);
+            // The first tick completes immediately
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                allowlist.refresh_github_hook_ips(&config).await;
+            }
+        });
+    }
+}
+
+impl IpAllowlistInner {
+    fn is_allowed(&self, ip: IpAddr) -> bool {
+        let ip = canonical_ip(ip);
+        self.allowed_ips.iter().any(|r| r.contains(&ip))
+            || self
+                .github_hook_ips
+                .as_ref()
+                .map_or(false, |r| r.read().unwrap().iter().any(|r| r.contains(&ip)))
+    }
+
+    fn is_trusted_proxy(&self, ip: IpAddr) -> bool {
+        let ip = canonical_ip(ip);
+        self.trusted_proxies.iter().any(|r| r.contains(&ip))
+    }
+
+    /// Resolve the client address: when the peer is a trusted proxy, the `X-Forwarded-For`
+    /// header is read from right to left, skipping trusted proxies.
+    /// Returns `None` for a malformed header.
+    fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> Option<IpAddr> {
+        if !self.is_trusted_proxy(peer) {
+            return Some(peer);
+        }
+
+        let mut client = peer;
+        for value in headers.get_all(X_FORWARDED_FOR).iter().rev() {
+            for entry in value.to_str().ok()?.rsplit(',') {
+                client = IpAddr::from_str(entry.trim()).ok()?;
+                if !self.is_trusted_proxy(client) {
+                    return Some(client);
+                }
+            }
+        }
+
+        Some(client)
+    }
+}
+
+/// Handle IPv4-mapped IPv6 addresses, as given by dual-stack sockets.
+fn canonical_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) if v6.segments()[..6] == [0, 0, 0, 0, 0, 0xffff] => {
+            v6.to_ipv4().map_or(ip, IpAddr::V4)
+        }
+        ip => ip,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::http::{HeaderMap, HeaderValue};
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use url::Url;
+    use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+
+    use crate::config::Config;
+
+    use super::IpAllowlist;
+
+    fn ip(value: &str) -> Option<std::net::IpAddr> {
+        Some(value.parse().unwrap())
+    }
+
+    #[test]
+    fn test_allowed_ips() {
+        let allowlist = IpAllowlist::new(vec!["10.0.0.0/8".parse().unwrap()], false, vec![]);
+        let headers = HeaderMap::new();
+
+        assert_eq!(allowlist.check(ip("10.1.2.3"), &headers), Ok(()));
+        assert_eq!(allowlist.check(ip("::ffff:10.1.2.3"), &headers), Ok(()));
+        assert_eq!(
+            allowlist.check(ip("192.168.1.1"), &headers),
+            Err(ip("192.168.1.1"))
+        );
+        assert_eq!(allowlist.check(None, &headers), Err(None));
+        assert_eq!(IpAllowlist::disabled().check(None, &headers), Ok(()));
+    }
+
+    #[test]
+    fn test_trusted_proxies() {
+        let allowlist = IpAllowlist::new(
+            vec!["10.0.0.0/8".parse().unwrap()],
+            false,
+            vec![
+                "127.0.0.1/32".parse().unwrap(),
+                "172.16.0.0/12".parse().unwrap(),
+            ],
+        );
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_static("192.168.1.1, 10.1.2.3, 172.16.0.1"),
+        );
+
+        // Addresses added before the last untrusted hop can be spoofed by the client
+        assert_eq!(allowlist.check(ip("127.0.0.1"), &headers), Ok(()));
+        assert_eq!(
+            allowlist.check(ip("192.168.1.2"), &headers),
+            Err(ip("192.168.1.2"))
+        );
+
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_static("10.1.2.3, nope"),
+        );
+        assert_eq!(allowlist.check(ip("127.0.0.1"), &headers), Err(None));
+    }
+
+    #[tokio::test]
+    async fn test_refresh_github_hook_ips() {
+        let server = MockServer::start().await;
+        let mut config = Config::empty();
+        config.set_github_api_url(Url::parse(&server.uri()).unwrap());
+
+        Mock::given(matchers::method("GET"))
+            .and(matchers::path("/meta"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "hooks": ["192.30.252.0/22"]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let allowlist = IpAllowlist::new(vec![], true, vec![]);
+        let headers = HeaderMap::new();
+        assert_eq!(
+            allowlist.check(ip("192.30.252.1"), &headers),
+            Err(ip("192.30.252.1"))
+        );
+
+        assert!(allowlist.refresh_github_hook_ips(&config).await);
+        assert_eq!(allowlist.check(ip("192.30.252.1"), &headers), Ok(()));
+
+        // Cached ranges are kept when GitHub cannot be reached
+        server.reset().await;
+        assert!(!allowlist.refresh_github_hook_ips(&config).await);
+        assert_eq!(allowlist.check(ip("192.30.252.1"), &headers), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_without_github_hook_ips() {
+        let server = MockServer::start().await;
+        let mut config = Config::empty();
+        config.set_github_api_url(Url::parse(&server.uri()).unwrap());
+
+        Mock::given(matchers::method("GET"))
+            .and(matchers::path("/meta"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "hooks": ["192.30.252.0/22"]
+            })))
+            .mount(&server)
+            .await;
+
+        let allowlist = IpAllowlist::new(vec!["10.0.0.0/8".parse().unwrap()], true, vec![]);
+        assert!(allowlist.refresh_github_hook_ips(&config).await);
+
+        let headers = HeaderMap::new();
+        let other = allowlist.without_github_hook_ips();
+        assert_eq!(other.check(ip("10.1.2.3"), &headers), Ok(()));
+        assert_eq!(
+            other.check(ip("192.30.252.1"), &headers),
+            Err(ip("192.30.252.1"))
+        );
+        assert_eq!(allowlist.check(ip("192.30.252.1"), &headers), Ok(()));
+
+        // Without configured ranges, only GitHub deliveries are restricted
+        let allowlist = IpAllowlist::new(vec![], true, vec![]);
+        assert!(!allowlist.without_github_hook_ips().is_enabled());
+    }
+
+    #[tokio::test]
+    async fn test_retry_github_hook_ips_refresh() {
+        let server = MockServer::start().await;
+        let mut config = Config::empty();
+        config.set_github_api_url(Url::parse(&server.uri()).unwrap());
+
+        Mock::given(matchers::method("GET"))
+            .and(matchers::path("/meta"))
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(matchers::method("GET"))
+            .and(matchers::path("/meta"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "hooks": ["192.30.252.0/22"]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let allowlist = IpAllowlist::new(vec![], true, vec![]);
+        allowlist
+            .retry_github_hook_ips_refresh(&config, Duration::from_millis(10))
+            .await;
+        assert_eq!(
+            allowlist.check(ip("192.30.252.1"), &HeaderMap::new()),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,588 @@
+//! API, exposing the server state and allowing manual synchronizations.
+
+use std::{collections::HashSet, path::PathBuf};
+
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    backends::{configured_backend, parse_body},
+    config::Config,
+    error::ErrorCode,
+    git::{RefType, Releases, RepoCloner, RepositoryPath},
+    history::Delivery,
+    jobs::{Job, JobRequest},
+    service::ServiceHandler,
+};
+
+/// Configured repository state.
+#[derive(Debug, Serialize)]
+pub struct RepositoryStatus {
+    pub name: String,
+    pub backend: Option<String>,
+    /// Target directory, unknown for never synchronized repositories using per-branch directories.
+    pub target_dir: Option<PathBuf>,
+    /// Commit currently checked out, if the repository has been cloned.
+    pub head_commit: Option<String>,
+    /// Active release, for repositories using release deployments.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_release: Option<String>,
+    pub last_job: Option<Job>,
+}
+
+/// Manual synchronization request.
+#[derive(Debug, Deserialize)]
+pub struct SyncRequest {
+    #[serde(rename = "ref")]
+    pub reference: String,
+    /// Wait for the job to finish, and return it.
+    #[serde(default)]
+    pub wait: bool,
+}
+
+/// Manual rollback request.
+#[derive(Debug, Deserialize)]
+pub struct RollbackRequest {
+    #[serde(rename = "ref")]
+    pub reference: String,
+    /// Release to activate (the release before the current one as default).
+    #[serde(default)]
+    pub release: Option<String>,
+    /// Wait for the job to finish, and return it.
+    #[serde(default)]
+    pub wait: bool,
+}
+
+/// Get known jobs, most recent first: jobs from the queue, then older jobs from history.
+fn all_jobs(services: &ServiceHandler) -> Result<Vec<Job>, ErrorCode> {
+    let mut jobs = services.jobs().list();
+    let known_ids: HashSet<String> = jobs.iter().map(|j| j.id.clone()).collect();
+    let history = services
+        .history()
+        .jobs()
+        .map_err(|e| ErrorCode::UnhandledError(e.to_string()))?;
+
+    jobs.extend(history.into_iter().filter(|j| !known_ids.contains(&j.id)));
+    jobs.sort_by_key(|j| std::cmp::Reverse(j.created_at));
+    Ok(jobs)
+}
+
+/// List jobs, without their outputs.
+#[tracing::instrument]
+pub async fn list_jobs(services: Extension<ServiceHandler>) -> Result<Json<Vec<Job>>, ErrorCode> {
+    Ok(Json(
+        all_jobs(&services)?
+            .into_iter()
+            .map(|mut j| {
+                j.report = None;
+                j
+            })
+            .collect(),
+    ))
+}
+
+/// Get a job, with its Git and action outputs.
+#[tracing::instrument]
+pub async fn get_job(
+    Path(id): Path<String>,
+    services: Extension<ServiceHandler>,
+) -> Result<Json<Job>, ErrorCode> {
+    if let Some(job) = services.jobs().get(&id) {
+        return Ok(Json(job));
+    }
+
+    all_jobs(&services)?
+        .into_iter()
+        .find(|j| j.id == id)
+        .map(Json)
+        .ok_or_else(|| ErrorCode::NotFound(format!("job {}", id)))
+}
+
+/// List received deliveries.
+#[tracing::instrument]
+pub async fn list_deliveries(
+    services: Extension<ServiceHandler>,
+) -> Result<Json<Vec<Delivery>>, ErrorCode> {
+    services
+        .history()
+        .deliveries()
+        .map(Json)
+        .map_err(|e| ErrorCode::UnhandledError(e.to_string()))
+}
+
+/// Trigger a repository synchronization, without waiting for a push.
+/// Answers `202 Accepted` with the job identifier, or the finished job when waiting for it.
+#[tracing::instrument(skip(config))]
+pub async fn sync_repository(
+    Path((owner, name)): Path<(String, String)>,
+    config: Extension<Config>,
+    services: Extension<ServiceHandler>,
+    body: String,
+) -> Result<(StatusCode, Json<Value>), ErrorCode> {
+    let request: SyncRequest = parse_body(&body)?;
+    let repository = parse_repository(&owner, &name)?;
+    let reference = parse_reference(&request.reference)?;
+
+    let job_request = JobRequest::synchronization(
+        configured_backend(&config, &repository)?,
+        repository,
+        reference,
+    );
+    enqueue_job(&config, &services, "sync", job_request, request.wait).await
+}
+
+/// Activate a previous release of a repository using release deployments.
+/// Answers `202 Accepted` with the job identifier, or the finished job when waiting for it.
+#[tracing::instrument(skip(config))]
+pub async fn rollback_repository(
+    Path((owner, name)): Path<(String, String)>,
+    config: Extension<Config>,
+    services: Extension<ServiceHandler>,
+    body: String,
+) -> Result<(StatusCode, Json<Value>), ErrorCode> {
+    let request: RollbackRequest = parse_body(&body)?;
+    let repository = parse_repository(&owner, &name)?;
+    let reference = parse_reference(&request.reference)?;
+
+    let job_request = JobRequest::rollback(
+        configured_backend(&config, &repository)?,
+        repository,
+        reference,
+        request.release.as_deref(),
+    );
+    enqueue_job(&config, &services, "rollback", job_request, request.wait).await
+}
+
+fn parse_repository(owner: &str, name: &str) -> Result<RepositoryPath, ErrorCode> {
+    RepositoryPath::new(&format!("{}/{}", owner, name))
+        .map_err(|e| ErrorCode::NotFound(e.to_string()))
+}
+
+fn parse_reference(reference: &str) -> Result<RefType, ErrorCode> {
+    reference
+        .parse::<RefType>()
+        .map_err(|e| ErrorCode::MalformedEventBodyField("ref".into(), e.to_string()))
+}
+
+/// Queue a job requested through the API, recording its delivery.
+async fn enqueue_job(
+    config: &Config,
+    services: &ServiceHandler,
+    event: &str,
+    request: JobRequest,
+    wait: bool,
+) -> Result<(StatusCode, Json<Value>), ErrorCode> {
+    let mut delivery = Delivery::new("api", event, None);
+    delivery.set_target(
+        &request.repository.full_name(),
+        &request.reference.to_string(),
+        None,
+    );
+
+    let job_id = services.jobs().enqueue(config, request);
+    delivery.job_ids.push(job_id.clone());
+    services.history().record_delivery(&delivery);
+
+    if wait {
+        let job = services
+            .jobs()
+            .wait_for(&job_id)
+            .await
+            .ok_or_else(|| ErrorCode::NotFound(format!("job {}", job_id)))?;
+        let job =
+            serde_json::to_value(job).map_err(|e| ErrorCode::UnhandledError(e.to_string()))?;
+        Ok((StatusCode::OK, Json(job)))
+    } else {
+        Ok((StatusCode::ACCEPTED, Json(json!({ "job_id": job_id }))))
+    }
+}
+
+/// List configured repositories, with their current state.
+#[tracing::instrument(skip(config))]
+pub async fn list_repositories(
+    config: Extension<Config>,
+    services: Extension<ServiceHandler>,
+) -> Result<Json<Vec<RepositoryStatus>>, ErrorCode> {
+    let jobs = all_jobs(&services)?;
+    let mut names: Vec<&String> = config.repositories().keys().collect();
+    names.sort();
+
+    let mut repositories = vec![];
+    for name in names {
+        let repo_path = match RepositoryPath::new(name) {
+            Ok(p) => p,
+            Err(_) => continue,
+        };
+        let last_job = jobs.iter().find(|j| &j.repository == name);
+        let per_branch = config
+            .repository(name)
+            .map(|r| r.has_per_branch_target_dir())
+            .unwrap_or(false);
+
+        // Per-branch directories are resolved from the last synchronized reference
+        let target_dir = match (per_branch, last_job) {
+            (false, _) => Some(RepoCloner::get_repository_target_dir(
+                &config, &repo_path, "",
+            )),
+            (true, Some(job)) => Some(RepoCloner::get_repository_target_dir(
+                &config,
+                &repo_path,
+                &job.reference,
+            )),
+            (true, None) => None,
+        };
+        let releases = config
+            .repository(name)
+            .and_then(|r| r.releases())
+            .and(target_dir.as_deref())
+            .map(Releases::new);
+        let current_release = releases.as_ref().and_then(|r| r.current());
+        let head_commit = match (&current_release, &target_dir) {
+            (Some(release), _) => Releases::release_commit(release).map(ToOwned::to_owned),
+            (None, Some(dir)) if releases.is_none() && dir.exists() => {
+                services.git().head_commit(dir).await.ok()
+            }
+            _ => None,
+        };
+
+        repositories.push(RepositoryStatus {
+            name: name.clone(),
+            backend: config
+                .repository(name)
+                .and_then(|r| r.backend())
+                .map(|b| b.to_string()),
+            target_dir,
+            head_commit,
+            current_release,
+            last_job: last_job.map(|j| Job {
+                report: None,
+                ..j.clone()
+            }),
+        });
+    }
+
+    Ok(Json(repositories))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use pretty_assertions::assert_eq;
+    use reqwest::StatusCode;
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    use crate::{
+        error::ErrorCode,
+        git::{GitBackend, RefType, ReleaseOptions, Releases, RepositoryPath},
+        http::build_http_router,
+        jobs::{JobRequest, JobStatus},
+        testing::{
+            assert_response_is_error, create_test_config, create_test_services,
+            extract_fake_git_service, response_to_json, wait_for_response_job,
+        },
+    };
+
+    fn get(uri: &str) -> Request<Body> {
+        Request::builder()
+            .method("GET")
+            .uri(uri)
+            .header("Authorization", "Bearer token")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    fn create_request() -> JobRequest {
+        JobRequest::synchronization(
+            GitBackend::GitHub,
+            RepositoryPath::new("Srynetix/things").unwrap(),
+            RefType::Branch("main".into()),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_list_and_get_jobs() {
+        let mut config = create_test_config();
+        config.set_api_token("token");
+        let services = create_test_services();
+        let job_id = services.jobs().enqueue(&config, create_request());
+        services.jobs().wait_until_idle().await;
+
+        let app = build_http_router(config, services);
+        let response = app.clone().oneshot(get("/api/jobs")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let data = response_to_json(response).await;
+        assert_eq!(data[0]["id"], job_id.as_str());
+        assert_eq!(data[0]["status"], "succeeded");
+        assert!(data[0].get("report").is_none());
+
+        let response = app
+            .oneshot(get(&format!("/api/jobs/{}", job_id)))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let data = response_to_json(response).await;
+        assert_eq!(data["id"], job_id.as_str());
+        assert_eq!(data["report"]["git_output"][0], "OK");
+    }
+
+    #[tokio::test]
+    async fn test_get_unknown_job() {
+        let mut config = create_test_config();
+        config.set_api_token("token");
+        let app = build_http_router(config, create_test_services());
+
+        let response = app.oneshot(get("/api/jobs/unknown")).await.unwrap();
+        let status = response.status();
+        assert_response_is_error(response, ErrorCode::NotFound("job unknown".into())).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_list_repositories() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tempdir.path().join("things")).unwrap();
+
+        let mut config = create_test_config();
+        config.set_api_token("token");
+        config.set_working_dir(tempdir.path());
+        config
+            .repository_mut("Srynetix/things")
+            .unwrap()
+            .set_backend(GitBackend::GitLab);
+        config
+            .repository_mut("Srynetix/other")
+            .unwrap()
+            .set_target_dir(tempdir.path().join("previews/{branch}"));
+
+        let services = create_test_services();
+        let job_id = services.jobs().enqueue(&config, create_request());
+        services.jobs().wait_until_idle().await;
+
+        let app = build_http_router(config, services);
+        let response = app.oneshot(get("/api/repositories")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let data = response_to_json(response).await;
+        assert_eq!(data[0]["name"], "Srynetix/other");
+        assert_eq!(data[0]["target_dir"], serde_json::Value::Null);
+        assert_eq!(data[0]["head_commit"], serde_json::Value::Null);
+        assert_eq!(data[0]["last_job"], serde_json::Value::Null);
+        assert_eq!(data[1]["name"], "Srynetix/things");
+        assert_eq!(data[1]["backend"], "gitlab");
+        assert_eq!(
+            data[1]["target_dir"],
+            tempdir.path().join("things").to_string_lossy().as_ref()
+        );
+        assert_eq!(
+            data[1]["head_commit"],
+            "0d1a26e67d8f5eaf1f6ba5c57fc3c7d91ac0fd1c"
+        );
+        assert_eq!(data[1]["last_job"]["id"], job_id.as_str());
+        assert_eq!(data[1]["last_job"]["status"], "succeeded");
+    }
+
+    fn sync(uri: &str, token: Option<&str>, body: serde_json::Value) -> Request<Body> {
+        let mut builder = Request::builder().method("POST").uri(uri);
+        if let Some(token) = token {
+            builder = builder.header("Authorization", format!("Bearer {}", token));
+        }
+
+        builder.body(body.to_string().into()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sync_repository() {
+        let mut config = create_test_config();
+        config.set_api_token("token");
+        config
+            .repository_mut("Srynetix/things")
+            .unwrap()
+            .set_backend(GitBackend::GitLab);
+        let services = create_test_services();
+        let app = build_http_router(config, services.clone());
+
+        let response = app
+            .oneshot(sync(
+                "/api/repositories/Srynetix/things/sync",
+                Some("token"),
+                json!({"ref": "refs/tags/v1.0.0"}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        let job = wait_for_response_job(&services, response).await;
+        assert_eq!(job.status, JobStatus::Succeeded);
+        assert_eq!(job.reference, "v1.0.0");
+
+        let calls = extract_fake_git_service(&services).clone_repository.calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].2, "https://gitlab.com/Srynetix/things");
+    }
+
+    #[tokio::test]
+    async fn test_sync_repository_wait() {
+        let mut config = create_test_config();
+        config.set_api_token("token");
+        config
+            .repository_mut("Srynetix/things")
+            .unwrap()
+            .set_backend(GitBackend::GitHub);
+        let app = build_http_router(config, create_test_services());
+
+        let response = app
+            .oneshot(sync(
+                "/api/repositories/Srynetix/things/sync",
+                Some("token"),
+                json!({"ref": "refs/branches/main", "wait": true}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let data = response_to_json(response).await;
+        assert_eq!(data["status"], "succeeded");
+        assert_eq!(data["report"]["git_output"][0], "OK");
+    }
+
+    #[tokio::test]
+    async fn test_sync_repository_unconfigured_backend() {
+        let mut config = create_test_config();
+        config.set_api_token("token");
+        let services = create_test_services();
+        let app = build_http_router(config, services.clone());
+
+        // Without a webhook event, the backend can not be guessed
+        let response = app
+            .oneshot(sync(
+                "/api/repositories/Srynetix/things/sync",
+                Some("token"),
+                json!({"ref": "refs/heads/main"}),
+            ))
+            .await
+            .unwrap();
+        let status = response.status();
+        assert_response_is_error(
+            response,
+            ErrorCode::UnconfiguredBackend("Srynetix/things".into()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(services.jobs().list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rollback_repository() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut config = create_test_config();
+        config.set_api_token("token");
+        config.set_working_dir(tempdir.path());
+        let repository = config.repository_mut("Srynetix/things").unwrap();
+        repository.set_releases(ReleaseOptions::default());
+        repository.set_backend(GitBackend::GitHub);
+        std::fs::create_dir_all(tempdir.path().join("things/repository")).unwrap();
+
+        let services = create_test_services();
+        let releases = Releases::new(&tempdir.path().join("things"));
+        for _ in 0..2 {
+            services.jobs().enqueue(&config, create_request());
+            services.jobs().wait_until_idle().await;
+            tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        }
+        let names = releases.list().unwrap();
+        assert_eq!(releases.current().as_ref(), names.get(1));
+
+        let app = build_http_router(config, services);
+        let response = app
+            .clone()
+            .oneshot(sync(
+                "/api/repositories/Srynetix/things/rollback",
+                Some("token"),
+                json!({"ref": "refs/heads/main", "wait": true}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let data = response_to_json(response).await;
+        assert_eq!(data["kind"], "rollback");
+        assert_eq!(data["status"], "succeeded");
+        assert_eq!(data["report"]["release"], names[0].as_str());
+        assert_eq!(releases.current().as_ref(), names.first());
+
+        let response = app.oneshot(get("/api/repositories")).await.unwrap();
+        let data = response_to_json(response).await;
+        assert_eq!(data[0]["current_release"], names[0].as_str());
+        assert_eq!(
+            data[0]["head_commit"],
+            "0d1a26e67d8f5eaf1f6ba5c57fc3c7d91ac0fd1c"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sync_repository_invalid_token() {
+        let mut config = create_test_config();
+        config.set_api_token("token");
+        let app = build_http_router(config, create_test_services());
+
+        for token in [None, Some("other")] {
+            let response = app
+                .clone()
+                .oneshot(sync(
+                    "/api/repositories/Srynetix/things/sync",
+                    token,
+                    json!({"ref": "refs/branches/main"}),
+                ))
+                .await
+                .unwrap();
+
+            let status = response.status();
+            assert_response_is_error(response, ErrorCode::InvalidApiToken).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_routes_invalid_token() {
+        let mut config = create_test_config();
+        config.set_api_token("other");
+        let app = build_http_router(config, create_test_services());
+
+        for uri in [
+            "/api/jobs",
+            "/api/jobs/unknown",
+            "/api/deliveries",
+            "/api/repositories",
+        ] {
+            let response = app.clone().oneshot(get(uri)).await.unwrap();
+
+            let status = response.status();
+            assert_response_is_error(response, ErrorCode::InvalidApiToken).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sync_repository_without_configured_token() {
+        let app = build_http_router(create_test_config(), create_test_services());
+
+        let response = app
+            .oneshot(sync(
+                "/api/repositories/Srynetix/things/sync",
+                Some(""),
+                json!({"ref": "refs/branches/main"}),
+            ))
+            .await
+            .unwrap();
+
+        let status = response.status();
+        assert_response_is_error(response, ErrorCode::InvalidApiToken).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+}
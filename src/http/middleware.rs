@@ -0,0 +1,288 @@
+use std::{
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{header, Request},
+    response::{IntoResponse, Response},
+};
+
+use tower::{Layer, Service};
+
+use crate::{crypto::is_valid_token, error::ErrorCode, history::DeliveryCache};
+
+use super::IpAllowlist;
+
+const BEARER_PREFIX: &str = "Bearer ";
+
+/// Check the `Authorization: Bearer <token>` header.
+/// Every request is rejected when no token is configured.
+pub struct VerifyBearerTokenLayer {
+    token: Option<String>,
+}
+
+impl VerifyBearerTokenLayer {
+    pub fn new(token: Option<String>) -> Self {
+        Self { token }
+    }
+}
+
+impl<S> Layer<S> for VerifyBearerTokenLayer {
+    type Service = VerifyBearerTokenMiddleware<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        VerifyBearerTokenMiddleware::new(self.token.clone(), inner)
+    }
+}
+
+#[derive(Clone)]
+pub struct VerifyBearerTokenMiddleware<S> {
+    token: Option<String>,
+    inner: S,
+}
+
+impl<S> VerifyBearerTokenMiddleware<S> {
+    pub fn new(token: Option<String>, inner: S) -> Self {
+        Self { token, inner }
+    }
+}
+
+type BoxFuture<'a, Output> = Pin<Box<dyn Future<Output = Output> + Send + 'a>>;
+
+impl<S> Service<Request<Body>> for VerifyBearerTokenMiddleware<S>
+where
+    S: Service<Request<Body>, Response = Response> + Send + 'static + Clone,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let token = self.token.clone();
+        let fut = async move {
+            let provided = request
+                .headers()
+                .get(header::AUTHORIZATION)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.strip_prefix(BEARER_PREFIX));
+
+            match (token, provided) {
+                (Some(token), Some(provided)) if is_valid_token(provided, &token) => (),
+                _ => return Ok(ErrorCode::InvalidApiToken.into_response()),
+            }
+
+            let future = inner.call(request);
+            let response: Response = future.await?;
+            Ok(response)
+        };
+
+        Box::pin(fut)
+    }
+}
+
+/// Reject deliveries whose identifier, read from a backend header, has already been received.
+/// The identifier is only remembered when the delivery succeeds, so failed deliveries can be redelivered.
+/// Requests without the header are rejected while the cache is enabled, as dropping the header
+/// would let a captured delivery be replayed.
+pub struct RejectReplayedDeliveryLayer {
+    header_name: &'static str,
+    cache: DeliveryCache,
+}
+
+impl RejectReplayedDeliveryLayer {
+    pub fn new(header_name: &'static str, cache: DeliveryCache) -> Self {
+        Self { header_name, cache }
+    }
+}
+
+impl<S> Layer<S> for RejectReplayedDeliveryLayer {
+    type Service = RejectReplayedDeliveryMiddleware<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RejectReplayedDeliveryMiddleware::new(self.header_name, self.cache.clone(), inner)
+    }
+}
+
+#[derive(Clone)]
+pub struct RejectReplayedDeliveryMiddleware<S> {
+    header_name: &'static str,
+    cache: DeliveryCache,
+    inner: S,
+}
+
+impl<S> RejectReplayedDeliveryMiddleware<S> {
+    pub fn new(header_name: &'static str, cache: DeliveryCache, inner: S) -> Self {
+        Self {
+            header_name,
+            cache,
+            inner,
+        }
+    }
+}
+
+impl<S> Service<Request<Body>> for RejectReplayedDeliveryMiddleware<S>
+where
+    S: Service<Request<Body>, Response = Response> + Send + 'static + Clone,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let cache = self.cache.clone();
+        let header_name = self.header_name;
+        let fut = async move {
+            let delivery_id = request
+                .headers()
+                .get(header_name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_owned());
+
+            let delivery_id = match delivery_id {
+                Some(id) => id,
+                None if cache.is_enabled() => {
+                    tracing::warn!(message = "Rejecting delivery without identifier");
+                    return Ok(ErrorCode::MissingDeliveryHeader(header_name.into()).into_response());
+                }
+                None => return inner.call(request).await,
+            };
+
+            // Identifiers are only unique per backend
+            let key = format!("{}:{}", header_name, delivery_id);
+            let reservation = match cache.reserve(&key) {
+                Some(r) => r,
+                None => {
+                    tracing::warn!(message = "Rejecting replayed delivery", delivery_id = %delivery_id);
+                    return Ok(ErrorCode::ReplayedDelivery(delivery_id).into_response());
+                }
+            };
+
+            // The reservation is released on failure, or if this future is dropped
+            let result = inner.call(request).await;
+            if matches!(&result, Ok(response) if response.status().is_success()) {
+                reservation.confirm();
+            }
+
+            result
+        };
+
+        Box::pin(fut)
+    }
+}
+
+/// Only accept requests from allowed source IP addresses.
+pub struct AllowSourceIpLayer {
+    allowlist: IpAllowlist,
+}
+
+impl AllowSourceIpLayer {
+    pub fn new(allowlist: IpAllowlist) -> Self {
+        Self { allowlist }
+    }
+}
+
+impl<S> Layer<S> for AllowSourceIpLayer {
+    type Service = AllowSourceIpMiddleware<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AllowSourceIpMiddleware::new(self.allowlist.clone(), inner)
+    }
+}
+
+#[derive(Clone)]
+pub struct AllowSourceIpMiddleware<S> {
+    allowlist: IpAllowlist,
+    inner: S,
+}
+
+impl<S> AllowSourceIpMiddleware<S> {
+    pub fn new(allowlist: IpAllowlist, inner: S) -> Self {
+        Self { allowlist, inner }
+    }
+}
+
+impl<S> Service<Request<Body>> for AllowSourceIpMiddleware<S>
+where
+    S: Service<Request<Body>, Response = Response> + Send + 'static + Clone,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let allowlist = self.allowlist.clone();
+        let fut = async move {
+            let peer = request
+                .extensions()
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|c| c.0.ip());
+
+            if let Err(client) = allowlist.check(peer, request.headers()) {
+                let client = client.map(|c| c.to_string()).unwrap_or_default();
+                tracing::warn!(message = "Rejecting request from unallowed source", client = %client);
+                return Ok(ErrorCode::ForbiddenSourceIp(client).into_response());
+            }
+
+            let future = inner.call(request);
+            let response: Response = future.await?;
+            Ok(response)
+        };
+
+        Box::pin(fut)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use axum::{body::Body, http::Request, response::Response};
+    use tower::{service_fn, Layer, Service};
+
+    use crate::history::DeliveryCache;
+
+    use super::RejectReplayedDeliveryLayer;
+
+    #[tokio::test]
+    async fn test_dropped_delivery_is_released() {
+        let cache = DeliveryCache::new(10);
+        let mut service = RejectReplayedDeliveryLayer::new("x-delivery", cache.clone()).layer(
+            service_fn(|_: Request<Body>| std::future::pending::<Result<Response, Infallible>>()),
+        );
+
+        let request = Request::builder()
+            .header("x-delivery", "id")
+            .body(Body::empty())
+            .unwrap();
+        let mut future = service.call(request);
+        assert!(futures::poll!(&mut future).is_pending());
+        assert!(cache.reserve("x-delivery:id").is_none());
+
+        // The client went away before the delivery was handled
+        drop(future);
+        assert!(cache.reserve("x-delivery:id").is_some());
+    }
+}
@@ -1,7 +1,13 @@
+mod allowlist;
+mod api;
+pub mod middleware;
+
+use std::net::SocketAddr;
+
 use axum::{
-    body,
-    extract::Extension,
-    http::{header, HeaderValue},
+    body::{self, Body, HttpBody},
+    extract::{connect_info::IntoMakeServiceWithConnectInfo, Extension},
+    http::{header, HeaderValue, Request},
     response::{IntoResponse, Response},
     routing::{get, post},
     Json, Router,
@@ -10,9 +16,18 @@ use axum::{
 use tower::ServiceBuilder;
 use tower_http::{trace::TraceLayer, ServiceBuilderExt};
 
+use self::middleware::{AllowSourceIpLayer, RejectReplayedDeliveryLayer, VerifyBearerTokenLayer};
+
+pub use self::allowlist::IpAllowlist;
+
 use crate::{
-    backends::github::middleware::VerifyGitHubSignatureLayer,
+    backends::{
+        bitbucket::middleware::VerifyBitbucketSignatureLayer,
+        gitea::middleware::VerifyGiteaSignatureLayer,
+        github::middleware::VerifyGitHubSignatureLayer, gitlab::middleware::VerifyGitLabTokenLayer,
+    },
     config::{Config, ServerConfig},
+    crypto::WebhookVerifier,
     error::{ErrorCode, ErrorCodeDetail},
     server_info::ServerInfo,
     service::ServiceHandler,
@@ -32,6 +47,35 @@ impl IntoResponse for ErrorCode {
     }
 }
 
+/// Buffer a request body, to be able to verify it before passing it to the handler.
+/// Bodies larger than `max_size` bytes are rejected, without reading them entirely.
+pub(crate) async fn buffer_request_body(
+    request: &mut Request<Body>,
+    max_size: usize,
+) -> Result<Vec<u8>, ErrorCode> {
+    let content_length = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if content_length.map_or(false, |l| l > max_size) {
+        return Err(ErrorCode::PayloadTooLarge(max_size));
+    }
+
+    let mut body = Vec::<u8>::new();
+    let request_body = request.body_mut();
+    while let Some(d) = request_body.data().await {
+        let data = d.map_err(|e| ErrorCode::UnhandledError(e.to_string()))?;
+        if body.len() + data.len() > max_size {
+            return Err(ErrorCode::PayloadTooLarge(max_size));
+        }
+
+        body.extend(data);
+    }
+
+    Ok(body)
+}
+
 #[tracing::instrument]
 async fn root() -> Json<ServerInfo> {
     Json(ServerInfo::new())
@@ -43,11 +87,19 @@ pub async fn start_server(
     config: Config,
     services: ServiceHandler,
 ) -> color_eyre::Result<()> {
+    services.start_workers(server_config.workers());
+    services
+        .ip_allowlist()
+        .start_github_hook_ips_refresh(config.clone())
+        .await;
     let app = build_http_router(config, services);
     tracing::info!("listening on {}", server_config.bind_ip());
 
+    // Peer addresses are needed to check source IPs
+    let make_service: IntoMakeServiceWithConnectInfo<Router, SocketAddr> =
+        app.into_make_service_with_connect_info();
     axum::Server::bind(server_config.bind_ip())
-        .serve(app.into_make_service())
+        .serve(make_service)
         .await?;
 
     Ok(())
@@ -61,14 +113,103 @@ pub(crate) fn build_http_router(config: Config, services: ServiceHandler) -> Rou
             HeaderValue::from_static("application/json"),
         );
 
-    Router::new()
-        .route("/", get(root))
-        .route(
-            "/webhook/github",
-            post(super::backends::github::webhook).layer(VerifyGitHubSignatureLayer::new(
-                config.webhook_secret().map(|x| x.to_owned()),
+    let mut github_webhooks = Router::new().route(
+        "/webhook/github",
+        post(super::backends::github::webhook)
+            .layer(RejectReplayedDeliveryLayer::new(
+                "x-github-delivery",
+                services.delivery_cache().clone(),
+            ))
+            .layer(VerifyGitHubSignatureLayer::new(
+                WebhookVerifier::from_config(&config, "github")
+                    .with_repository_field(super::backends::github::REPOSITORY_FIELD),
             )),
+    );
+
+    let mut webhooks = Router::new()
+        .route(
+            "/webhook/gitlab",
+            post(super::backends::gitlab::webhook)
+                .layer(RejectReplayedDeliveryLayer::new(
+                    "x-gitlab-event-uuid",
+                    services.delivery_cache().clone(),
+                ))
+                .layer(VerifyGitLabTokenLayer::new(
+                    WebhookVerifier::from_config(&config, "gitlab")
+                        .with_repository_field(super::backends::gitlab::REPOSITORY_FIELD),
+                )),
         )
+        .route(
+            "/webhook/gitea",
+            post(super::backends::gitea::webhook)
+                .layer(RejectReplayedDeliveryLayer::new(
+                    "x-gitea-delivery",
+                    services.delivery_cache().clone(),
+                ))
+                .layer(VerifyGiteaSignatureLayer::new(
+                    WebhookVerifier::from_config(&config, "gitea")
+                        .with_repository_field(super::backends::gitea::REPOSITORY_FIELD),
+                )),
+        )
+        .route(
+            "/webhook/bitbucket",
+            post(super::backends::bitbucket::webhook_cloud)
+                .layer(RejectReplayedDeliveryLayer::new(
+                    "x-request-uuid",
+                    services.delivery_cache().clone(),
+                ))
+                .layer(VerifyBitbucketSignatureLayer::new(
+                    WebhookVerifier::from_config(&config, "bitbucket")
+                        .with_repository_field(super::backends::bitbucket::CLOUD_REPOSITORY_FIELD),
+                )),
+        )
+        .route(
+            "/webhook/bitbucket-server",
+            post(super::backends::bitbucket::webhook_server)
+                .layer(RejectReplayedDeliveryLayer::new(
+                    "x-request-id",
+                    services.delivery_cache().clone(),
+                ))
+                .layer(VerifyBitbucketSignatureLayer::new(
+                    WebhookVerifier::from_config(&config, "bitbucket-server")
+                        .with_repository_field(super::backends::bitbucket::SERVER_REPOSITORY_FIELD),
+                )),
+        );
+
+    // Only webhooks are restricted, the API has its own authentication.
+    // GitHub hook ranges are shared by every GitHub user, so they only allow GitHub deliveries.
+    let github_allowlist = services.ip_allowlist().clone();
+    let allowlist = github_allowlist.without_github_hook_ips();
+    if github_allowlist.is_enabled() {
+        github_webhooks = github_webhooks.layer(AllowSourceIpLayer::new(github_allowlist));
+    }
+    if allowlist.is_enabled() {
+        webhooks = webhooks.layer(AllowSourceIpLayer::new(allowlist));
+    }
+
+    // Jobs and deliveries expose command outputs and paths, so every API route is authenticated
+    let api = Router::new()
+        .route("/api/jobs", get(api::list_jobs))
+        .route("/api/jobs/:id", get(api::get_job))
+        .route("/api/deliveries", get(api::list_deliveries))
+        .route("/api/repositories", get(api::list_repositories))
+        .route(
+            "/api/repositories/:owner/:name/sync",
+            post(api::sync_repository),
+        )
+        .route(
+            "/api/repositories/:owner/:name/rollback",
+            post(api::rollback_repository),
+        )
+        .layer(VerifyBearerTokenLayer::new(
+            config.api_token().map(|x| x.to_owned()),
+        ));
+
+    Router::new()
+        .route("/", get(root))
+        .merge(api)
+        .merge(webhooks)
+        .merge(github_webhooks)
         .layer(middleware.into_inner())
         .layer(Extension(config))
         .layer(Extension(services))
@@ -0,0 +1,8 @@
+//! Background synchronization jobs.
+
+mod models;
+mod queue;
+
+pub(crate) use models::{generate_id, now_millis};
+pub use models::{Job, JobId, JobKind, JobRequest, JobStatus};
+pub use queue::JobQueue;
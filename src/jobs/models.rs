@@ -0,0 +1,166 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::git::{GitBackend, RefType, RepositoryPath, SyncReport};
+
+/// Job identifier.
+pub type JobId = String;
+
+/// Job status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    /// Replaced by a more recent job for the same repository and reference.
+    Superseded,
+}
+
+impl JobStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Self::Succeeded | Self::Failed | Self::Superseded)
+    }
+}
+
+/// Job kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobKind {
+    /// Clone or update the reference checkout, then run the repository actions.
+    Synchronization,
+    /// Run the repository teardown actions, then remove the reference checkout.
+    Cleanup,
+    /// Activate a previous release of the reference.
+    Rollback,
+}
+
+impl Default for JobKind {
+    fn default() -> Self {
+        Self::Synchronization
+    }
+}
+
+/// Repository operation to execute in a job.
+#[derive(Debug)]
+pub struct JobRequest {
+    pub kind: JobKind,
+    pub backend: GitBackend,
+    pub repository: RepositoryPath,
+    pub reference: RefType,
+    /// Set when the reference was force-pushed.
+    pub forced: bool,
+    /// Exact commit to check out, as described by the push event.
+    pub commit: Option<String>,
+    /// Release to roll back to (the previous one as default).
+    pub release: Option<String>,
+}
+
+impl JobRequest {
+    pub fn synchronization(
+        backend: GitBackend,
+        repository: RepositoryPath,
+        reference: RefType,
+    ) -> Self {
+        Self {
+            kind: JobKind::Synchronization,
+            backend,
+            repository,
+            reference,
+            forced: false,
+            commit: None,
+            release: None,
+        }
+    }
+
+    pub fn cleanup(backend: GitBackend, repository: RepositoryPath, reference: RefType) -> Self {
+        Self {
+            kind: JobKind::Cleanup,
+            ..Self::synchronization(backend, repository, reference)
+        }
+    }
+
+    pub fn rollback(
+        backend: GitBackend,
+        repository: RepositoryPath,
+        reference: RefType,
+        release: Option<&str>,
+    ) -> Self {
+        Self {
+            kind: JobKind::Rollback,
+            release: release.map(ToOwned::to_owned),
+            ..Self::synchronization(backend, repository, reference)
+        }
+    }
+
+    pub fn with_forced(mut self, forced: bool) -> Self {
+        self.forced = forced;
+        self
+    }
+
+    pub fn with_commit(mut self, commit: Option<&str>) -> Self {
+        self.commit = commit.map(ToOwned::to_owned);
+        self
+    }
+}
+
+/// Job state, as exposed to clients.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Job {
+    pub id: JobId,
+    #[serde(default)]
+    pub kind: JobKind,
+    pub repository: String,
+    pub reference: String,
+    /// Commit requested by the push event, if known.
+    #[serde(default)]
+    pub commit: Option<String>,
+    /// Set when the reference was force-pushed, by this push or a superseded one.
+    #[serde(default)]
+    pub forced: bool,
+    pub status: JobStatus,
+    /// Timestamps, in milliseconds since the Unix epoch.
+    pub created_at: u64,
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+    pub duration_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub report: Option<SyncReport>,
+    pub error: Option<String>,
+    pub superseded_by: Option<JobId>,
+}
+
+impl Job {
+    pub(crate) fn new(request: &JobRequest) -> Self {
+        Self {
+            id: generate_id(),
+            kind: request.kind,
+            repository: request.repository.full_name(),
+            reference: request.reference.to_string(),
+            commit: request.commit.clone(),
+            forced: request.forced,
+            status: JobStatus::Queued,
+            created_at: now_millis(),
+            started_at: None,
+            finished_at: None,
+            duration_ms: None,
+            report: None,
+            error: None,
+            superseded_by: None,
+        }
+    }
+}
+
+/// Generate a random identifier, for jobs or deliveries.
+pub(crate) fn generate_id() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
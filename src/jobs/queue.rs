@@ -0,0 +1,631 @@
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::{mpsc, watch, Mutex as AsyncMutex, Semaphore};
+
+use crate::{
+    config::Config,
+    git::{RepoCloner, SyncFailure, SyncReport},
+    service::ServiceHandler,
+};
+
+use super::models::{now_millis, Job, JobId, JobKind, JobRequest, JobStatus};
+
+/// Number of finished jobs kept in memory. Older ones are only available from the history.
+const FINISHED_JOBS_RETENTION: usize = 500;
+
+#[derive(Debug)]
+struct QueuedJob {
+    id: JobId,
+    config: Config,
+    request: JobRequest,
+}
+
+#[derive(Debug)]
+struct JobQueueInner {
+    sender: mpsc::UnboundedSender<QueuedJob>,
+    receiver: Mutex<Option<mpsc::UnboundedReceiver<QueuedJob>>>,
+    jobs: Mutex<HashMap<JobId, Job>>,
+    directory_locks: Mutex<HashMap<PathBuf, Arc<AsyncMutex<()>>>>,
+    updates_sender: watch::Sender<()>,
+    updates_receiver: watch::Receiver<()>,
+}
+
+/// Queue of synchronization jobs, processed in the background by a worker pool.
+#[derive(Debug, Clone)]
+pub struct JobQueue {
+    inner: Arc<JobQueueInner>,
+}
+
+impl JobQueue {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let (updates_sender, updates_receiver) = watch::channel(());
+
+        Self {
+            inner: Arc::new(JobQueueInner {
+                sender,
+                receiver: Mutex::new(Some(receiver)),
+                jobs: Mutex::new(HashMap::new()),
+                directory_locks: Mutex::new(HashMap::new()),
+                updates_sender,
+                updates_receiver,
+            }),
+        }
+    }
+
+    /// Queue a new job, using the configuration snapshot passed.
+    /// Jobs still waiting for the same repository and reference are superseded by the new one,
+    /// so only the latest push gets synchronized. A superseded force-push is still handled as such.
+    pub fn enqueue(&self, config: &Config, mut request: JobRequest) -> JobId {
+        let mut job = Job::new(&request);
+        let id = job.id.clone();
+        tracing::info!(
+            message = "Queueing job",
+            job_id = %id,
+            kind = ?job.kind,
+            repository = %job.repository,
+            reference = %job.reference
+        );
+
+        {
+            let mut jobs = self.inner.jobs.lock().unwrap();
+            for pending in jobs.values_mut().filter(|j| {
+                j.status == JobStatus::Queued
+                    && j.kind == job.kind
+                    && j.repository == job.repository
+                    && j.reference == job.reference
+            }) {
+                tracing::info!(
+                    message = "Superseding pending job",
+                    job_id = %pending.id,
+                    superseded_by = %id
+                );
+                pending.status = JobStatus::Superseded;
+                pending.superseded_by = Some(id.clone());
+                pending.finished_at = Some(now_millis());
+                job.forced |= pending.forced;
+            }
+            request.forced = job.forced;
+
+            jobs.insert(id.clone(), job);
+        }
+        self.notify_update();
+        let queued = QueuedJob {
+            id: id.clone(),
+            config: config.clone(),
+            request,
+        };
+
+        if self.inner.sender.send(queued).is_err() {
+            self.finish(
+                &id,
+                Err((SyncReport::default(), "Job queue is closed".into())),
+            );
+            self.notify_update();
+        }
+
+        id
+    }
+
+    /// Get a job by its identifier.
+    pub fn get(&self, id: &str) -> Option<Job> {
+        self.inner.jobs.lock().unwrap().get(id).cloned()
+    }
+
+    /// Get all known jobs, most recent first.
+    pub fn list(&self) -> Vec<Job> {
+        let mut jobs: Vec<Job> = self.inner.jobs.lock().unwrap().values().cloned().collect();
+        jobs.sort_by_key(|j| Reverse(j.created_at));
+        jobs
+    }
+
+    /// Wait until a job is finished.
+    pub async fn wait_for(&self, id: &str) -> Option<Job> {
+        let mut updates = self.inner.updates_receiver.clone();
+        loop {
+            match self.get(id) {
+                Some(job) if !job.status.is_finished() => (),
+                other => return other,
+            }
+
+            if updates.changed().await.is_err() {
+                return self.get(id);
+            }
+        }
+    }
+
+    /// Wait until all known jobs are finished.
+    pub async fn wait_until_idle(&self) {
+        let mut updates = self.inner.updates_receiver.clone();
+        while self
+            .inner
+            .jobs
+            .lock()
+            .unwrap()
+            .values()
+            .any(|j| !j.status.is_finished())
+        {
+            if updates.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Start processing jobs in the background, running at most `concurrency` jobs at once.
+    /// Jobs targeting the same directory are run one after the other.
+    /// Workers can only be started once.
+    pub(crate) fn start_workers(&self, services: ServiceHandler, concurrency: usize) {
+        let mut receiver = match self.inner.receiver.lock().unwrap().take() {
+            Some(r) => r,
+            None => {
+                tracing::warn!("Job workers are already started");
+                return;
+            }
+        };
+
+        let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+        tokio::spawn(async move {
+            while let Some(job) = receiver.recv().await {
+                let services = services.clone();
+                let semaphore = semaphore.clone();
+
+                tokio::spawn(async move {
+                    let target_dir = RepoCloner::get_repository_target_dir(
+                        &job.config,
+                        &job.request.repository,
+                        &job.request.reference.to_string(),
+                    );
+
+                    {
+                        // Wait for the directory to be available before taking a worker slot
+                        let _directory_guard = services
+                            .jobs()
+                            .directory_lock(&target_dir)
+                            .lock_owned()
+                            .await;
+                        let _permit = semaphore
+                            .acquire_owned()
+                            .await
+                            .expect("job semaphore should never be closed");
+
+                        services.jobs().run(&services, job).await;
+                    }
+
+                    services.jobs().release_directory_lock(&target_dir);
+                });
+            }
+        });
+    }
+
+    /// Get the lock guarding a repository target directory.
+    pub(crate) fn directory_lock(&self, path: &Path) -> Arc<AsyncMutex<()>> {
+        self.inner
+            .directory_locks
+            .lock()
+            .unwrap()
+            .entry(path.to_owned())
+            .or_default()
+            .clone()
+    }
+
+    /// Forget the lock guarding a repository target directory, unless it is still in use.
+    fn release_directory_lock(&self, path: &Path) {
+        let mut locks = self.inner.directory_locks.lock().unwrap();
+        if locks.get(path).map_or(false, |l| Arc::strong_count(l) == 1) {
+            locks.remove(path);
+        }
+    }
+
+    #[tracing::instrument(skip(self, services), fields(job_id = %job.id))]
+    async fn run(&self, services: &ServiceHandler, job: QueuedJob) {
+        let started = {
+            let mut jobs = self.inner.jobs.lock().unwrap();
+            match jobs.get_mut(&job.id) {
+                Some(j) if j.status == JobStatus::Queued => {
+                    j.status = JobStatus::Running;
+                    j.started_at = Some(now_millis());
+                    true
+                }
+                _ => false,
+            }
+        };
+
+        if !started {
+            tracing::info!(message = "Skipping superseded job", job_id = %job.id);
+            if let Some(superseded) = self.get(&job.id) {
+                services.history().record_job(&superseded);
+            }
+            self.notify_update();
+            return;
+        }
+        self.notify_update();
+
+        let result = match job.request.kind {
+            JobKind::Synchronization => {
+                RepoCloner::create_or_update_using_config(
+                    &job.config,
+                    services,
+                    job.request.backend,
+                    &job.request.repository,
+                    job.request.reference,
+                    job.request.forced,
+                    job.request.commit.as_deref(),
+                )
+                .await
+            }
+            JobKind::Cleanup => {
+                RepoCloner::remove_using_config(
+                    &job.config,
+                    services,
+                    &job.request.repository,
+                    &job.request.reference,
+                )
+                .await
+            }
+            JobKind::Rollback => RepoCloner::rollback_using_config(
+                &job.config,
+                &job.request.repository,
+                &job.request.reference,
+                job.request.release.as_deref(),
+            )
+            .map_err(SyncFailure::from),
+        };
+
+        match &result {
+            Ok(_) => tracing::info!(message = "Job succeeded", job_id = %job.id),
+            Err(e) => tracing::error!(message = "Job failed", job_id = %job.id, error = %e),
+        }
+
+        let result = result.map_err(|f| (*f.report, f.error.to_string()));
+        if let Some(finished) = self.finish(&job.id, result) {
+            services.history().record_job(&finished);
+        }
+        self.notify_update();
+    }
+
+    /// Mark a job as finished, keeping the report of failed jobs to know what ran before the failure.
+    /// Waiters are not notified yet, so the caller can record the job beforehand.
+    fn finish(&self, id: &str, result: Result<SyncReport, (SyncReport, String)>) -> Option<Job> {
+        let mut jobs = self.inner.jobs.lock().unwrap();
+        let finished = jobs.get_mut(id).map(|j| {
+            let now = now_millis();
+            j.finished_at = Some(now);
+            j.duration_ms = j.started_at.map(|s| now.saturating_sub(s));
+            match result {
+                Ok(report) => {
+                    j.status = JobStatus::Succeeded;
+                    j.report = Some(report);
+                }
+                Err((report, e)) => {
+                    j.status = JobStatus::Failed;
+                    j.report = Some(report);
+                    j.error = Some(e);
+                }
+            }
+
+            j.clone()
+        });
+
+        prune_finished_jobs(&mut jobs, FINISHED_JOBS_RETENTION);
+        finished
+    }
+
+    fn notify_update(&self) {
+        self.inner.updates_sender.send(()).ok();
+    }
+}
+
+impl Default for JobQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Drop the oldest finished jobs, keeping at most `retention` of them.
+fn prune_finished_jobs(jobs: &mut HashMap<JobId, Job>, retention: usize) {
+    let mut finished: Vec<(u64, JobId)> = jobs
+        .values()
+        .filter(|j| j.status.is_finished())
+        .map(|j| (j.finished_at.unwrap_or(j.created_at), j.id.clone()))
+        .collect();
+    if finished.len() <= retention {
+        return;
+    }
+
+    finished.sort_unstable();
+    for (_, id) in &finished[..finished.len() - retention] {
+        jobs.remove(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        actions::Action,
+        git::{GitBackend, GitError, RefType, RepoCloner, RepositoryPath, SyncStrategy},
+        testing::{
+            create_failed_action_error, create_test_config, create_test_services,
+            extract_fake_action_service, extract_fake_git_service,
+        },
+    };
+
+    use super::{prune_finished_jobs, Job, JobRequest, JobStatus};
+
+    fn create_request() -> JobRequest {
+        create_branch_request("main")
+    }
+
+    fn create_branch_request(branch: &str) -> JobRequest {
+        JobRequest::synchronization(
+            GitBackend::GitHub,
+            RepositoryPath::new("Srynetix/things").unwrap(),
+            RefType::Branch(branch.into()),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_job_success() {
+        let config = create_test_config();
+        let services = create_test_services();
+
+        let id = services.jobs().enqueue(&config, create_request());
+        let job = services.jobs().wait_for(&id).await.unwrap();
+
+        assert_eq!(job.status, JobStatus::Succeeded);
+        assert_eq!(job.repository, "Srynetix/things");
+        assert_eq!(job.reference, "main");
+        assert_eq!(job.report.unwrap().git_output, vec!["OK".to_string()]);
+        assert!(job.started_at.is_some());
+        assert!(job.finished_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_job_failure() {
+        let config = create_test_config();
+        let services = create_test_services();
+        extract_fake_git_service(&services)
+            .clone_repository
+            .return_value(Err(GitError::GitExecutionError("boom".into())));
+
+        let id = services.jobs().enqueue(&config, create_request());
+        let job = services.jobs().wait_for(&id).await.unwrap();
+
+        assert_eq!(job.status, JobStatus::Failed);
+        assert!(job.error.is_some());
+        assert!(job.report.unwrap().git_output.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_job_failure_keeps_report() {
+        let mut config = create_test_config();
+        config
+            .repository_mut("Srynetix/things")
+            .unwrap()
+            .set_actions(vec![Action::new("npm run build")]);
+        let services = create_test_services();
+        extract_fake_action_service(&services)
+            .run
+            .return_value(Err(create_failed_action_error("npm run build")));
+
+        let id = services.jobs().enqueue(&config, create_request());
+        let job = services.jobs().wait_for(&id).await.unwrap();
+
+        assert_eq!(job.status, JobStatus::Failed);
+        let report = job.report.unwrap();
+        assert_eq!(report.git_output, vec!["OK".to_string()]);
+        assert_eq!(report.action_outputs[0].command, "npm run build");
+        assert_eq!(report.action_outputs[0].status_code, Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_jobs_list() {
+        let config = create_test_config();
+        let services = create_test_services();
+
+        let first = services.jobs().enqueue(&config, create_request());
+        let second = services
+            .jobs()
+            .enqueue(&config, create_branch_request("other"));
+        services.jobs().wait_until_idle().await;
+
+        let jobs = services.jobs().list();
+        assert_eq!(jobs.len(), 2);
+        assert!(jobs.iter().any(|j| j.id == first));
+        assert!(jobs.iter().any(|j| j.id == second));
+        assert!(jobs.iter().all(|j| j.status == JobStatus::Succeeded));
+    }
+
+    #[tokio::test]
+    async fn test_jobs_coalescing() {
+        let config = create_test_config();
+        let services = create_test_services();
+
+        // Simulate a running synchronization on the target directory
+        let lock = services
+            .jobs()
+            .directory_lock(&RepoCloner::get_repository_target_dir(
+                &config,
+                &create_request().repository,
+                "main",
+            ));
+        let guard = lock.lock().await;
+
+        let first = services.jobs().enqueue(&config, create_request());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(
+            services.jobs().get(&first).unwrap().status,
+            JobStatus::Queued
+        );
+
+        let second = services.jobs().enqueue(&config, create_request());
+        let third = services.jobs().enqueue(&config, create_request());
+        let other_branch = services
+            .jobs()
+            .enqueue(&config, create_branch_request("other"));
+
+        drop(guard);
+        services.jobs().wait_until_idle().await;
+
+        let first = services.jobs().get(&first).unwrap();
+        assert_eq!(first.status, JobStatus::Superseded);
+        assert_eq!(first.superseded_by.as_deref(), Some(second.as_str()));
+        let second = services.jobs().get(&second).unwrap();
+        assert_eq!(second.status, JobStatus::Superseded);
+        assert_eq!(second.superseded_by.as_deref(), Some(third.as_str()));
+        assert_eq!(
+            services.jobs().get(&third).unwrap().status,
+            JobStatus::Succeeded
+        );
+        assert_eq!(
+            services.jobs().get(&other_branch).unwrap().status,
+            JobStatus::Succeeded
+        );
+
+        let fake_git_service = extract_fake_git_service(&services);
+        assert_eq!(fake_git_service.clone_repository.num_calls(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_jobs_coalescing_keeps_forced() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tempdir.path().join("things")).unwrap();
+
+        let mut config = create_test_config();
+        config.set_working_dir(tempdir.path());
+        config
+            .repository_mut("Srynetix/things")
+            .unwrap()
+            .set_sync_strategy(SyncStrategy::Pull);
+        let services = create_test_services();
+
+        let lock = services
+            .jobs()
+            .directory_lock(&RepoCloner::get_repository_target_dir(
+                &config,
+                &create_request().repository,
+                "main",
+            ));
+        let guard = lock.lock().await;
+
+        let first = services.jobs().enqueue(&config, create_request());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // A force-push followed by a regular push, before the first one is handled
+        let second = services
+            .jobs()
+            .enqueue(&config, create_request().with_forced(true));
+        let third = services.jobs().enqueue(&config, create_request());
+        assert!(services.jobs().get(&third).unwrap().forced);
+
+        drop(guard);
+        services.jobs().wait_until_idle().await;
+
+        assert!(!services.jobs().get(&first).unwrap().forced);
+        assert_eq!(
+            services.jobs().get(&second).unwrap().status,
+            JobStatus::Superseded
+        );
+        let third = services.jobs().get(&third).unwrap();
+        assert_eq!(third.status, JobStatus::Succeeded);
+        assert!(third.forced);
+
+        // Force-pushed references are reset instead of pulled
+        let fake_git_service = extract_fake_git_service(&services);
+        assert!(!fake_git_service.pull.called());
+        assert!(fake_git_service.reset_hard.called());
+    }
+
+    #[tokio::test]
+    async fn test_jobs_coalescing_same_kind_only() {
+        let config = create_test_config();
+        let services = create_test_services();
+
+        let lock = services
+            .jobs()
+            .directory_lock(&RepoCloner::get_repository_target_dir(
+                &config,
+                &create_request().repository,
+                "main",
+            ));
+        let guard = lock.lock().await;
+
+        let synchronization = services.jobs().enqueue(&config, create_request());
+        let request = create_request();
+        let cleanup = services.jobs().enqueue(
+            &config,
+            JobRequest::cleanup(request.backend, request.repository, request.reference),
+        );
+
+        drop(guard);
+        services.jobs().wait_until_idle().await;
+
+        for id in [synchronization, cleanup] {
+            let job = services.jobs().get(&id).unwrap();
+            assert_ne!(job.status, JobStatus::Superseded);
+            assert_eq!(job.superseded_by, None);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_directory_locks_released() {
+        let config = create_test_config();
+        let services = create_test_services();
+
+        services.jobs().enqueue(&config, create_request());
+        services
+            .jobs()
+            .enqueue(&config, create_branch_request("other"));
+        services.jobs().wait_until_idle().await;
+
+        // Locks are released right after the jobs are marked as finished
+        for _ in 0..100 {
+            if services
+                .jobs()
+                .inner
+                .directory_locks
+                .lock()
+                .unwrap()
+                .is_empty()
+            {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("directory locks were not released");
+    }
+
+    #[test]
+    fn test_prune_finished_jobs() {
+        let mut jobs = HashMap::new();
+        for (created_at, status) in [
+            (1, JobStatus::Succeeded),
+            (2, JobStatus::Queued),
+            (3, JobStatus::Failed),
+            (4, JobStatus::Superseded),
+            (5, JobStatus::Running),
+        ] {
+            let mut job = Job::new(&create_request());
+            job.created_at = created_at;
+            job.status = status;
+            if status.is_finished() {
+                job.finished_at = Some(created_at);
+            }
+            jobs.insert(job.id.clone(), job);
+        }
+
+        prune_finished_jobs(&mut jobs, 1);
+
+        let mut remaining: Vec<_> = jobs.values().map(|j| j.created_at).collect();
+        remaining.sort_unstable();
+        assert_eq!(remaining, vec![2, 4, 5]);
+    }
+}
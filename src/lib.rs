@@ -1,10 +1,16 @@
+pub mod actions;
 pub mod backends;
 pub mod cmdargs;
 pub mod config;
 mod crypto;
 mod error;
 pub mod git;
+pub mod history;
 pub mod http;
+pub mod jobs;
 pub mod logging;
 mod server_info;
 pub mod service;
+
+#[cfg(test)]
+mod testing;
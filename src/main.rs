@@ -1,10 +1,14 @@
+use std::path::PathBuf;
+
 use clap::Parser;
+use url::Url;
 
-use hookrunner::backends::github;
+use hookrunner::backends::{gitea, github, gitlab};
 use hookrunner::cmdargs::{Args, ServeCommand, SubCommand};
 use hookrunner::config::{Config, ConfigError, ServerConfig};
-use hookrunner::git::RepoCloner;
-use hookrunner::http::start_server;
+use hookrunner::git::{GitBackend, GitError, RepoCloner};
+use hookrunner::history::{DeliveryCache, HistoryStore};
+use hookrunner::http::{start_server, IpAllowlist};
 use hookrunner::logging::TracingSetup;
 use hookrunner::service::ServiceHandler;
 
@@ -15,7 +19,10 @@ async fn main() -> color_eyre::Result<()> {
 
     let args = Args::parse();
     let config = build_configuration(&args)?;
-    let services = ServiceHandler::new_defaults()?;
+    let services = ServiceHandler::new_defaults(config.git_implementation())?
+        .with_history(HistoryStore::from_config(&config)?)
+        .with_delivery_cache(DeliveryCache::from_config(&config)?)
+        .with_ip_allowlist(IpAllowlist::from_config(&config));
 
     TracingSetup::with_setup(config, |config| async move {
         match args.command {
@@ -24,29 +31,107 @@ async fn main() -> color_eyre::Result<()> {
                 start_server(server_config, config, services).await?
             }
             SubCommand::Synchronize(sync_args) => {
+                let backend = sync_args
+                    .backend
+                    .unwrap_or_else(|| config.repo_backend(&sync_args.repository.full_name()));
+
                 RepoCloner::create_or_update_using_config(
                     &config,
                     &services,
-                    sync_args.backend,
+                    backend,
                     &sync_args.repository,
                     sync_args.reference,
+                    false,
+                    None,
                 )
                 .await?;
             }
+            SubCommand::Rollback(rollback_args) => {
+                let report = RepoCloner::rollback_using_config(
+                    &config,
+                    &rollback_args.repository,
+                    &rollback_args.reference,
+                    rollback_args.release.as_deref(),
+                )?;
+                tracing::info!(message = "Rolled back", release = ?report.release);
+            }
             SubCommand::Install(install_args) => {
-                let client = github::Client::new(install_args.token);
                 let repo = install_args.repository;
 
-                client
-                    .try_register_webhook(&config, repo.owner(), repo.name(), &install_args.url)
-                    .await?;
+                match install_args.backend {
+                    GitBackend::GitHub => {
+                        github::Client::new(install_args.token)
+                            .try_register_webhook(
+                                &config,
+                                repo.owner(),
+                                repo.name(),
+                                &install_args.url,
+                            )
+                            .await?;
+                    }
+                    GitBackend::GitLab => {
+                        gitlab::Client::new(install_args.token)
+                            .try_register_webhook(
+                                &config,
+                                repo.owner(),
+                                repo.name(),
+                                &install_args.url,
+                            )
+                            .await?;
+                    }
+                    GitBackend::Gitea(base_url) => {
+                        gitea::Client::new(Url::parse(&base_url)?, install_args.token)
+                            .try_register_webhook(
+                                &config,
+                                repo.owner(),
+                                repo.name(),
+                                &install_args.url,
+                            )
+                            .await?;
+                    }
+                    other => {
+                        return Err(GitError::UnsupportedGitBackend(format!("{:?}", other)).into())
+                    }
+                }
             }
             SubCommand::Uninstall(install_args) => {
-                let client = github::Client::new(install_args.token);
                 let repo = install_args.repository;
-                client
-                    .try_unregister_webhook(&config, repo.owner(), repo.owner(), &install_args.url)
-                    .await?;
+
+                match install_args.backend {
+                    GitBackend::GitHub => {
+                        github::Client::new(install_args.token)
+                            .try_unregister_webhook(
+                                &config,
+                                repo.owner(),
+                                repo.name(),
+                                &install_args.url,
+                            )
+                            .await?;
+                    }
+                    GitBackend::GitLab => {
+                        gitlab::Client::new(install_args.token)
+                            .try_unregister_webhook(
+                                &config,
+                                repo.owner(),
+                                repo.name(),
+                                &install_args.url,
+                            )
+                            .await?;
+                    }
+                    GitBackend::Gitea(base_url) => {
+                        gitea::Client::new(Url::parse(&base_url)?, install_args.token)
+                            .try_unregister_webhook(
+                                &config,
+                                repo.owner(),
+                                repo.name(),
+                                &install_args.url,
+                            )
+                            .await?;
+                    }
+                    other => {
+                        return Err(GitError::UnsupportedGitBackend(format!("{:?}", other)).into())
+                    }
+                }
             }
         }
 
@@ -55,15 +140,29 @@ async fn main() -> color_eyre::Result<()> {
     .await
 }
 
+/// Build configuration, using this precedence order (highest first):
+/// command-line arguments, environment variables, configuration file, default values.
 fn build_configuration(args: &Args) -> Result<Config, ConfigError> {
-    let mut config = Config::from_env();
+    let config_path = args
+        .config
+        .clone()
+        .or_else(|| std::env::var_os("HR_CONFIG").map(PathBuf::from));
+    let mut config = Config::load(config_path.as_deref())?;
 
     if let Some(m) = &args.github_api_url {
         config.set_github_api_url(m.clone());
     }
 
     if let Some(m) = &args.repo_mapping {
-        config.set_repo_mapping(m);
+        config.set_repo_mapping(m)?;
+    }
+
+    if let Some(a) = &args.repo_actions {
+        config.set_repo_actions(a)?;
+    }
+
+    if let Some(m) = &args.gitlab_api_url {
+        config.set_gitlab_api_url(m.clone());
     }
 
     if let Some(t) = &args.telemetry_url {
@@ -74,10 +173,22 @@ fn build_configuration(args: &Args) -> Result<Config, ConfigError> {
         config.set_working_dir(w);
     }
 
+    if let Some(t) = &args.trash_dir {
+        config.set_trash_dir(t);
+    }
+
+    if let Some(g) = args.git_implementation {
+        config.set_git_implementation(g);
+    }
+
     if let Some(s) = &args.webhook_secret {
         config.set_webhook_secret(s);
     }
 
+    if let Some(t) = &args.api_token {
+        config.set_api_token(t);
+    }
+
     config.validate_configuration().map(|_| config)
 }
 
@@ -88,5 +199,9 @@ fn build_server_configuration(args: &ServeCommand) -> Result<ServerConfig, Confi
         server_config.set_bind_ip(*m);
     }
 
+    if let Some(w) = args.workers {
+        server_config.set_workers(w);
+    }
+
     Ok(server_config)
 }
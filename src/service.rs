@@ -1,24 +1,106 @@
 use std::sync::Arc;
 
-use crate::git::{GitError, GitExecutable, GitService};
+use crate::{
+    actions::{ActionService, ShellExecutor},
+    git::{GitError, GitExecutable, GitImplementation, GitService},
+    history::{DeliveryCache, HistoryStore},
+    http::IpAllowlist,
+    jobs::JobQueue,
+};
+
+const DEFAULT_DELIVERY_CACHE_SIZE: usize = 1000;
 
 #[derive(Debug, Clone)]
 pub struct ServiceHandler {
     git_service: Arc<dyn GitService>,
+    action_service: Arc<dyn ActionService>,
+    job_queue: JobQueue,
+    history: HistoryStore,
+    delivery_cache: DeliveryCache,
+    ip_allowlist: IpAllowlist,
 }
 
 impl ServiceHandler {
-    pub fn new(git_service: Arc<dyn GitService>) -> Self {
-        Self { git_service }
+    pub fn new(git_service: Arc<dyn GitService>, action_service: Arc<dyn ActionService>) -> Self {
+        Self {
+            git_service,
+            action_service,
+            job_queue: JobQueue::new(),
+            history: HistoryStore::disabled(),
+            delivery_cache: DeliveryCache::new(DEFAULT_DELIVERY_CACHE_SIZE),
+            ip_allowlist: IpAllowlist::disabled(),
+        }
     }
 
-    pub fn new_defaults() -> Result<Self, GitError> {
+    pub fn new_defaults(implementation: GitImplementation) -> Result<Self, GitError> {
         Ok(Self {
-            git_service: Arc::new(GitExecutable::new()?),
+            git_service: Self::create_git_service(implementation)?,
+            action_service: Arc::new(ShellExecutor::new()),
+            job_queue: JobQueue::new(),
+            history: HistoryStore::disabled(),
+            delivery_cache: DeliveryCache::new(DEFAULT_DELIVERY_CACHE_SIZE),
+            ip_allowlist: IpAllowlist::disabled(),
         })
     }
 
+    fn create_git_service(
+        implementation: GitImplementation,
+    ) -> Result<Arc<dyn GitService>, GitError> {
+        match implementation {
+            GitImplementation::Executable => Ok(Arc::new(GitExecutable::new()?)),
+            #[cfg(feature = "native-git")]
+            GitImplementation::Native => Ok(Arc::new(crate::git::GitNative::new())),
+            #[cfg(not(feature = "native-git"))]
+            GitImplementation::Native => Err(GitError::UnsupportedGitImplementation(
+                "native (built without the 'native-git' feature)".into(),
+            )),
+        }
+    }
+
+    /// Use a history store to record deliveries and jobs.
+    pub fn with_history(mut self, history: HistoryStore) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Use a cache of seen delivery identifiers to reject replayed deliveries.
+    pub fn with_delivery_cache(mut self, delivery_cache: DeliveryCache) -> Self {
+        self.delivery_cache = delivery_cache;
+        self
+    }
+
+    /// Only accept webhook deliveries from allowed source IP addresses.
+    pub fn with_ip_allowlist(mut self, ip_allowlist: IpAllowlist) -> Self {
+        self.ip_allowlist = ip_allowlist;
+        self
+    }
+
     pub fn git(&self) -> &dyn GitService {
         self.git_service.as_ref()
     }
+
+    pub fn actions(&self) -> &dyn ActionService {
+        self.action_service.as_ref()
+    }
+
+    pub fn history(&self) -> &HistoryStore {
+        &self.history
+    }
+
+    pub fn delivery_cache(&self) -> &DeliveryCache {
+        &self.delivery_cache
+    }
+
+    pub fn ip_allowlist(&self) -> &IpAllowlist {
+        &self.ip_allowlist
+    }
+
+    pub fn jobs(&self) -> &JobQueue {
+        &self.job_queue
+    }
+
+    /// Start the job worker pool, running at most `concurrency` synchronizations at once.
+    pub fn start_workers(&self, concurrency: usize) {
+        self.job_queue.start_workers(self.clone(), concurrency);
+    }
 }
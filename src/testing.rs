@@ -0,0 +1,522 @@
+use std::{
+    any::Any,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    response::Response,
+    Router,
+};
+use pretty_assertions::assert_eq;
+use pseudo::Mock;
+use serde_json::json;
+use tower::ServiceExt;
+
+use crate::{
+    actions::{Action, ActionError, ActionOutput, ActionService},
+    config::Config,
+    error::ErrorCode,
+    git::{
+        CloneOptions, GitBackend, GitCredentials, GitError, GitService, RefType, RepoCloner,
+        RepositoryPath, SyncOptions, SyncStrategy,
+    },
+    history::{DeliveryCache, HistoryStore},
+    http::build_http_router,
+    jobs::{Job, JobStatus},
+    service::ServiceHandler,
+};
+
+/// Clone arguments: working directory, reference, URL, folder name, options and credentials.
+pub(crate) type CloneArgs = (
+    PathBuf,
+    Option<String>,
+    String,
+    String,
+    CloneOptions,
+    GitCredentials,
+);
+
+#[derive(Debug)]
+pub(crate) struct FakeGitService {
+    pub clone_repository: Mock<CloneArgs, Result<String, GitError>>,
+    pub pull: Mock<PathBuf, Result<String, GitError>>,
+    pub checkout: Mock<(PathBuf, String, GitCredentials), Result<String, GitError>>,
+    pub fetch: Mock<(PathBuf, CloneOptions, GitCredentials), Result<String, GitError>>,
+    pub sparse_checkout: Mock<(PathBuf, Vec<String>, GitCredentials), Result<String, GitError>>,
+    pub reset_hard: Mock<(PathBuf, String, GitCredentials), Result<String, GitError>>,
+    pub clean: Mock<PathBuf, Result<String, GitError>>,
+    pub update_submodules: Mock<PathBuf, Result<String, GitError>>,
+    pub lfs_pull: Mock<PathBuf, Result<String, GitError>>,
+    pub head_commit: Mock<PathBuf, Result<String, GitError>>,
+}
+
+impl FakeGitService {
+    pub fn new() -> Self {
+        Self {
+            clone_repository: Mock::new(Ok("OK".into())),
+            pull: Mock::new(Ok("OK".into())),
+            checkout: Mock::new(Ok("OK".into())),
+            fetch: Mock::new(Ok("OK".into())),
+            sparse_checkout: Mock::new(Ok("OK".into())),
+            reset_hard: Mock::new(Ok("OK".into())),
+            clean: Mock::new(Ok("OK".into())),
+            update_submodules: Mock::new(Ok("OK".into())),
+            lfs_pull: Mock::new(Ok("OK".into())),
+            head_commit: Mock::new(Ok("0d1a26e67d8f5eaf1f6ba5c57fc3c7d91ac0fd1c".into())),
+        }
+    }
+}
+
+#[async_trait]
+impl GitService for FakeGitService {
+    async fn clone_repository(
+        &self,
+        working_dir: &Path,
+        reference: Option<&str>,
+        repo_path: &str,
+        folder_path: &str,
+        options: &CloneOptions,
+        credentials: &GitCredentials,
+    ) -> Result<String, GitError> {
+        self.clone_repository.call((
+            working_dir.to_owned(),
+            reference.map(ToOwned::to_owned),
+            repo_path.to_owned(),
+            folder_path.to_owned(),
+            options.clone(),
+            credentials.clone(),
+        ))
+    }
+
+    async fn pull(
+        &self,
+        working_dir: &Path,
+        _credentials: &GitCredentials,
+    ) -> Result<String, GitError> {
+        self.pull.call(working_dir.to_owned())
+    }
+
+    async fn checkout(
+        &self,
+        working_dir: &Path,
+        reference: &str,
+        credentials: &GitCredentials,
+    ) -> Result<String, GitError> {
+        self.checkout.call((
+            working_dir.to_owned(),
+            reference.to_owned(),
+            credentials.clone(),
+        ))
+    }
+
+    async fn fetch(
+        &self,
+        working_dir: &Path,
+        options: &CloneOptions,
+        credentials: &GitCredentials,
+    ) -> Result<String, GitError> {
+        self.fetch
+            .call((working_dir.to_owned(), options.clone(), credentials.clone()))
+    }
+
+    async fn sparse_checkout(
+        &self,
+        working_dir: &Path,
+        paths: &[String],
+        credentials: &GitCredentials,
+    ) -> Result<String, GitError> {
+        self.sparse_checkout
+            .call((working_dir.to_owned(), paths.to_vec(), credentials.clone()))
+    }
+
+    async fn reset_hard(
+        &self,
+        working_dir: &Path,
+        revision: &str,
+        credentials: &GitCredentials,
+    ) -> Result<String, GitError> {
+        self.reset_hard.call((
+            working_dir.to_owned(),
+            revision.to_owned(),
+            credentials.clone(),
+        ))
+    }
+
+    async fn clean(&self, working_dir: &Path) -> Result<String, GitError> {
+        self.clean.call(working_dir.to_owned())
+    }
+
+    async fn update_submodules(
+        &self,
+        working_dir: &Path,
+        _credentials: &GitCredentials,
+    ) -> Result<String, GitError> {
+        self.update_submodules.call(working_dir.to_owned())
+    }
+
+    async fn lfs_pull(
+        &self,
+        working_dir: &Path,
+        _credentials: &GitCredentials,
+    ) -> Result<String, GitError> {
+        self.lfs_pull.call(working_dir.to_owned())
+    }
+
+    async fn head_commit(&self, working_dir: &Path) -> Result<String, GitError> {
+        self.head_commit.call(working_dir.to_owned())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct FakeActionService {
+    pub run: Mock<(PathBuf, Action), Result<ActionOutput, ActionError>>,
+}
+
+impl FakeActionService {
+    pub fn new() -> Self {
+        Self {
+            run: Mock::new(Ok(ActionOutput {
+                command: "command".into(),
+                status_code: Some(0),
+                stdout: "OK".into(),
+                stderr: String::new(),
+                duration_ms: 0,
+            })),
+        }
+    }
+}
+
+#[async_trait]
+impl ActionService for FakeActionService {
+    async fn run(
+        &self,
+        repository_dir: &Path,
+        action: &Action,
+    ) -> Result<ActionOutput, ActionError> {
+        self.run.call((repository_dir.to_owned(), action.clone()))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Create the error of an action which failed with status code 1.
+pub(crate) fn create_failed_action_error(command: &str) -> ActionError {
+    ActionError::CommandFailed(ActionOutput {
+        command: command.into(),
+        status_code: Some(1),
+        stdout: String::new(),
+        stderr: String::new(),
+        duration_ms: 0,
+    })
+}
+
+pub(crate) fn create_test_config() -> Config {
+    Config::empty()
+}
+
+pub(crate) fn create_test_services() -> ServiceHandler {
+    create_test_services_with_history(HistoryStore::disabled())
+}
+
+pub(crate) fn create_test_services_with_history(history: HistoryStore) -> ServiceHandler {
+    let services = ServiceHandler::new(
+        Arc::new(FakeGitService::new()),
+        Arc::new(FakeActionService::new()),
+    )
+    .with_history(history)
+    // Replay checks need delivery headers, which most tests do not send
+    .with_delivery_cache(DeliveryCache::disabled());
+    services.start_workers(1);
+    services
+}
+
+pub(crate) fn extract_fake_git_service(services: &ServiceHandler) -> &FakeGitService {
+    services
+        .git()
+        .as_any()
+        .downcast_ref::<FakeGitService>()
+        .unwrap()
+}
+
+pub(crate) fn extract_fake_action_service(services: &ServiceHandler) -> &FakeActionService {
+    services
+        .actions()
+        .as_any()
+        .downcast_ref::<FakeActionService>()
+        .unwrap()
+}
+
+pub(crate) async fn response_to_string<T: hyper::body::HttpBody>(response: Response<T>) -> String
+where
+    T::Error: std::fmt::Debug,
+{
+    let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+pub(crate) async fn response_to_json<T: hyper::body::HttpBody>(
+    response: Response<T>,
+) -> serde_json::Value
+where
+    T::Error: std::fmt::Debug,
+{
+    let string = response_to_string(response).await;
+    serde_json::from_str(&string).unwrap()
+}
+
+pub(crate) fn error_to_json(err: ErrorCode) -> serde_json::Value {
+    serde_json::to_value(err.details()).unwrap()
+}
+
+pub(crate) async fn assert_response_is_error<T: hyper::body::HttpBody>(
+    response: Response<T>,
+    error: ErrorCode,
+) where
+    T::Error: std::fmt::Debug,
+{
+    let data = response_to_json(response).await;
+    assert_eq!(data, error_to_json(error));
+}
+
+/// Extract the job identifier from a `202 Accepted` webhook response, then wait for the job to finish.
+pub(crate) async fn wait_for_response_job(services: &ServiceHandler, response: Response) -> Job {
+    let data = response_to_json(response).await;
+    let job_id = data["job_id"]
+        .as_str()
+        .expect("response should contain a job identifier");
+    services
+        .jobs()
+        .wait_for(job_id)
+        .await
+        .expect("job should exist")
+}
+
+/// Local Git repository, used as a remote by real Git implementations.
+pub(crate) struct TestRemote {
+    pub root: tempfile::TempDir,
+}
+
+impl TestRemote {
+    /// Create `<root>/Srynetix/things`, with a first commit on `main` tagged `v1`.
+    pub fn new() -> Self {
+        let remote = Self {
+            root: tempfile::tempdir().unwrap(),
+        };
+        std::fs::create_dir_all(remote.path()).unwrap();
+        remote.git(&["init", "--initial-branch=main"]);
+        remote.commit("first");
+        remote.git(&["tag", "v1"]);
+        remote
+    }
+
+    pub fn backend(&self) -> GitBackend {
+        GitBackend::Custom(format!("file://{}", self.root.path().display()))
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.root.path().join("Srynetix").join("things")
+    }
+
+    /// Commit a change to `file.txt`, returning the new commit hash.
+    pub fn commit(&self, content: &str) -> String {
+        std::fs::write(self.path().join("file.txt"), content).unwrap();
+        self.git(&["add", "file.txt"]);
+        self.git(&["commit", "-m", content]);
+        self.git(&["rev-parse", "HEAD"])
+    }
+
+    fn git(&self, args: &[&str]) -> String {
+        let output = std::process::Command::new("git")
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(self.path())
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    }
+}
+
+/// Synchronize a checkout through every strategy with a real Git implementation.
+pub(crate) async fn check_git_service(git_service: Arc<dyn GitService>) {
+    let remote = TestRemote::new();
+    let services = ServiceHandler::new(git_service, Arc::new(FakeActionService::new()));
+    let working_dir = tempfile::tempdir().unwrap();
+    let checkout = working_dir.path().join("things");
+    let repo_path = RepositoryPath::new("Srynetix/things").unwrap();
+    let main = RefType::Branch("main".into());
+
+    let sync = |reference: RefType, strategy: SyncStrategy| {
+        let options = SyncOptions {
+            strategy,
+            ..SyncOptions::default()
+        };
+        let services = &services;
+        let backend = remote.backend();
+        let repo_path = &repo_path;
+        let checkout = &checkout;
+        async move {
+            RepoCloner::create_or_update_in_directory(
+                services,
+                backend,
+                repo_path,
+                reference,
+                &options,
+                checkout,
+                &mut vec![],
+            )
+            .await
+            .unwrap();
+            let content = std::fs::read_to_string(checkout.join("file.txt")).unwrap();
+            let head = services.git().head_commit(checkout).await.unwrap();
+            (content, head)
+        }
+    };
+
+    // Clone
+    let (content, _) = sync(main.clone(), SyncStrategy::Reset).await;
+    assert_eq!(content, "first");
+
+    // Fetch and reset, even with local modifications
+    let second = remote.commit("second");
+    std::fs::write(checkout.join("file.txt"), "local").unwrap();
+    assert_eq!(
+        sync(main.clone(), SyncStrategy::Reset).await,
+        ("second".to_string(), second)
+    );
+
+    // Fetch and fast-forward
+    let third = remote.commit("third");
+    assert_eq!(
+        sync(main.clone(), SyncStrategy::Pull).await,
+        ("third".to_string(), third)
+    );
+
+    // Reset and clean
+    std::fs::write(checkout.join("untracked.txt"), "untracked").unwrap();
+    sync(main, SyncStrategy::ResetClean).await;
+    assert!(!checkout.join("untracked.txt").exists());
+
+    // Tags
+    let (content, _) = sync(RefType::Tag("v1".into()), SyncStrategy::Reset).await;
+    assert_eq!(content, "first");
+}
+
+/// Synchronize a checkout through the webhook handlers of several backends,
+/// with a real Git implementation.
+pub(crate) async fn check_webhook_synchronization(git_service: Arc<dyn GitService>) {
+    let remote = TestRemote::new();
+    let working_dir = tempfile::tempdir().unwrap();
+    let checkout = working_dir.path().join("things");
+    let mut config = create_test_config();
+    config.set_working_dir(working_dir.path());
+    config
+        .repository_mut("Srynetix/things")
+        .unwrap()
+        .set_backend(remote.backend());
+
+    let services = ServiceHandler::new(git_service, Arc::new(FakeActionService::new()));
+    services.start_workers(1);
+    let app = build_http_router(config, services.clone());
+
+    // GitHub push: clone
+    let first = services.git().head_commit(&remote.path()).await.unwrap();
+    let body = json!({
+        "ref": "refs/heads/main",
+        "after": first,
+        "repository": {"full_name": "Srynetix/things", "name": "things"},
+        "pusher": {"name": "sample", "email": "sample"}
+    });
+    let request = Request::builder()
+        .method("POST")
+        .header("User-Agent", "GitHub-Hookshot/value")
+        .header("X-GitHub-Event", "push")
+        .header("X-GitHub-Delivery", "72d3162e-cc78-11e3-81ab-4c9367dc0958")
+        .uri("/webhook/github")
+        .body(body.to_string().into())
+        .unwrap();
+    deliver(&app, &services, request).await;
+    assert_eq!(
+        std::fs::read_to_string(checkout.join("file.txt")).unwrap(),
+        "first"
+    );
+
+    // GitLab push: fetch and reset to the pushed commit, not to the branch tip
+    let second = remote.commit("second");
+    remote.commit("third");
+    let body = json!({
+        "object_kind": "push",
+        "ref": "refs/heads/main",
+        "before": first,
+        "after": second,
+        "checkout_sha": second,
+        "user_name": "sample",
+        "project": {
+            "id": 15,
+            "name": "things",
+            "path_with_namespace": "Srynetix/things",
+            "web_url": "https://gitlab.example.com/Srynetix/things"
+        }
+    });
+    let request = Request::builder()
+        .method("POST")
+        .header("User-Agent", "GitLab/15.0.0")
+        .header("X-Gitlab-Event", "Push Hook")
+        .header(
+            "X-Gitlab-Event-UUID",
+            "13792a0e-1b0e-4b6a-a2a6-ecdb2e2b1f0e",
+        )
+        .uri("/webhook/gitlab")
+        .body(body.to_string().into())
+        .unwrap();
+    deliver(&app, &services, request).await;
+    assert_eq!(
+        std::fs::read_to_string(checkout.join("file.txt")).unwrap(),
+        "second"
+    );
+    assert_eq!(services.git().head_commit(&checkout).await.unwrap(), second);
+
+    // Gitea tag push
+    let body = json!({
+        "ref": "refs/tags/v1",
+        "before": second,
+        "after": first,
+        "repository": {
+            "id": 140,
+            "name": "things",
+            "full_name": "Srynetix/things",
+            "html_url": "https://gitea.example.com/Srynetix/things"
+        },
+        "pusher": {"login": "sample", "email": "sample@example.com"}
+    });
+    let request = Request::builder()
+        .method("POST")
+        .header("X-Gitea-Event", "push")
+        .header("X-Gitea-Delivery", "f6266f16-1bf3-46a5-9ea4-602e06ead473")
+        .uri("/webhook/gitea")
+        .body(body.to_string().into())
+        .unwrap();
+    deliver(&app, &services, request).await;
+    assert_eq!(
+        std::fs::read_to_string(checkout.join("file.txt")).unwrap(),
+        "first"
+    );
+}
+
+/// Send a webhook delivery, then wait for its job to succeed.
+async fn deliver(app: &Router, services: &ServiceHandler, request: Request<Body>) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let job = wait_for_response_job(services, response).await;
+    assert_eq!(job.status, JobStatus::Succeeded, "{:?}", job);
+}